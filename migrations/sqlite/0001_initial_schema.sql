@@ -0,0 +1,184 @@
+-- Schema of the SQLite storage. Ids are the hex of the MongoDB ObjectIds so copied data keeps
+-- its ids, dates are milliseconds since the epoch as in BSON, enums are stored by their name and
+-- lists are JSON text. There are no foreign keys: deletes cascade in the storage code as they do
+-- on MongoDB, and data copied from MongoDB can point at documents that are already gone.
+-- Text is compared byte by byte, as MongoDB sorts strings.
+
+CREATE TABLE users (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    email TEXT NOT NULL UNIQUE,
+    username TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL
+);
+
+CREATE TABLE records (
+    id TEXT PRIMARY KEY,
+    record_type TEXT NOT NULL,
+    user_id TEXT,
+    key TEXT,
+    secret TEXT,
+    service TEXT,
+    password TEXT,
+    email TEXT,
+    username TEXT,
+    two_factor BIGINT,
+    revision BIGINT,
+    created_at BIGINT,
+    updated_at BIGINT,
+    password_changed_at BIGINT,
+    breach_password_count BIGINT,
+    breach_email_count BIGINT,
+    breach_checked_at BIGINT,
+    rotation_period BIGINT,
+    rotation_reminded_at BIGINT,
+    uris TEXT,
+    tags TEXT,
+    org_id TEXT,
+    collection_id TEXT
+);
+
+CREATE INDEX records_user_id ON records (user_id, org_id);
+CREATE INDEX records_org_id ON records (org_id, collection_id);
+
+CREATE TABLE record_shares (
+    id TEXT PRIMARY KEY,
+    record_id TEXT NOT NULL,
+    owner_id TEXT NOT NULL,
+    owner_username TEXT NOT NULL,
+    grantee_id TEXT NOT NULL,
+    grantee_username TEXT NOT NULL,
+    permission TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    UNIQUE (record_id, grantee_id)
+);
+
+CREATE INDEX record_shares_owner_id ON record_shares (owner_id);
+CREATE INDEX record_shares_grantee_id ON record_shares (grantee_id);
+
+CREATE TABLE organizations (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE org_members (
+    id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    org_name TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    role TEXT NOT NULL,
+    joined_at BIGINT NOT NULL,
+    UNIQUE (org_id, user_id)
+);
+
+CREATE INDEX org_members_user_id ON org_members (user_id);
+
+CREATE TABLE org_invitations (
+    id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    org_name TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    role TEXT NOT NULL,
+    invited_by TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX org_invitations_org_id ON org_invitations (org_id);
+CREATE INDEX org_invitations_user_id ON org_invitations (user_id);
+
+CREATE TABLE org_collections (
+    id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX org_collections_org_id ON org_collections (org_id);
+
+CREATE TABLE emergency_access (
+    id TEXT PRIMARY KEY,
+    grantor_id TEXT NOT NULL,
+    grantor_username TEXT NOT NULL,
+    grantee_id TEXT NOT NULL,
+    grantee_username TEXT NOT NULL,
+    access TEXT NOT NULL,
+    wait_days BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    requested_at BIGINT,
+    approved_at BIGINT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX emergency_access_grantor_id ON emergency_access (grantor_id);
+CREATE INDEX emergency_access_grantee_id ON emergency_access (grantee_id);
+CREATE INDEX emergency_access_status ON emergency_access (status);
+
+-- Attachments are split in chunks as in GridFS, a file row is only written once its chunks are
+CREATE TABLE attachments (
+    id TEXT PRIMARY KEY,
+    record_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    uploaded_by TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    key TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    length BIGINT NOT NULL,
+    chunk_size BIGINT NOT NULL,
+    upload_date BIGINT NOT NULL
+);
+
+CREATE INDEX attachments_record_id ON attachments (record_id);
+CREATE INDEX attachments_user_id ON attachments (user_id);
+
+CREATE TABLE attachment_chunks (
+    id TEXT PRIMARY KEY,
+    files_id TEXT NOT NULL,
+    n BIGINT NOT NULL,
+    data BLOB NOT NULL,
+    UNIQUE (files_id, n)
+);
+
+CREATE TABLE sends (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    text TEXT,
+    file_name TEXT,
+    file_content TEXT,
+    password TEXT,
+    max_views BIGINT NOT NULL,
+    views_left BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX sends_expires_at ON sends (expires_at);
+
+CREATE TABLE audit_log (
+    id TEXT PRIMARY KEY,
+    action TEXT NOT NULL,
+    owner_id TEXT NOT NULL,
+    actor_id TEXT,
+    target_id TEXT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX audit_log_target_id ON audit_log (target_id, created_at);
+
+CREATE TABLE saved_searches (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    query TEXT,
+    mode TEXT,
+    types TEXT NOT NULL,
+    sort TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX saved_searches_user_id ON saved_searches (user_id, name);
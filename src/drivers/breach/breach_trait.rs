@@ -0,0 +1,17 @@
+use crate::shared::types::ApiErrors;
+
+#[cfg(test)]
+use mockall::automock;
+
+/**
+ * A corpus of breached credentials. Only SHA-1 hashes, as uppercase hex, are handed to a source
+ * so an implementation backed by a remote service can use k-anonymity range queries
+ */
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait BreachSource: Send + Sync {
+    /// Number of times the password hash shows up in breaches, 0 when it was never seen
+    async fn password_breaches(&self, sha1: &str) -> Result<u64, ApiErrors>;
+    /// Number of breaches the hash of the lowercased email shows up in, 0 when it was never seen
+    async fn email_breaches(&self, sha1: &str) -> Result<u64, ApiErrors>;
+}
@@ -0,0 +1,85 @@
+pub mod breach_trait;
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use rocket::tokio::fs;
+use sha1::{Digest, Sha1};
+
+use crate::{drivers::breach::breach_trait::BreachSource, shared::types::ApiErrors};
+
+/// Length of the hash prefix used to name a range file
+const PREFIX_LENGTH: usize = 5;
+
+/**
+ * Uppercase hex SHA-1 of a value, the format used by HIBP
+ */
+pub fn sha1_hex(value: &str) -> String {
+    Sha1::digest(value.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+/**
+ * Reads HIBP style range files from disk, as produced by the HIBP downloader.
+ * Each file is named after the first 5 characters of the hash, ie `21BD1.txt`,
+ * and holds one `SUFFIX:COUNT` line per breached hash
+ */
+#[derive(Clone)]
+pub struct LocalBreachSource {
+    passwords_dir: PathBuf,
+    emails_dir: PathBuf,
+}
+
+impl LocalBreachSource {
+    pub fn new(passwords_dir: &str, emails_dir: &str) -> LocalBreachSource {
+        for dir in [passwords_dir, emails_dir] {
+            if !PathBuf::from(dir).is_dir() {
+                println!("Breach range directory {} does not exist, nothing will be flagged from it", dir);
+            }
+        }
+        LocalBreachSource {
+            passwords_dir: PathBuf::from(passwords_dir),
+            emails_dir: PathBuf::from(emails_dir),
+        }
+    }
+
+    async fn lookup(dir: &Path, sha1: &str) -> Result<u64, ApiErrors> {
+        let sha1 = sha1.to_uppercase();
+        if sha1.len() != 40 || !sha1.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ApiErrors::ServerError(format!("{} is not a SHA-1 hash", sha1)));
+        }
+        let (prefix, suffix) = sha1.split_at(PREFIX_LENGTH);
+
+        // Range files are published both with and without an extension
+        for name in [format!("{}.txt", prefix), prefix.to_string()] {
+            let contents = match fs::read_to_string(dir.join(name)).await {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(ApiErrors::ServerError(err.to_string())),
+            };
+            let count = contents
+                .lines()
+                .filter_map(|line| line.trim().split_once(':'))
+                .find(|(line_suffix, _)| line_suffix.eq_ignore_ascii_case(suffix))
+                .map(|(_, count)| count.trim().parse().unwrap_or(1))
+                .unwrap_or(0);
+            return Ok(count);
+        }
+        Ok(0)
+    }
+}
+
+#[async_trait]
+impl BreachSource for LocalBreachSource {
+    async fn password_breaches(&self, sha1: &str) -> Result<u64, ApiErrors> {
+        LocalBreachSource::lookup(&self.passwords_dir, sha1).await
+    }
+
+    async fn email_breaches(&self, sha1: &str) -> Result<u64, ApiErrors> {
+        LocalBreachSource::lookup(&self.emails_dir, sha1).await
+    }
+}
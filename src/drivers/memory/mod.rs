@@ -0,0 +1,1030 @@
+/*
+    Storage kept in memory, for local development and as a fake in tests. It follows the
+    semantics of the MongoDB backend: the same filters, sort orders, not found errors and
+    revision checks. Everything is lost when the process stops
+*/
+use std::{
+    cmp::{Ordering, Reverse},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use bson::{oid::ObjectId, Bson, DateTime};
+use rocket::futures::stream;
+
+use crate::{
+    drivers::storage::storage_trait::{AttachmentChunks, RecordStream, Storage},
+    modules::search_module::{relevance::relevance, SearchParams},
+    shared::{
+        pagination::{PageCursor, PageRequest, SortDirection},
+        types::{
+            ApiErrors, Attachment, AttachmentChunk, AuditEvent, AuthUser, BreachStatus, EmergencyAccess,
+            EmergencyStatus, OrgCollection, OrgInvitation, OrgMember, OrgRole, Organization, Record, RecordShare,
+            RecordWrite, RecordWriteResult, SavedSearch, SecretSend, UpdateRecord, UpdateUser, User,
+        },
+    },
+};
+
+#[derive(Default)]
+struct Collections {
+    users: Vec<User>,
+    records: Vec<Record>,
+    record_shares: Vec<RecordShare>,
+    organizations: Vec<Organization>,
+    org_members: Vec<OrgMember>,
+    org_invitations: Vec<OrgInvitation>,
+    org_collections: Vec<OrgCollection>,
+    emergency_access: Vec<EmergencyAccess>,
+    attachments: Vec<Attachment>,
+    attachment_chunks: Vec<AttachmentChunk>,
+    sends: Vec<SecretSend>,
+    audit_log: Vec<AuditEvent>,
+    saved_searches: Vec<SavedSearch>,
+}
+
+impl Collections {
+    /**
+     * Remove a record with its shares and attachments
+     */
+    fn remove_record(&mut self, index: usize) -> Record {
+        let record = self.records.remove(index);
+        self.record_shares.retain(|share| Some(share.record_id) != record.id);
+        self.remove_attachments_of_record(record.id);
+        record
+    }
+
+    fn remove_attachments_of_record(&mut self, record_id: Option<ObjectId>) {
+        let attachment_ids: Vec<ObjectId> = self
+            .attachments
+            .iter()
+            .filter(|attachment| Some(attachment.metadata.record_id) == record_id)
+            .map(|attachment| attachment.id)
+            .collect();
+        self.attachments.retain(|attachment| !attachment_ids.contains(&attachment.id));
+        self.attachment_chunks.retain(|chunk| !attachment_ids.contains(&chunk.files_id));
+    }
+}
+
+/**
+ * Every clone shares the same data
+ */
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    data: Arc<Mutex<Collections>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    fn data(&self) -> Result<MutexGuard<'_, Collections>, ApiErrors> {
+        self.data
+            .lock()
+            .map_err(|_| ApiErrors::ServerError("Storage is not available".to_string()))
+    }
+
+    /**
+     * A record owned by the user, optionally pinned to a revision.
+     * Records created before revisions existed have no field and count as revision 0
+     */
+    fn record_matches(record: &Record, record_id: ObjectId, user_id: ObjectId, expected_revision: Option<i64>) -> bool {
+        record.id == Some(record_id)
+            && record.user_id == Some(user_id)
+            && expected_revision.is_none_or(|revision| record.revision.unwrap_or(0) == revision)
+    }
+
+    /**
+     * Work out why a conditional write matched nothing
+     */
+    fn missed_write_error(records: &[Record], record_id: ObjectId, user_id: ObjectId, expected_revision: Option<i64>) -> ApiErrors {
+        let exists = records
+            .iter()
+            .any(|record| Self::record_matches(record, record_id, user_id, None));
+        match (expected_revision, exists) {
+            (Some(_), true) => ApiErrors::PreconditionFailed("Record has been modified".to_string()),
+            _ => ApiErrors::NotFound("Record not found".to_string()),
+        }
+    }
+
+    /**
+     * Apply a record patch, null fields are unset
+     */
+    fn apply_update(record: &mut Record, updated_record: UpdateRecord) {
+        let now = DateTime::now();
+        if matches!(updated_record.password, Some(Some(_))) {
+            record.password_changed_at = Some(now);
+        }
+        // A breach check only holds for the credentials that were checked
+        if updated_record.password.is_some() || updated_record.email.is_some() {
+            record.breach = None;
+        }
+        fn patch<T>(field: &mut Option<T>, value: Option<Option<T>>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        patch(&mut record.email, updated_record.email);
+        patch(&mut record.password, updated_record.password);
+        patch(&mut record.username, updated_record.username);
+        patch(&mut record.service, updated_record.service);
+        patch(&mut record.key, updated_record.key);
+        patch(&mut record.secret, updated_record.secret);
+        patch(&mut record.two_factor, updated_record.two_factor);
+        patch(&mut record.rotation_period, updated_record.rotation_period);
+        patch(&mut record.uris, updated_record.uris);
+        patch(&mut record.tags, updated_record.tags);
+        record.updated_at = Some(now);
+        // Every write bumps the revision so concurrent writers can detect each other
+        record.revision = Some(record.revision.unwrap_or(0) + 1);
+    }
+
+    fn update_record_in(
+        records: &mut [Record],
+        updated_record: UpdateRecord,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<i64, ApiErrors> {
+        match records
+            .iter_mut()
+            .find(|record| Self::record_matches(record, record_id, user_id, expected_revision))
+        {
+            Some(record) => {
+                Self::apply_update(record, updated_record);
+                Ok(record.revision.unwrap_or(0))
+            }
+            None => Err(Self::missed_write_error(records, record_id, user_id, expected_revision)),
+        }
+    }
+
+    fn to_auth_user(user: &User) -> Result<AuthUser, ApiErrors> {
+        Ok(AuthUser {
+            id: user.id.ok_or(ApiErrors::ServerError("Object id was not found".to_string()))?.to_string(),
+            name: user.name.clone(),
+            email: user.email.clone(),
+            username: user.username.clone(),
+        })
+    }
+
+    /**
+     * Records a search reaches, the ones of the user, shared with them and of their organizations
+     */
+    fn search_matches(params: &SearchParams, record: &Record) -> bool {
+        let reachable = (record.user_id == Some(params.user_id) && record.org_id.is_none())
+            || record.id.is_some_and(|id| params.shared_record_ids.contains(&id))
+            || record.org_id.is_some_and(|org_id| params.org_ids.contains(&org_id));
+        let record_types: Vec<_> = [&params.password_record, &params.secret_record].into_iter().flatten().collect();
+        let of_type = record_types.is_empty() || record_types.contains(&&record.record_type);
+        let matches_query = params.query.as_ref().is_none_or(|query| relevance(query, record).is_some());
+        reachable && of_type && matches_query
+    }
+
+    /**
+     * Up to one record more than the limit of a page, in the order MongoDB sorts them
+     */
+    fn page_of(mut records: Vec<Record>, page: &PageRequest) -> Vec<Record> {
+        let order = |a: &Record, b: &Record| match page.sort {
+            Some(sort) => {
+                let order = compare_values(&sort.field.value(a), &sort.field.value(b)).then(a.id.cmp(&b.id));
+                match sort.direction {
+                    SortDirection::Asc => order,
+                    SortDirection::Desc => order.reverse(),
+                }
+            }
+            None => a.id.cmp(&b.id),
+        };
+        records.sort_by(order);
+        if let Some(PageCursor::After { value, id, .. }) = &page.cursor {
+            let after = |record: &Record| {
+                let order = match page.sort {
+                    Some(sort) => compare_values(&sort.field.value(record), value).then(record.id.cmp(&Some(*id))),
+                    None => record.id.cmp(&Some(*id)),
+                };
+                match page.sort.map(|sort| sort.direction) {
+                    Some(SortDirection::Desc) => order == Ordering::Less,
+                    _ => order == Ordering::Greater,
+                }
+            };
+            records.retain(after);
+        }
+        records.truncate(page.limit as usize + 1);
+        records
+    }
+}
+
+/**
+ * Order of sort values, null first as in MongoDB
+ */
+fn compare_values(a: &Bson, b: &Bson) -> Ordering {
+    match (a, b) {
+        (Bson::Null, Bson::Null) => Ordering::Equal,
+        (Bson::Null, _) => Ordering::Less,
+        (_, Bson::Null) => Ordering::Greater,
+        (Bson::String(a), Bson::String(b)) => a.cmp(b),
+        (Bson::DateTime(a), Bson::DateTime(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
+fn not_found(message: &str) -> ApiErrors {
+    ApiErrors::NotFound(message.to_string())
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn email_exists(&self, email: &str) -> Result<bool, ApiErrors> {
+        Ok(self.data()?.users.iter().any(|user| user.email == email))
+    }
+
+    async fn username_exists(&self, username: &str) -> Result<bool, ApiErrors> {
+        Ok(self.data()?.users.iter().any(|user| user.username == username))
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<ObjectId, ApiErrors> {
+        let id = user.id.unwrap_or_default();
+        self.data()?.users.push(User { id: Some(id), ..user.clone() });
+        Ok(id)
+    }
+
+    async fn get_user(&self, email: &str) -> Result<User, ApiErrors> {
+        self.data()?
+            .users
+            .iter()
+            .find(|user| user.email == email)
+            .cloned()
+            .ok_or_else(|| ApiErrors::BadRequest("User not found".to_string()))
+    }
+
+    async fn insert_record(&self, record: Record) -> Result<ObjectId, ApiErrors> {
+        let id = record.id.unwrap_or_default();
+        self.data()?.records.push(Record { id: Some(id), ..record });
+        Ok(id)
+    }
+
+    async fn get_record(&self, record_id: ObjectId, user_id: ObjectId) -> Result<Record, ApiErrors> {
+        self.data()?
+            .records
+            .iter()
+            .find(|record| Self::record_matches(record, record_id, user_id, None))
+            .cloned()
+            .ok_or_else(|| not_found("Record not found"))
+    }
+
+    async fn get_record_by_id(&self, record_id: ObjectId) -> Result<Record, ApiErrors> {
+        self.data()?
+            .records
+            .iter()
+            .find(|record| record.id == Some(record_id))
+            .cloned()
+            .ok_or_else(|| not_found("Record not found"))
+    }
+
+    async fn get_all_user_records(&self, user_id: ObjectId) -> Result<RecordStream, ApiErrors> {
+        let records: Vec<Result<Record, ApiErrors>> = self
+            .data()?
+            .records
+            .iter()
+            .filter(|record| record.user_id == Some(user_id) && record.org_id.is_none())
+            .cloned()
+            .map(Ok)
+            .collect();
+        Ok(Box::pin(stream::iter(records)))
+    }
+
+    async fn get_user_records_page(&self, user_id: ObjectId, page: PageRequest) -> Result<Vec<Record>, ApiErrors> {
+        let records = self
+            .data()?
+            .records
+            .iter()
+            .filter(|record| record.user_id == Some(user_id) && record.org_id.is_none())
+            .cloned()
+            .collect();
+        Ok(Self::page_of(records, &page))
+    }
+
+    async fn count_user_records(&self, user_id: ObjectId) -> Result<u64, ApiErrors> {
+        Ok(self
+            .data()?
+            .records
+            .iter()
+            .filter(|record| record.user_id == Some(user_id) && record.org_id.is_none())
+            .count() as u64)
+    }
+
+    async fn get_user_record_metadata(&self, user_id: ObjectId) -> Result<Vec<Record>, ApiErrors> {
+        Ok(self
+            .data()?
+            .records
+            .iter()
+            .filter(|record| record.user_id == Some(user_id) && record.org_id.is_none())
+            .map(|record| Record { password: None, secret: None, ..record.clone() })
+            .collect())
+    }
+
+    async fn delete_record(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        match data
+            .records
+            .iter()
+            .position(|record| Self::record_matches(record, record_id, user_id, expected_revision))
+        {
+            Some(index) => {
+                data.remove_record(index);
+                Ok(())
+            }
+            None => Err(Self::missed_write_error(&data.records, record_id, user_id, expected_revision)),
+        }
+    }
+
+    async fn update_record(
+        &self,
+        updated_record: UpdateRecord,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<i64, ApiErrors> {
+        Self::update_record_in(&mut self.data()?.records, updated_record, record_id, user_id, expected_revision)
+    }
+
+    async fn write_records_atomically(
+        &self,
+        writes: Vec<RecordWrite>,
+    ) -> Result<Vec<RecordWriteResult>, ApiErrors> {
+        let mut data = self.data()?;
+        // Writes go to a copy of the records, it replaces them only once every write succeeded
+        let mut records = data.records.clone();
+        let mut deleted_record_ids = Vec::new();
+        let mut results = Vec::with_capacity(writes.len());
+        for write in writes {
+            let result = match write {
+                RecordWrite::Insert(record) => {
+                    let id = record.id.unwrap_or_default();
+                    records.push(Record { id: Some(id), ..record });
+                    RecordWriteResult::Inserted(id)
+                }
+                RecordWrite::Update { record_id, owner_id, updated_record, expected_revision } => {
+                    RecordWriteResult::Updated(Self::update_record_in(
+                        &mut records,
+                        updated_record,
+                        record_id,
+                        owner_id,
+                        expected_revision,
+                    )?)
+                }
+                RecordWrite::Delete { record_id, owner_id, expected_revision } => {
+                    match records
+                        .iter()
+                        .position(|record| Self::record_matches(record, record_id, owner_id, expected_revision))
+                    {
+                        Some(index) => {
+                            records.remove(index);
+                            deleted_record_ids.push(record_id);
+                            RecordWriteResult::Deleted
+                        }
+                        None => return Err(Self::missed_write_error(&records, record_id, owner_id, expected_revision)),
+                    }
+                }
+            };
+            results.push(result);
+        }
+
+        data.records = records;
+        for record_id in deleted_record_ids {
+            data.record_shares.retain(|share| share.record_id != record_id);
+            data.remove_attachments_of_record(Some(record_id));
+        }
+        Ok(results)
+    }
+
+    async fn set_record_breach(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        revision: Option<i64>,
+        breach: BreachStatus,
+    ) -> Result<(), ApiErrors> {
+        if let Some(record) = self
+            .data()?
+            .records
+            .iter_mut()
+            .find(|record| Self::record_matches(record, record_id, user_id, Some(revision.unwrap_or(0))))
+        {
+            record.breach = Some(breach);
+        }
+        Ok(())
+    }
+
+    async fn set_rotation_reminded(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        reminded_at: DateTime,
+    ) -> Result<(), ApiErrors> {
+        if let Some(record) = self
+            .data()?
+            .records
+            .iter_mut()
+            .find(|record| Self::record_matches(record, record_id, user_id, None))
+        {
+            record.rotation_reminded_at = Some(reminded_at);
+        }
+        Ok(())
+    }
+
+    async fn get_record_owner_ids(&self) -> Result<Vec<ObjectId>, ApiErrors> {
+        let mut ids: Vec<ObjectId> = self.data()?.records.iter().filter_map(|record| record.user_id).collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    async fn upsert_record_share(&self, share: RecordShare) -> Result<ObjectId, ApiErrors> {
+        let mut data = self.data()?;
+        match data
+            .record_shares
+            .iter_mut()
+            .find(|stored| stored.record_id == share.record_id && stored.grantee_id == share.grantee_id)
+        {
+            Some(stored) => {
+                stored.permission = share.permission;
+                stored.id.ok_or_else(|| ApiErrors::ServerError("Object id was not found".to_string()))
+            }
+            None => {
+                let id = ObjectId::new();
+                data.record_shares.push(RecordShare { id: Some(id), ..share });
+                Ok(id)
+            }
+        }
+    }
+
+    async fn get_record_share(&self, record_id: ObjectId, grantee_id: ObjectId) -> Result<Option<RecordShare>, ApiErrors> {
+        Ok(self
+            .data()?
+            .record_shares
+            .iter()
+            .find(|share| share.record_id == record_id && share.grantee_id == grantee_id)
+            .cloned())
+    }
+
+    async fn get_shares_with_user(&self, grantee_id: ObjectId) -> Result<Vec<RecordShare>, ApiErrors> {
+        let mut shares: Vec<RecordShare> = self
+            .data()?
+            .record_shares
+            .iter()
+            .filter(|share| share.grantee_id == grantee_id)
+            .cloned()
+            .collect();
+        shares.sort_by_key(|item| Reverse(item.created_at));
+        Ok(shares)
+    }
+
+    async fn get_shares_by_user(&self, owner_id: ObjectId) -> Result<Vec<RecordShare>, ApiErrors> {
+        let mut shares: Vec<RecordShare> = self
+            .data()?
+            .record_shares
+            .iter()
+            .filter(|share| share.owner_id == owner_id)
+            .cloned()
+            .collect();
+        shares.sort_by_key(|item| Reverse(item.created_at));
+        Ok(shares)
+    }
+
+    async fn delete_record_share(&self, share_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let index = data
+            .record_shares
+            .iter()
+            .position(|share| share.id == Some(share_id) && (share.owner_id == user_id || share.grantee_id == user_id))
+            .ok_or_else(|| not_found("Share not found"))?;
+        data.record_shares.remove(index);
+        Ok(())
+    }
+
+    async fn create_organization(&self, org: Organization, owner_username: &str) -> Result<ObjectId, ApiErrors> {
+        let mut data = self.data()?;
+        let org_id = org.id.unwrap_or_default();
+        data.org_members.push(OrgMember {
+            id: Some(ObjectId::new()),
+            org_id,
+            org_name: org.name.clone(),
+            user_id: org.created_by,
+            username: owner_username.to_string(),
+            role: OrgRole::Owner,
+            joined_at: org.created_at,
+        });
+        data.organizations.push(Organization { id: Some(org_id), ..org });
+        Ok(org_id)
+    }
+
+    async fn get_organization(&self, org_id: ObjectId) -> Result<Organization, ApiErrors> {
+        self.data()?
+            .organizations
+            .iter()
+            .find(|org| org.id == Some(org_id))
+            .cloned()
+            .ok_or_else(|| not_found("Organization not found"))
+    }
+
+    async fn delete_organization(&self, org_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let record_ids: Vec<Option<ObjectId>> = data
+            .records
+            .iter()
+            .filter(|record| record.org_id == Some(org_id))
+            .map(|record| record.id)
+            .collect();
+        for record_id in record_ids {
+            data.remove_attachments_of_record(record_id);
+        }
+        data.records.retain(|record| record.org_id != Some(org_id));
+        data.org_collections.retain(|collection| collection.org_id != org_id);
+        data.org_invitations.retain(|invitation| invitation.org_id != org_id);
+        data.org_members.retain(|member| member.org_id != org_id);
+        let index = data
+            .organizations
+            .iter()
+            .position(|org| org.id == Some(org_id))
+            .ok_or_else(|| not_found("Organization not found"))?;
+        data.organizations.remove(index);
+        Ok(())
+    }
+
+    async fn get_org_member(&self, org_id: ObjectId, user_id: ObjectId) -> Result<Option<OrgMember>, ApiErrors> {
+        Ok(self
+            .data()?
+            .org_members
+            .iter()
+            .find(|member| member.org_id == org_id && member.user_id == user_id)
+            .cloned())
+    }
+
+    async fn get_org_members(&self, org_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors> {
+        let mut members: Vec<OrgMember> = self
+            .data()?
+            .org_members
+            .iter()
+            .filter(|member| member.org_id == org_id)
+            .cloned()
+            .collect();
+        members.sort_by_key(|member| member.joined_at);
+        Ok(members)
+    }
+
+    async fn get_user_memberships(&self, user_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors> {
+        let mut members: Vec<OrgMember> = self
+            .data()?
+            .org_members
+            .iter()
+            .filter(|member| member.user_id == user_id)
+            .cloned()
+            .collect();
+        members.sort_by(|a, b| a.org_name.cmp(&b.org_name));
+        Ok(members)
+    }
+
+    async fn insert_org_member(&self, member: OrgMember) -> Result<ObjectId, ApiErrors> {
+        let id = member.id.unwrap_or_default();
+        self.data()?.org_members.push(OrgMember { id: Some(id), ..member });
+        Ok(id)
+    }
+
+    async fn update_org_member_role(&self, org_id: ObjectId, user_id: ObjectId, role: OrgRole) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let member = data
+            .org_members
+            .iter_mut()
+            .find(|member| member.org_id == org_id && member.user_id == user_id)
+            .ok_or_else(|| not_found("Member not found"))?;
+        member.role = role;
+        Ok(())
+    }
+
+    async fn delete_org_member(&self, org_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let index = data
+            .org_members
+            .iter()
+            .position(|member| member.org_id == org_id && member.user_id == user_id)
+            .ok_or_else(|| not_found("Member not found"))?;
+        data.org_members.remove(index);
+        Ok(())
+    }
+
+    async fn insert_org_invitation(&self, invitation: OrgInvitation) -> Result<ObjectId, ApiErrors> {
+        let id = invitation.id.unwrap_or_default();
+        self.data()?.org_invitations.push(OrgInvitation { id: Some(id), ..invitation });
+        Ok(id)
+    }
+
+    async fn get_org_invitation(&self, invitation_id: ObjectId) -> Result<OrgInvitation, ApiErrors> {
+        self.data()?
+            .org_invitations
+            .iter()
+            .find(|invitation| invitation.id == Some(invitation_id))
+            .cloned()
+            .ok_or_else(|| not_found("Invitation not found"))
+    }
+
+    async fn get_org_invitations(&self, org_id: ObjectId) -> Result<Vec<OrgInvitation>, ApiErrors> {
+        let mut invitations: Vec<OrgInvitation> = self
+            .data()?
+            .org_invitations
+            .iter()
+            .filter(|invitation| invitation.org_id == org_id)
+            .cloned()
+            .collect();
+        invitations.sort_by_key(|item| Reverse(item.created_at));
+        Ok(invitations)
+    }
+
+    async fn get_user_invitations(&self, user_id: ObjectId) -> Result<Vec<OrgInvitation>, ApiErrors> {
+        let mut invitations: Vec<OrgInvitation> = self
+            .data()?
+            .org_invitations
+            .iter()
+            .filter(|invitation| invitation.user_id == user_id)
+            .cloned()
+            .collect();
+        invitations.sort_by_key(|item| Reverse(item.created_at));
+        Ok(invitations)
+    }
+
+    async fn delete_org_invitation(&self, invitation_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let index = data
+            .org_invitations
+            .iter()
+            .position(|invitation| invitation.id == Some(invitation_id))
+            .ok_or_else(|| not_found("Invitation not found"))?;
+        data.org_invitations.remove(index);
+        Ok(())
+    }
+
+    async fn insert_org_collection(&self, collection: OrgCollection) -> Result<ObjectId, ApiErrors> {
+        let id = collection.id.unwrap_or_default();
+        self.data()?.org_collections.push(OrgCollection { id: Some(id), ..collection });
+        Ok(id)
+    }
+
+    async fn get_org_collections(&self, org_id: ObjectId) -> Result<Vec<OrgCollection>, ApiErrors> {
+        let mut collections: Vec<OrgCollection> = self
+            .data()?
+            .org_collections
+            .iter()
+            .filter(|collection| collection.org_id == org_id)
+            .cloned()
+            .collect();
+        collections.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(collections)
+    }
+
+    async fn delete_org_collection(&self, org_id: ObjectId, collection_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let index = data
+            .org_collections
+            .iter()
+            .position(|collection| collection.id == Some(collection_id) && collection.org_id == org_id)
+            .ok_or_else(|| not_found("Collection not found"))?;
+        data.org_collections.remove(index);
+        for record in data
+            .records
+            .iter_mut()
+            .filter(|record| record.org_id == Some(org_id) && record.collection_id == Some(collection_id))
+        {
+            record.collection_id = None;
+        }
+        Ok(())
+    }
+
+    async fn get_org_records(&self, org_id: ObjectId, collection_id: Option<ObjectId>) -> Result<Vec<Record>, ApiErrors> {
+        let mut records: Vec<Record> = self
+            .data()?
+            .records
+            .iter()
+            .filter(|record| record.org_id == Some(org_id))
+            .filter(|record| collection_id.is_none() || record.collection_id == collection_id)
+            .cloned()
+            .collect();
+        records.sort_by(|a, b| a.service.cmp(&b.service).then_with(|| a.key.cmp(&b.key)));
+        Ok(records)
+    }
+
+    async fn insert_emergency_access(&self, access: EmergencyAccess) -> Result<ObjectId, ApiErrors> {
+        let id = access.id.unwrap_or_default();
+        self.data()?.emergency_access.push(EmergencyAccess { id: Some(id), ..access });
+        Ok(id)
+    }
+
+    async fn get_emergency_access(&self, access_id: ObjectId) -> Result<EmergencyAccess, ApiErrors> {
+        self.data()?
+            .emergency_access
+            .iter()
+            .find(|access| access.id == Some(access_id))
+            .cloned()
+            .ok_or_else(|| not_found("Emergency access not found"))
+    }
+
+    async fn get_emergency_contacts(&self, grantor_id: ObjectId) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        let mut accesses: Vec<EmergencyAccess> = self
+            .data()?
+            .emergency_access
+            .iter()
+            .filter(|access| access.grantor_id == grantor_id)
+            .cloned()
+            .collect();
+        accesses.sort_by_key(|access| access.created_at);
+        Ok(accesses)
+    }
+
+    async fn get_emergency_grants(&self, grantee_id: ObjectId) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        let mut accesses: Vec<EmergencyAccess> = self
+            .data()?
+            .emergency_access
+            .iter()
+            .filter(|access| access.grantee_id == grantee_id)
+            .cloned()
+            .collect();
+        accesses.sort_by_key(|access| access.created_at);
+        Ok(accesses)
+    }
+
+    async fn get_requested_emergency_accesses(&self) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        let mut accesses: Vec<EmergencyAccess> = self
+            .data()?
+            .emergency_access
+            .iter()
+            .filter(|access| access.status == EmergencyStatus::Requested)
+            .cloned()
+            .collect();
+        accesses.sort_by_key(|access| access.requested_at);
+        Ok(accesses)
+    }
+
+    async fn set_emergency_status(
+        &self,
+        access_id: ObjectId,
+        expected: EmergencyStatus,
+        status: EmergencyStatus,
+        at: DateTime,
+    ) -> Result<bool, ApiErrors> {
+        let mut data = self.data()?;
+        let access = match data
+            .emergency_access
+            .iter_mut()
+            .find(|access| access.id == Some(access_id) && access.status == expected)
+        {
+            Some(access) => access,
+            None => return Ok(false),
+        };
+        access.status = status;
+        match status {
+            EmergencyStatus::Requested => access.requested_at = Some(at),
+            EmergencyStatus::Approved => access.approved_at = Some(at),
+            EmergencyStatus::Idle => {
+                access.requested_at = None;
+                access.approved_at = None;
+            }
+        }
+        Ok(true)
+    }
+
+    async fn delete_emergency_access(&self, access_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let index = data
+            .emergency_access
+            .iter()
+            .position(|access| access.id == Some(access_id))
+            .ok_or_else(|| not_found("Emergency access not found"))?;
+        data.emergency_access.remove(index);
+        Ok(())
+    }
+
+    async fn insert_attachment_chunk(&self, chunk: AttachmentChunk) -> Result<(), ApiErrors> {
+        self.data()?
+            .attachment_chunks
+            .push(AttachmentChunk { id: Some(chunk.id.unwrap_or_default()), ..chunk });
+        Ok(())
+    }
+
+    async fn insert_attachment(&self, attachment: Attachment) -> Result<(), ApiErrors> {
+        self.data()?.attachments.push(attachment);
+        Ok(())
+    }
+
+    async fn get_attachment(&self, attachment_id: ObjectId, record_id: ObjectId) -> Result<Attachment, ApiErrors> {
+        self.data()?
+            .attachments
+            .iter()
+            .find(|attachment| attachment.id == attachment_id && attachment.metadata.record_id == record_id)
+            .cloned()
+            .ok_or_else(|| not_found("Attachment not found"))
+    }
+
+    async fn get_record_attachments(&self, record_id: ObjectId) -> Result<Vec<Attachment>, ApiErrors> {
+        let mut attachments: Vec<Attachment> = self
+            .data()?
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.metadata.record_id == record_id)
+            .cloned()
+            .collect();
+        attachments.sort_by_key(|attachment| attachment.upload_date);
+        Ok(attachments)
+    }
+
+    async fn get_attachment_chunks(&self, attachment_id: ObjectId) -> Result<AttachmentChunks, ApiErrors> {
+        let mut chunks: Vec<AttachmentChunk> = self
+            .data()?
+            .attachment_chunks
+            .iter()
+            .filter(|chunk| chunk.files_id == attachment_id)
+            .cloned()
+            .collect();
+        chunks.sort_by_key(|chunk| chunk.n);
+        Ok(Box::pin(stream::iter(chunks.into_iter().map(Ok))))
+    }
+
+    async fn get_used_attachment_bytes(&self, user_id: ObjectId) -> Result<i64, ApiErrors> {
+        Ok(self
+            .data()?
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.metadata.user_id == user_id)
+            .map(|attachment| attachment.metadata.size)
+            .sum())
+    }
+
+    async fn delete_attachment(&self, attachment_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        data.attachments.retain(|attachment| attachment.id != attachment_id);
+        data.attachment_chunks.retain(|chunk| chunk.files_id != attachment_id);
+        Ok(())
+    }
+
+    async fn insert_send(&self, send: SecretSend) -> Result<(), ApiErrors> {
+        self.data()?.sends.push(send);
+        Ok(())
+    }
+
+    async fn get_send(&self, send_id: &str) -> Result<SecretSend, ApiErrors> {
+        self.data()?
+            .sends
+            .iter()
+            .find(|send| send.id == send_id)
+            .cloned()
+            .ok_or_else(|| not_found("Send not found"))
+    }
+
+    async fn consume_send(&self, send_id: &str, now: DateTime) -> Result<Option<SecretSend>, ApiErrors> {
+        let mut data = self.data()?;
+        let index = match data
+            .sends
+            .iter()
+            .position(|send| send.id == send_id && send.views_left > 0 && send.expires_at > now)
+        {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        // The send as it was before the view
+        let send = data.sends[index].clone();
+        data.sends[index].views_left -= 1;
+        if data.sends[index].views_left <= 0 {
+            data.sends.remove(index);
+        }
+        Ok(Some(send))
+    }
+
+    async fn delete_expired_sends(&self, now: DateTime) -> Result<u64, ApiErrors> {
+        let mut data = self.data()?;
+        let before = data.sends.len();
+        data.sends.retain(|send| send.expires_at > now && send.views_left > 0);
+        Ok((before - data.sends.len()) as u64)
+    }
+
+    async fn insert_audit_event(&self, event: AuditEvent) -> Result<ObjectId, ApiErrors> {
+        let id = event.id.unwrap_or_default();
+        self.data()?.audit_log.push(AuditEvent { id: Some(id), ..event });
+        Ok(id)
+    }
+
+    async fn get_audit_events(&self, target_id: ObjectId) -> Result<Vec<AuditEvent>, ApiErrors> {
+        let mut events: Vec<AuditEvent> = self
+            .data()?
+            .audit_log
+            .iter()
+            .filter(|event| event.target_id == Some(target_id))
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| event.created_at);
+        Ok(events)
+    }
+
+    async fn search_records(&self, params: SearchParams) -> Result<Vec<Record>, ApiErrors> {
+        let records = self
+            .data()?
+            .records
+            .iter()
+            .filter(|record| Self::search_matches(&params, record))
+            .cloned()
+            .collect();
+        Ok(Self::page_of(records, &params.page))
+    }
+
+    async fn count_search_records(&self, params: SearchParams) -> Result<u64, ApiErrors> {
+        Ok(self
+            .data()?
+            .records
+            .iter()
+            .filter(|record| Self::search_matches(&params, record))
+            .count() as u64)
+    }
+
+    async fn insert_saved_search(&self, search: SavedSearch) -> Result<ObjectId, ApiErrors> {
+        let id = search.id.unwrap_or_default();
+        self.data()?.saved_searches.push(SavedSearch { id: Some(id), ..search });
+        Ok(id)
+    }
+
+    async fn get_saved_search(&self, search_id: ObjectId, user_id: ObjectId) -> Result<SavedSearch, ApiErrors> {
+        self.data()?
+            .saved_searches
+            .iter()
+            .find(|search| search.id == Some(search_id) && search.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| not_found("Saved search not found"))
+    }
+
+    async fn get_saved_searches(&self, user_id: ObjectId) -> Result<Vec<SavedSearch>, ApiErrors> {
+        let mut searches: Vec<SavedSearch> = self
+            .data()?
+            .saved_searches
+            .iter()
+            .filter(|search| search.user_id == user_id)
+            .cloned()
+            .collect();
+        searches.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(searches)
+    }
+
+    async fn replace_saved_search(&self, search: SavedSearch) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let stored = data
+            .saved_searches
+            .iter_mut()
+            .find(|stored| stored.id.is_some() && stored.id == search.id && stored.user_id == search.user_id)
+            .ok_or_else(|| not_found("Saved search not found"))?;
+        *stored = search;
+        Ok(())
+    }
+
+    async fn delete_saved_search(&self, search_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let index = data
+            .saved_searches
+            .iter()
+            .position(|search| search.id == Some(search_id) && search.user_id == user_id)
+            .ok_or_else(|| not_found("Saved search not found"))?;
+        data.saved_searches.remove(index);
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<AuthUser, ApiErrors> {
+        self.data()?
+            .users
+            .iter()
+            .find(|user| user.id == Some(user_id))
+            .map(Self::to_auth_user)
+            .ok_or_else(|| not_found("User not found"))?
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<AuthUser, ApiErrors> {
+        self.data()?
+            .users
+            .iter()
+            .find(|user| user.username == username)
+            .map(Self::to_auth_user)
+            .ok_or_else(|| not_found("User not found"))?
+    }
+
+    async fn update_user_fields(&self, user_id: ObjectId, update_user: UpdateUser) -> Result<(), ApiErrors> {
+        let mut data = self.data()?;
+        let user = data
+            .users
+            .iter_mut()
+            .find(|user| user.id == Some(user_id))
+            .ok_or_else(|| not_found("User not found"))?;
+        if let Some(email) = update_user.email {
+            user.email = email;
+        }
+        if let Some(password) = update_user.new_password {
+            user.password = password;
+        }
+        Ok(())
+    }
+}
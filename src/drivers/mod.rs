@@ -1 +1,6 @@
+pub mod breach;
+pub mod memory;
 pub mod mongodb;
+pub mod notifier;
+pub mod sql;
+pub mod storage;
@@ -1,21 +1,68 @@
-pub mod mongo_trait;
+pub mod page_filter;
+pub mod query_filter;
 
-use crate::{drivers::mongodb::mongo_trait::TMongoClient, shared::types::{Record, UpdateRecord, AuthUser, UpdateUser}};
-use bson::{doc, oid::ObjectId, Document, Regex};
+use crate::{drivers::storage::storage_trait::{AttachmentChunks, RecordStream, Storage}, shared::types::{Attachment, AttachmentChunk, AuditEvent, SecretSend, BreachStatus, EmergencyAccess, EmergencyStatus, OrgCollection, OrgInvitation, OrgMember, OrgRole, Organization, Record, RecordShare, RecordWrite, RecordWriteResult, SavedSearch, UpdateRecord, AuthUser, UpdateUser}};
+use std::time::Duration;
+
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
+use rocket::futures::TryStreamExt;
+use serde::{de::DeserializeOwned, Serialize};
 use mongodb::{
-    options::{ClientOptions, FindOptions},
-    Client, Cursor, 
+    error::{CommandError, ErrorKind},
+    options::{AggregateOptions, ClientOptions, CountOptions, FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+    ClientSession,
+    Client,
 };
 use crate::{
     modules::search_module::SearchParams,
     shared::{
-        env_config::{get_db_name, get_db_uri},
+        pagination::PageRequest,
+        env_config::{get_db_name, get_db_uri, get_search_index, get_search_max_time_ms},
         types::{
             ApiErrors, User,
         },
     },
 };
 
+/**
+ * Filter of the records a search matches, on every page
+ */
+fn search_filter(params: &SearchParams) -> Result<Document, ApiErrors> {
+    // Personal records of the user, the ones shared with them and those of their organizations
+    let mut conditions = vec![Bson::Document(doc! {
+        "$or": [
+            { "user_id": params.user_id, "org_id": null },
+            { "_id": { "$in": params.shared_record_ids.clone() } },
+            { "org_id": { "$in": params.org_ids.clone() } },
+        ]
+    })];
+
+    let record_types: Vec<Bson> = [&params.password_record, &params.secret_record]
+        .into_iter()
+        .flatten()
+        .map(|record_type| bson::to_bson(&record_type).map_err(|err| ApiErrors::ServerError(err.to_string())))
+        .collect::<Result<_, _>>()?;
+    if !record_types.is_empty() {
+        conditions.push(Bson::Document(doc! { "record_type": { "$in": record_types } }));
+    }
+
+    if let Some(query) = &params.query {
+        conditions.push(Bson::Document(query_filter::query_filter(query)));
+    }
+    Ok(doc! { "$and": conditions })
+}
+
+fn search_error(err: mongodb::error::Error) -> ApiErrors {
+    match err.kind.as_ref() {
+        // MaxTimeMSExpired
+        ErrorKind::Command(CommandError { code: 50, .. }) => {
+            ApiErrors::BadRequest("Search took too long, narrow the query down".to_string())
+        }
+        _ => ApiErrors::ServerError(err.to_string()),
+    }
+}
+
+#[derive(Clone)]
 pub struct MongoClient {
     client: Option<mongodb::Client>,
 }
@@ -29,6 +76,23 @@ impl MongoClient {
     pub fn new() -> MongoClient {
         MongoClient { client: None }
     }
+
+    /**
+     * Connect to the Database
+     */
+    pub async fn connect(&mut self) {
+        let db_uri = get_db_uri();
+        let client_options = ClientOptions::parse(db_uri)
+            .await
+            .expect("There was an error parsing the DB_URI");
+
+        let client = Client::with_options(client_options)
+            .expect("There was an error connecting to the database");
+
+        println!("Connection to mongodb established!");
+
+        self.client = Some(client);
+    }
     /**
      * Get the client
      */
@@ -38,58 +102,302 @@ impl MongoClient {
             None => panic!("Connect to client not established!"),
         }
     }
-}
 
-#[async_trait]
-impl TMongoClient for MongoClient {
     /**
-     * Connect to the Database
+     * Filter for a single record owned by the user, optionally pinned to a revision.
+     * Records created before revisions existed have no field and count as revision 0
      */
-    async fn connect(&mut self) {
-        let db_uri = get_db_uri();
-        let client_options = ClientOptions::parse(db_uri)
+    fn record_filter(record_id: ObjectId, user_id: ObjectId, expected_revision: Option<i64>) -> Document {
+        let mut filter = doc! { "_id": record_id, "user_id": user_id };
+        match expected_revision {
+            Some(0) => {
+                filter.insert("revision", doc! { "$in": [0_i64, null] });
+            }
+            Some(revision) => {
+                filter.insert("revision", revision);
+            }
+            None => {}
+        }
+        filter
+    }
+
+    /**
+     * Build the update for a record patch, null fields are unset
+     */
+    fn patch_field<T: Into<Bson>>(set: &mut Document, unset: &mut Document, field: &str, value: Option<Option<T>>) {
+        match value {
+            Some(Some(value)) => {
+                set.insert(field, value);
+            }
+            Some(None) => {
+                unset.insert(field, "");
+            }
+            None => {}
+        }
+    }
+
+    fn record_update_document(updated_record: UpdateRecord) -> Document {
+        let password_changed = matches!(updated_record.password, Some(Some(_)));
+        // A breach check only holds for the credentials that were checked
+        let credentials_changed = updated_record.password.is_some() || updated_record.email.is_some();
+        let fields = [
+            ("email", updated_record.email),
+            ("password", updated_record.password),
+            ("username", updated_record.username),
+            ("service", updated_record.service),
+            ("key", updated_record.key),
+            ("secret", updated_record.secret),
+        ];
+        let now = DateTime::now();
+        let mut set = doc! { "updated_at": now };
+        let mut unset = Document::new();
+        if password_changed {
+            set.insert("password_changed_at", now);
+        }
+        for (field, value) in fields {
+            Self::patch_field(&mut set, &mut unset, field, value);
+        }
+        if credentials_changed {
+            unset.insert("breach", "");
+        }
+        Self::patch_field(&mut set, &mut unset, "two_factor", updated_record.two_factor);
+        Self::patch_field(&mut set, &mut unset, "rotation_period", updated_record.rotation_period);
+        Self::patch_field(&mut set, &mut unset, "uris", updated_record.uris);
+        Self::patch_field(&mut set, &mut unset, "tags", updated_record.tags);
+
+        // Every write bumps the revision so concurrent writers can detect each other
+        let mut update = doc! { "$inc": { "revision": 1_i64 }, "$set": set };
+        if !unset.is_empty() {
+            update.insert("$unset", unset);
+        }
+        update
+    }
+
+    fn to_auth_user(user: User) -> Result<AuthUser, ApiErrors> {
+        Ok(AuthUser {
+            id: user.id.ok_or(ApiErrors::ServerError("Object id was not found".to_string()))?.to_string(),
+            name: user.name,
+            email: user.email,
+            username: user.username,
+        })
+    }
+
+    /**
+     * Remove the shares of a deleted record, inside the session of the delete when there is one
+     */
+    async fn delete_shares_of_record(
+        &self,
+        record_id: ObjectId,
+        session: Option<&mut ClientSession>,
+    ) -> Result<(), ApiErrors> {
+        let collection = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<RecordShare>("record_shares");
+        let filter = doc! { "record_id": record_id };
+        let res = match session {
+            Some(session) => collection.delete_many_with_session(filter, None, session).await,
+            None => collection.delete_many(filter, None).await,
+        };
+        res.map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
+    }
+
+    /**
+     * Remove the attachments of a deleted record
+     */
+    async fn delete_attachments_of_record(&self, record_id: ObjectId) -> Result<(), ApiErrors> {
+        let attachments: Vec<Attachment> = self
+            .find_all("attachments.files", doc! { "metadata.record_id": record_id }, doc! {})
+            .await?;
+        for attachment in attachments {
+            self.delete_attachment(attachment.id).await?;
+        }
+        Ok(())
+    }
+
+    async fn find_all<T>(&self, collection: &str, filter: Document, sort: Document) -> Result<Vec<T>, ApiErrors>
+    where
+        T: DeserializeOwned + Unpin + Send + Sync,
+    {
+        let find_options = FindOptions::builder().sort(sort).build();
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<T>(collection)
+            .find(filter, find_options)
             .await
-            .expect("There was an error parsing the DB_URI");
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .try_collect()
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))
+    }
 
-        let client = Client::with_options(client_options)
-            .expect("There was an error connecting to the database");
+    async fn insert<T: Serialize>(&self, collection: &str, document: &T) -> Result<ObjectId, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<T>(collection)
+            .insert_one(document, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .inserted_id
+            .as_object_id()
+            .ok_or_else(|| ApiErrors::ServerError("Error converting object id".to_string()))
+    }
 
-        println!("Connection to mongodb established!");
+    /**
+     * Delete a single document, not found when nothing matched
+     */
+    async fn delete_one(&self, collection: &str, filter: Document, not_found: &str) -> Result<(), ApiErrors> {
+        let res = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Document>(collection)
+            .delete_one(filter, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        if res.deleted_count == 0 {
+            return Err(ApiErrors::NotFound(not_found.to_string()));
+        }
+        Ok(())
+    }
 
-        self.client = Some(client);
+    async fn delete_all(&self, collection: &str, filter: Document) -> Result<(), ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Document>(collection)
+            .delete_many(filter, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
     }
 
+    /**
+     * Work out why a conditional write matched nothing
+     */
+    async fn missed_write_error(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> ApiErrors {
+        if expected_revision.is_none() {
+            return ApiErrors::NotFound("Record not found".to_string());
+        }
+        let exists = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .count_documents(doc! { "_id": record_id, "user_id": user_id }, None)
+            .await;
+        match exists {
+            Ok(0) => ApiErrors::NotFound("Record not found".to_string()),
+            Ok(_) => ApiErrors::PreconditionFailed("Record has been modified".to_string()),
+            Err(err) => ApiErrors::ServerError(err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for MongoClient {
     async fn search_records(
         &self,
         params: SearchParams,
-    ) -> Result<Cursor<Record>, ApiErrors> {
-        let mut filter = doc! {
-            "user_id": params.user_id
+    ) -> Result<Vec<Record>, ApiErrors> {
+        let mut filter = search_filter(&params)?;
+        if let Some(page_filter) = page_filter::page_filter(&params.page) {
+            filter
+                .get_array_mut("$and")
+                .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+                .push(Bson::Document(page_filter));
+        }
+        let max_time = Duration::from_millis(get_search_max_time_ms());
+        let collection = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records");
+        let search_stage = get_search_index()
+            .zip(params.query.as_ref())
+            .and_then(|(index, query)| query_filter::fuzzy_search_stage(query, &index));
+
+        // One record more than the limit tells whether another page follows
+        let res = match search_stage {
+            // The index only finds the records, they are ranked by the api and paged by id
+            Some(search_stage) => {
+                let pipeline = vec![
+                    search_stage,
+                    doc! { "$match": filter },
+                    doc! { "$sort": page_filter::page_sort(&params.page) },
+                    doc! { "$limit": params.page.limit + 1 },
+                ];
+                let aggregate_options = AggregateOptions::builder().max_time(max_time).build();
+                collection
+                    .aggregate(pipeline, aggregate_options)
+                    .await
+                    .map(|cursor| cursor.with_type::<Record>())
+            }
+            None => {
+                let find_options = FindOptions::builder()
+                    .sort(page_filter::page_sort(&params.page))
+                    .limit(params.page.limit + 1)
+                    .max_time(max_time)
+                    .build();
+                collection.find(filter, find_options).await
+            }
         };
 
-        if let Some(query) = params.query {
-            //{ $or: [{ key:{ $regex: /n/i } }, { service: { $regex: /n/i }}] }
-            let reg = Regex { pattern: format!("{}", query.clone()), options: "i".to_string()};
-            filter.insert("$or", vec![
-                doc!{"service": doc!{"$regex": reg.clone()}},
-                doc!{"key": doc!{"$regex": reg}},
-            ]);
-        }
-        print!("{:?}", filter);
-        let find_options = FindOptions::builder()
-            .limit(params.limit.unwrap_or(10))
-            .skip(params.page)
+        res.map_err(search_error)?.try_collect().await.map_err(search_error)
+    }
+
+    async fn count_search_records(&self, params: SearchParams) -> Result<u64, ApiErrors> {
+        let count_options = CountOptions::builder()
+            .max_time(Duration::from_millis(get_search_max_time_ms()))
             .build();
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .count_documents(search_filter(&params)?, count_options)
+            .await
+            .map_err(search_error)
+    }
+
+    async fn insert_saved_search(&self, search: SavedSearch) -> Result<ObjectId, ApiErrors> {
+        self.insert("saved_searches", &search).await
+    }
 
+    async fn get_saved_search(&self, search_id: ObjectId, user_id: ObjectId) -> Result<SavedSearch, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<SavedSearch>("saved_searches")
+            .find_one(doc! { "_id": search_id, "user_id": user_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .ok_or_else(|| ApiErrors::NotFound("Saved search not found".to_string()))
+    }
+
+    async fn get_saved_searches(&self, user_id: ObjectId) -> Result<Vec<SavedSearch>, ApiErrors> {
+        self.find_all("saved_searches", doc! { "user_id": user_id }, doc! { "name": 1 }).await
+    }
+
+    async fn replace_saved_search(&self, search: SavedSearch) -> Result<(), ApiErrors> {
+        let search_id = search
+            .id
+            .ok_or_else(|| ApiErrors::ServerError("Object id was not found".to_string()))?;
         let res = self
             .get_client()
             .database(&get_db_name())
-            .collection::<Record>("records")
-            .find(filter, find_options)
+            .collection::<SavedSearch>("saved_searches")
+            .replace_one(doc! { "_id": search_id, "user_id": search.user_id }, &search, None)
             .await
             .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        if res.matched_count == 0 {
+            return Err(ApiErrors::NotFound("Saved search not found".to_string()));
+        }
+        Ok(())
+    }
 
-        Ok(res)
+    async fn delete_saved_search(&self, search_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors> {
+        self.delete_one("saved_searches", doc! { "_id": search_id, "user_id": user_id }, "Saved search not found")
+            .await
     }
 
     async fn email_exists(&self, email: &str) -> Result<bool, ApiErrors> {
@@ -145,15 +453,68 @@ impl TMongoClient for MongoClient {
     async fn get_all_user_records(
         &self,
         user_id: ObjectId,
-    ) -> Result<Cursor<Record>, ApiErrors> {
-        let res = self
+    ) -> Result<RecordStream, ApiErrors> {
+        let cursor = self
             .get_client()
             .database(&get_db_name())
             .collection::<Record>("records")
-            .find(doc! { "user_id": user_id }, None)
+            .find(doc! { "user_id": user_id, "org_id": null }, None)
             .await
             .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
-        Ok(res)
+        Ok(Box::pin(cursor.map_err(|err| ApiErrors::ServerError(err.to_string()))))
+    }
+
+    async fn get_user_records_page(
+        &self,
+        user_id: ObjectId,
+        page: PageRequest,
+    ) -> Result<Vec<Record>, ApiErrors> {
+        let mut filter = doc! { "user_id": user_id, "org_id": null };
+        if let Some(page_filter) = page_filter::page_filter(&page) {
+            filter.insert("$and", vec![page_filter]);
+        }
+        let find_options = FindOptions::builder()
+            .sort(page_filter::page_sort(&page))
+            .limit(page.limit + 1)
+            .build();
+
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .find(filter, find_options)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .try_collect()
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))
+    }
+
+    async fn count_user_records(&self, user_id: ObjectId) -> Result<u64, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .count_documents(doc! { "user_id": user_id, "org_id": null }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))
+    }
+
+    async fn get_user_record_metadata(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<Record>, ApiErrors> {
+        let find_options = FindOptions::builder()
+            .projection(doc! { "password": 0, "secret": 0 })
+            .build();
+
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .find(doc! { "user_id": user_id, "org_id": null }, find_options)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .try_collect()
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))
     }
 
     async fn insert_record(&self, record: Record) -> Result<ObjectId, ApiErrors> {
@@ -188,57 +549,121 @@ impl TMongoClient for MongoClient {
         Ok(record)
     }
 
-    async fn delete_record(&self, record_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors> {
+    async fn get_record_by_id(&self, record_id: ObjectId) -> Result<Record, ApiErrors> {
         self.get_client()
             .database(&get_db_name())
             .collection::<Record>("records")
-            .find_one_and_delete(doc! {"_id": record_id, "user_id": user_id}, None)
+            .find_one(doc! { "_id": record_id }, None)
             .await
             .map_err(|err| ApiErrors::ServerError(err.to_string()))?
-            .ok_or_else(|| ApiErrors::NotFound("Record not found".to_string()))?;
-        Ok(())
+            .ok_or_else(|| ApiErrors::NotFound("Record not found".to_string()))
     }
 
-    async fn update_record(
+    async fn delete_record(
         &self,
-        updated_record: UpdateRecord,
         record_id: ObjectId,
         user_id: ObjectId,
+        expected_revision: Option<i64>,
     ) -> Result<(), ApiErrors> {
-        let mut update = Document::new();
-        if let Some(email) = updated_record.email {
-            update.insert("email", email);
-        }
-        if let Some(password) = updated_record.password {
-            update.insert("password", password);
-        }
-        if let Some(username) = updated_record.username {
-            update.insert("username", username);
-        }
-        if let Some(service) = updated_record.service {
-            update.insert("service", service);
-        }
-        if let Some(key) = updated_record.key {
-            update.insert("key", key);
+        let deleted = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .find_one_and_delete(Self::record_filter(record_id, user_id, expected_revision), None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+
+        match deleted {
+            Some(_) => {
+                self.delete_shares_of_record(record_id, None).await?;
+                self.delete_attachments_of_record(record_id).await
+            }
+            None => Err(self.missed_write_error(record_id, user_id, expected_revision).await),
         }
-        if let Some(secret) = updated_record.secret {
-            update.insert("secret", secret);
+    }
+
+    async fn update_record(
+        &self,
+        updated_record: UpdateRecord,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<i64, ApiErrors> {
+        let update = Self::record_update_document(updated_record);
+
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let record = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .find_one_and_update(
+                Self::record_filter(record_id, user_id, expected_revision),
+                update,
+                options,
+            )
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+
+        match record {
+            Some(record) => Ok(record.revision.unwrap_or(0)),
+            None => Err(self.missed_write_error(record_id, user_id, expected_revision).await),
         }
+    }
 
+    async fn set_record_breach(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        revision: Option<i64>,
+        breach: BreachStatus,
+    ) -> Result<(), ApiErrors> {
+        let breach = bson::to_bson(&breach).map_err(|err| ApiErrors::ServerError(err.to_string()))?;
         self.get_client()
             .database(&get_db_name())
             .collection::<Record>("records")
-            .find_one_and_update(
-                doc! { "_id": record_id, "user_id": user_id },
-                doc! { "$set": update },
+            .update_one(
+                Self::record_filter(record_id, user_id, Some(revision.unwrap_or(0))),
+                doc! { "$set": { "breach": breach } },
                 None,
             )
             .await
             .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
+    }
 
+    async fn set_rotation_reminded(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        reminded_at: DateTime,
+    ) -> Result<(), ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .update_one(
+                Self::record_filter(record_id, user_id, None),
+                doc! { "$set": { "rotation_reminded_at": reminded_at } },
+                None,
+            )
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
         Ok(())
     }
 
+    async fn get_record_owner_ids(&self) -> Result<Vec<ObjectId>, ApiErrors> {
+        let ids = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .distinct("user_id", None, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(ids.into_iter().filter_map(|id| id.as_object_id()).collect())
+    }
+
     async fn get_user_by_id(
         &self,
         user_id: ObjectId,
@@ -251,14 +676,443 @@ impl TMongoClient for MongoClient {
                 None
             ).await
             .map_err(|err| ApiErrors::ServerError(err.to_string()))?
-            .map(|user_doc|
-                Ok(AuthUser { 
-                    id: user_doc.id.ok_or(ApiErrors::ServerError("Object id was not found".to_string()))?.to_string(), 
-                    name: user_doc.name, 
-                    email: user_doc.email,
-                    username: user_doc.username 
-                })
-            ).ok_or(ApiErrors::NotFound("User not found".to_string()))?
+            .map(Self::to_auth_user)
+            .ok_or(ApiErrors::NotFound("User not found".to_string()))?
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<AuthUser, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<User>("users")
+            .find_one(doc! { "username": username }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .map(Self::to_auth_user)
+            .ok_or(ApiErrors::NotFound("User not found".to_string()))?
+    }
+
+    async fn upsert_record_share(&self, share: RecordShare) -> Result<ObjectId, ApiErrors> {
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        let share = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<RecordShare>("record_shares")
+            .find_one_and_update(
+                doc! { "record_id": share.record_id, "grantee_id": share.grantee_id },
+                doc! {
+                    "$set": {
+                        "permission": bson::to_bson(&share.permission)
+                            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+                    },
+                    "$setOnInsert": {
+                        "owner_id": share.owner_id,
+                        "owner_username": share.owner_username,
+                        "grantee_username": share.grantee_username,
+                        "created_at": share.created_at,
+                    },
+                },
+                options,
+            )
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .ok_or_else(|| ApiErrors::ServerError("Share was not stored".to_string()))?;
+        share.id.ok_or_else(|| ApiErrors::ServerError("Object id was not found".to_string()))
+    }
+
+    async fn get_record_share(
+        &self,
+        record_id: ObjectId,
+        grantee_id: ObjectId,
+    ) -> Result<Option<RecordShare>, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<RecordShare>("record_shares")
+            .find_one(doc! { "record_id": record_id, "grantee_id": grantee_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))
+    }
+
+    async fn get_shares_with_user(&self, grantee_id: ObjectId) -> Result<Vec<RecordShare>, ApiErrors> {
+        self.find_all("record_shares", doc! { "grantee_id": grantee_id }, doc! { "created_at": -1 }).await
+    }
+
+    async fn get_shares_by_user(&self, owner_id: ObjectId) -> Result<Vec<RecordShare>, ApiErrors> {
+        self.find_all("record_shares", doc! { "owner_id": owner_id }, doc! { "created_at": -1 }).await
+    }
+
+    async fn delete_record_share(&self, share_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors> {
+        self.delete_one(
+            "record_shares",
+            doc! { "_id": share_id, "$or": [{ "owner_id": user_id }, { "grantee_id": user_id }] },
+            "Share not found",
+        )
+        .await
+    }
+
+    async fn create_organization(&self, org: Organization, owner_username: &str) -> Result<ObjectId, ApiErrors> {
+        let org_id = self.insert("organizations", &org).await?;
+        let owner = OrgMember {
+            id: None,
+            org_id,
+            org_name: org.name,
+            user_id: org.created_by,
+            username: owner_username.to_string(),
+            role: OrgRole::Owner,
+            joined_at: org.created_at,
+        };
+        self.insert("org_members", &owner).await?;
+        Ok(org_id)
+    }
+
+    async fn get_organization(&self, org_id: ObjectId) -> Result<Organization, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Organization>("organizations")
+            .find_one(doc! { "_id": org_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .ok_or_else(|| ApiErrors::NotFound("Organization not found".to_string()))
+    }
+
+    async fn delete_organization(&self, org_id: ObjectId) -> Result<(), ApiErrors> {
+        // The organization goes last so a failure part way can be retried, and attachments go before
+        // their records so a retry still finds them
+        let records: Vec<Record> = self.find_all("records", doc! { "org_id": org_id }, doc! {}).await?;
+        for record_id in records.iter().filter_map(|record| record.id) {
+            self.delete_attachments_of_record(record_id).await?;
+        }
+        self.delete_all("records", doc! { "org_id": org_id }).await?;
+        for collection in ["org_collections", "org_invitations", "org_members"] {
+            self.delete_all(collection, doc! { "org_id": org_id }).await?;
+        }
+        self.delete_one("organizations", doc! { "_id": org_id }, "Organization not found").await
+    }
+
+    async fn get_org_member(&self, org_id: ObjectId, user_id: ObjectId) -> Result<Option<OrgMember>, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<OrgMember>("org_members")
+            .find_one(doc! { "org_id": org_id, "user_id": user_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))
+    }
+
+    async fn get_org_members(&self, org_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors> {
+        self.find_all("org_members", doc! { "org_id": org_id }, doc! { "joined_at": 1 }).await
+    }
+
+    async fn get_user_memberships(&self, user_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors> {
+        self.find_all("org_members", doc! { "user_id": user_id }, doc! { "org_name": 1 }).await
+    }
+
+    async fn insert_org_member(&self, member: OrgMember) -> Result<ObjectId, ApiErrors> {
+        self.insert("org_members", &member).await
+    }
+
+    async fn update_org_member_role(&self, org_id: ObjectId, user_id: ObjectId, role: OrgRole) -> Result<(), ApiErrors> {
+        let role = bson::to_bson(&role).map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        let res = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<OrgMember>("org_members")
+            .update_one(doc! { "org_id": org_id, "user_id": user_id }, doc! { "$set": { "role": role } }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        if res.matched_count == 0 {
+            return Err(ApiErrors::NotFound("Member not found".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn delete_org_member(&self, org_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors> {
+        self.delete_one("org_members", doc! { "org_id": org_id, "user_id": user_id }, "Member not found").await
+    }
+
+    async fn insert_org_invitation(&self, invitation: OrgInvitation) -> Result<ObjectId, ApiErrors> {
+        self.insert("org_invitations", &invitation).await
+    }
+
+    async fn get_org_invitation(&self, invitation_id: ObjectId) -> Result<OrgInvitation, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<OrgInvitation>("org_invitations")
+            .find_one(doc! { "_id": invitation_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .ok_or_else(|| ApiErrors::NotFound("Invitation not found".to_string()))
+    }
+
+    async fn get_org_invitations(&self, org_id: ObjectId) -> Result<Vec<OrgInvitation>, ApiErrors> {
+        self.find_all("org_invitations", doc! { "org_id": org_id }, doc! { "created_at": -1 }).await
+    }
+
+    async fn get_user_invitations(&self, user_id: ObjectId) -> Result<Vec<OrgInvitation>, ApiErrors> {
+        self.find_all("org_invitations", doc! { "user_id": user_id }, doc! { "created_at": -1 }).await
+    }
+
+    async fn delete_org_invitation(&self, invitation_id: ObjectId) -> Result<(), ApiErrors> {
+        self.delete_one("org_invitations", doc! { "_id": invitation_id }, "Invitation not found").await
+    }
+
+    async fn insert_org_collection(&self, collection: OrgCollection) -> Result<ObjectId, ApiErrors> {
+        self.insert("org_collections", &collection).await
+    }
+
+    async fn get_org_collections(&self, org_id: ObjectId) -> Result<Vec<OrgCollection>, ApiErrors> {
+        self.find_all("org_collections", doc! { "org_id": org_id }, doc! { "name": 1 }).await
+    }
+
+    async fn delete_org_collection(&self, org_id: ObjectId, collection_id: ObjectId) -> Result<(), ApiErrors> {
+        self.delete_one(
+            "org_collections",
+            doc! { "_id": collection_id, "org_id": org_id },
+            "Collection not found",
+        )
+        .await?;
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records")
+            .update_many(
+                doc! { "org_id": org_id, "collection_id": collection_id },
+                doc! { "$unset": { "collection_id": "" } },
+                None,
+            )
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
+    }
+
+    async fn insert_emergency_access(&self, access: EmergencyAccess) -> Result<ObjectId, ApiErrors> {
+        self.insert("emergency_access", &access).await
+    }
+
+    async fn get_emergency_access(&self, access_id: ObjectId) -> Result<EmergencyAccess, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<EmergencyAccess>("emergency_access")
+            .find_one(doc! { "_id": access_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .ok_or_else(|| ApiErrors::NotFound("Emergency access not found".to_string()))
+    }
+
+    async fn get_emergency_contacts(&self, grantor_id: ObjectId) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        self.find_all("emergency_access", doc! { "grantor_id": grantor_id }, doc! { "created_at": 1 }).await
+    }
+
+    async fn get_emergency_grants(&self, grantee_id: ObjectId) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        self.find_all("emergency_access", doc! { "grantee_id": grantee_id }, doc! { "created_at": 1 }).await
+    }
+
+    async fn get_requested_emergency_accesses(&self) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        let status = bson::to_bson(&EmergencyStatus::Requested).map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        self.find_all("emergency_access", doc! { "status": status }, doc! { "requested_at": 1 }).await
+    }
+
+    async fn set_emergency_status(
+        &self,
+        access_id: ObjectId,
+        expected: EmergencyStatus,
+        status: EmergencyStatus,
+        at: DateTime,
+    ) -> Result<bool, ApiErrors> {
+        let to_bson = |status: &EmergencyStatus| bson::to_bson(status).map_err(|err| ApiErrors::ServerError(err.to_string()));
+        let mut set = doc! { "status": to_bson(&status)? };
+        let mut update = Document::new();
+        match status {
+            EmergencyStatus::Requested => {
+                set.insert("requested_at", at);
+            }
+            EmergencyStatus::Approved => {
+                set.insert("approved_at", at);
+            }
+            EmergencyStatus::Idle => {
+                update.insert("$unset", doc! { "requested_at": "", "approved_at": "" });
+            }
+        }
+        update.insert("$set", set);
+
+        let res = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<EmergencyAccess>("emergency_access")
+            .update_one(doc! { "_id": access_id, "status": to_bson(&expected)? }, update, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(res.modified_count == 1)
+    }
+
+    async fn delete_emergency_access(&self, access_id: ObjectId) -> Result<(), ApiErrors> {
+        self.delete_one("emergency_access", doc! { "_id": access_id }, "Emergency access not found").await
+    }
+
+    async fn insert_attachment_chunk(&self, chunk: AttachmentChunk) -> Result<(), ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<AttachmentChunk>("attachments.chunks")
+            .insert_one(chunk, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
+    }
+
+    async fn insert_attachment(&self, attachment: Attachment) -> Result<(), ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Attachment>("attachments.files")
+            .insert_one(attachment, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_attachment(&self, attachment_id: ObjectId, record_id: ObjectId) -> Result<Attachment, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<Attachment>("attachments.files")
+            .find_one(doc! { "_id": attachment_id, "metadata.record_id": record_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .ok_or_else(|| ApiErrors::NotFound("Attachment not found".to_string()))
+    }
+
+    async fn get_record_attachments(&self, record_id: ObjectId) -> Result<Vec<Attachment>, ApiErrors> {
+        self.find_all("attachments.files", doc! { "metadata.record_id": record_id }, doc! { "uploadDate": 1 })
+            .await
+    }
+
+    async fn get_attachment_chunks(&self, attachment_id: ObjectId) -> Result<AttachmentChunks, ApiErrors> {
+        let find_options = FindOptions::builder().sort(doc! { "n": 1 }).build();
+        let cursor = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<AttachmentChunk>("attachments.chunks")
+            .find(doc! { "files_id": attachment_id }, find_options)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(Box::pin(cursor.map_err(|err| ApiErrors::ServerError(err.to_string()))))
+    }
+
+    async fn get_used_attachment_bytes(&self, user_id: ObjectId) -> Result<i64, ApiErrors> {
+        let mut cursor = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Attachment>("attachments.files")
+            .aggregate(
+                [
+                    doc! { "$match": { "metadata.user_id": user_id } },
+                    doc! { "$group": { "_id": null, "total": { "$sum": "$metadata.size" } } },
+                ],
+                None,
+            )
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        let total = cursor
+            .try_next()
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .and_then(|total| match total.get("total") {
+                Some(Bson::Int32(total)) => Some(*total as i64),
+                Some(Bson::Int64(total)) => Some(*total),
+                _ => None,
+            });
+        Ok(total.unwrap_or(0))
+    }
+
+    async fn delete_attachment(&self, attachment_id: ObjectId) -> Result<(), ApiErrors> {
+        let database = self.get_client().database(&get_db_name());
+        database
+            .collection::<Attachment>("attachments.files")
+            .delete_one(doc! { "_id": attachment_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        database
+            .collection::<AttachmentChunk>("attachments.chunks")
+            .delete_many(doc! { "files_id": attachment_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
+    }
+
+    async fn insert_send(&self, send: SecretSend) -> Result<(), ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<SecretSend>("sends")
+            .insert_one(send, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_send(&self, send_id: &str) -> Result<SecretSend, ApiErrors> {
+        self.get_client()
+            .database(&get_db_name())
+            .collection::<SecretSend>("sends")
+            .find_one(doc! { "_id": send_id }, None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?
+            .ok_or_else(|| ApiErrors::NotFound("Send not found".to_string()))
+    }
+
+    async fn consume_send(&self, send_id: &str, now: DateTime) -> Result<Option<SecretSend>, ApiErrors> {
+        let collection = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<SecretSend>("sends");
+        // Returns the document from before the update, so two readers can never get the same view
+        let send = collection
+            .find_one_and_update(
+                doc! { "_id": send_id, "views_left": { "$gt": 0 }, "expires_at": { "$gt": now } },
+                doc! { "$inc": { "views_left": -1 } },
+                None,
+            )
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+
+        if matches!(&send, Some(send) if send.views_left <= 1) {
+            collection
+                .delete_one(doc! { "_id": send_id, "views_left": { "$lte": 0 } }, None)
+                .await
+                .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        }
+        Ok(send)
+    }
+
+    async fn delete_expired_sends(&self, now: DateTime) -> Result<u64, ApiErrors> {
+        let res = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<SecretSend>("sends")
+            .delete_many(
+                doc! { "$or": [{ "expires_at": { "$lte": now } }, { "views_left": { "$lte": 0 } }] },
+                None,
+            )
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(res.deleted_count)
+    }
+
+    async fn insert_audit_event(&self, event: AuditEvent) -> Result<ObjectId, ApiErrors> {
+        self.insert("audit_log", &event).await
+    }
+
+    async fn get_audit_events(&self, target_id: ObjectId) -> Result<Vec<AuditEvent>, ApiErrors> {
+        self.find_all("audit_log", doc! { "target_id": target_id }, doc! { "created_at": 1 }).await
+    }
+
+    async fn get_org_records(
+        &self,
+        org_id: ObjectId,
+        collection_id: Option<ObjectId>,
+    ) -> Result<Vec<Record>, ApiErrors> {
+        let mut filter = doc! { "org_id": org_id };
+        if let Some(collection_id) = collection_id {
+            filter.insert("collection_id", collection_id);
+        }
+        self.find_all("records", filter, doc! { "service": 1, "key": 1 }).await
     }
 
     async fn update_user_fields(
@@ -288,4 +1142,100 @@ impl TMongoClient for MongoClient {
         Ok(())
             
     }
+
+    async fn write_records_atomically(
+        &self,
+        writes: Vec<RecordWrite>,
+    ) -> Result<Vec<RecordWriteResult>, ApiErrors> {
+        let collection = self
+            .get_client()
+            .database(&get_db_name())
+            .collection::<Record>("records");
+        let mut session = self
+            .get_client()
+            .start_session(None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        session
+            .start_transaction(None)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+
+        let mut results = Vec::with_capacity(writes.len());
+        let mut deleted_record_ids = Vec::new();
+        for write in writes {
+            let result = match write {
+                RecordWrite::Insert(record) => collection
+                    .insert_one_with_session(record, None, &mut session)
+                    .await
+                    .map_err(|err| ApiErrors::ServerError(err.to_string()))
+                    .and_then(|res| {
+                        res.inserted_id
+                            .as_object_id()
+                            .map(RecordWriteResult::Inserted)
+                            .ok_or_else(|| ApiErrors::ServerError("Error converting object id".to_string()))
+                    }),
+                RecordWrite::Update { record_id, owner_id, updated_record, expected_revision } => {
+                    let options = FindOneAndUpdateOptions::builder()
+                        .return_document(ReturnDocument::After)
+                        .build();
+                    match collection
+                        .find_one_and_update_with_session(
+                            Self::record_filter(record_id, owner_id, expected_revision),
+                            Self::record_update_document(updated_record),
+                            options,
+                            &mut session,
+                        )
+                        .await
+                    {
+                        Ok(Some(record)) => Ok(RecordWriteResult::Updated(record.revision.unwrap_or(0))),
+                        Ok(None) => Err(self.missed_write_error(record_id, owner_id, expected_revision).await),
+                        Err(err) => Err(ApiErrors::ServerError(err.to_string())),
+                    }
+                }
+                RecordWrite::Delete { record_id, owner_id, expected_revision } => {
+                    match collection
+                        .find_one_and_delete_with_session(
+                            Self::record_filter(record_id, owner_id, expected_revision),
+                            None,
+                            &mut session,
+                        )
+                        .await
+                    {
+                        Ok(Some(_)) => {
+                            deleted_record_ids.push(record_id);
+                            self.delete_shares_of_record(record_id, Some(&mut session))
+                                .await
+                                .map(|_| RecordWriteResult::Deleted)
+                        }
+                        Ok(None) => Err(self.missed_write_error(record_id, owner_id, expected_revision).await),
+                        Err(err) => Err(ApiErrors::ServerError(err.to_string())),
+                    }
+                }
+            };
+
+            match result {
+                Ok(result) => results.push(result),
+                Err(err) => {
+                    session
+                        .abort_transaction()
+                        .await
+                        .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+                    return Err(err);
+                }
+            }
+        }
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+
+        // Attachments are too large for a transaction, they go once the records are gone
+        for record_id in deleted_record_ids {
+            self.delete_attachments_of_record(record_id).await?;
+        }
+
+        Ok(results)
+    }
 }
@@ -0,0 +1,51 @@
+use bson::{doc, Bson, Document};
+
+use crate::shared::pagination::{PageCursor, PageRequest, SortDirection};
+
+/**
+ * Filter of the records after the cursor of a page, None on the first page. Records without the
+ * sort field come first in ascending order and last in descending order, as MongoDB sorts them
+ */
+pub fn page_filter(page: &PageRequest) -> Option<Document> {
+    let (value, id) = match &page.cursor {
+        Some(PageCursor::After { value, id, .. }) => (value.clone(), *id),
+        Some(PageCursor::Offset { .. }) | None => return None,
+    };
+    let sort = match page.sort {
+        Some(sort) => sort,
+        None => return Some(doc! { "_id": { "$gt": id } }),
+    };
+    let path = sort.field.path();
+    Some(match (sort.direction, value) {
+        (SortDirection::Asc, Bson::Null) => doc! {
+            "$or": [{ path: { "$ne": null } }, { path: null, "_id": { "$gt": id } }]
+        },
+        (SortDirection::Asc, value) => doc! {
+            "$or": [{ path: { "$gt": value.clone() } }, { path: value, "_id": { "$gt": id } }]
+        },
+        (SortDirection::Desc, Bson::Null) => doc! { path: null, "_id": { "$lt": id } },
+        (SortDirection::Desc, value) => doc! {
+            "$or": [
+                { path: { "$lt": value.clone() } },
+                { path: value, "_id": { "$lt": id } },
+                { path: null },
+            ]
+        },
+    })
+}
+
+/**
+ * Order of the records of a page, by id after the sort field
+ */
+pub fn page_sort(page: &PageRequest) -> Document {
+    match page.sort {
+        Some(sort) => {
+            let direction = match sort.direction {
+                SortDirection::Asc => 1,
+                SortDirection::Desc => -1,
+            };
+            doc! { sort.field.path(): direction, "_id": direction }
+        }
+        None => doc! { "_id": 1 },
+    }
+}
@@ -0,0 +1,77 @@
+use bson::{doc, Bson, Document, Regex};
+
+use crate::modules::search_module::query::{DateRange, Expr, SearchField};
+
+/**
+ * Compile a search query to a filter on the records collection
+ */
+pub fn query_filter(expr: &Expr) -> Document {
+    match expr {
+        Expr::And(operands) => doc! { "$and": operands.iter().map(query_filter).collect::<Vec<Document>>() },
+        Expr::Or(operands) => doc! { "$or": operands.iter().map(query_filter).collect::<Vec<Document>>() },
+        Expr::Not(operand) => doc! { "$nor": [query_filter(operand)] },
+        // Text is escaped so it is looked for as it is
+        Expr::Text { field, value } => pattern_filter(*field, regex::escape(value)),
+        Expr::Pattern { field, pattern } => pattern_filter(*field, pattern.clone()),
+        // Fuzzy text does not narrow the filter, the records found are ranked afterwards
+        Expr::Fuzzy { .. } => Document::new(),
+        Expr::Date { field, range } => doc! { field.path(): range_filter(range) },
+    }
+}
+
+/**
+ * A pattern has to match one of its fields, ie { $or: [{ service: { $regex: /n/i } }, { key: { $regex: /n/i } }] }
+ */
+fn pattern_filter(field: Option<SearchField>, pattern: String) -> Document {
+    let reg = Regex { pattern, options: "i".to_string() };
+    let fields: Vec<Document> = Expr::text_fields(field)
+        .iter()
+        .map(|field| doc! { field.path(): { "$regex": reg.clone() } })
+        .collect();
+    doc! { "$or": fields }
+}
+
+fn range_filter(range: &DateRange) -> Document {
+    let mut filter = Document::new();
+    if let Some(start) = range.start {
+        filter.insert(if start.inclusive { "$gte" } else { "$gt" }, Bson::DateTime(start.at));
+    }
+    if let Some(end) = range.end {
+        filter.insert(if end.inclusive { "$lte" } else { "$lt" }, Bson::DateTime(end.at));
+    }
+    filter
+}
+
+/**
+ * Atlas Search clauses of which a record has to match one to match the query, None when the query
+ * can match records without fuzzy text
+ */
+fn fuzzy_clauses(expr: &Expr) -> Option<Vec<Document>> {
+    match expr {
+        Expr::And(operands) => operands.iter().find_map(fuzzy_clauses),
+        Expr::Or(operands) => operands
+            .iter()
+            .map(fuzzy_clauses)
+            .collect::<Option<Vec<Vec<Document>>>>()
+            .map(|clauses| clauses.concat()),
+        Expr::Fuzzy { field, value } => {
+            let paths: Vec<&str> = Expr::text_fields(*field).iter().map(|field| field.path()).collect();
+            Some(vec![doc! { "text": { "query": value, "path": paths, "fuzzy": { "maxEdits": 2 } } }])
+        }
+        Expr::Not(_) | Expr::Text { .. } | Expr::Pattern { .. } | Expr::Date { .. } => None,
+    }
+}
+
+/**
+ * A `$search` stage on an Atlas Search index that narrows a fuzzy search down to the records
+ * close to its terms, so large vaults are not ranked in full
+ */
+pub fn fuzzy_search_stage(expr: &Expr, index: &str) -> Option<Document> {
+    let clauses = fuzzy_clauses(expr)?;
+    Some(doc! {
+        "$search": {
+            "index": index,
+            "compound": { "should": clauses, "minimumShouldMatch": 1 },
+        }
+    })
+}
@@ -0,0 +1,107 @@
+pub mod notifier_trait;
+
+use hmac::{Hmac, Mac};
+use rocket::serde::json::serde_json;
+use sha2::Sha256;
+
+use crate::{
+    drivers::notifier::notifier_trait::Notifier,
+    shared::{
+        env_config::{get_notifier, get_webhook_secret, get_webhook_url},
+        types::{ApiErrors, Notification},
+    },
+};
+
+/// Header holding the hex HMAC-SHA256 of the body, keyed with the webhook secret
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/**
+ * Pick the notifier set by the `NOTIFIER` env, defaults to logging notifications
+ */
+pub fn notifier_from_env() -> Box<dyn Notifier> {
+    notifier_for(&get_notifier(), get_webhook_url(), get_webhook_secret())
+}
+
+/**
+ * A webhook notifier without a URL falls back to logging, so a missing env does not stop the api
+ */
+pub fn notifier_for(kind: &str, webhook_url: Option<String>, webhook_secret: Option<String>) -> Box<dyn Notifier> {
+    match (kind, webhook_url) {
+        ("webhook", Some(url)) => Box::new(WebhookNotifier::new(url, webhook_secret)),
+        ("webhook", None) => {
+            println!("NOTIFIER is webhook but WEBHOOK_URL is not set, notifications will only be logged");
+            Box::new(LogNotifier)
+        }
+        _ => Box::new(LogNotifier),
+    }
+}
+
+/**
+ * Prints notifications, useful when no delivery is set up
+ */
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), ApiErrors> {
+        println!(
+            "Notification {:?} for user {}: {}",
+            notification.event, notification.user_id, notification.subject
+        );
+        Ok(())
+    }
+}
+
+/**
+ * Posts notifications as JSON to a URL, signed with a shared secret when one is set
+ */
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String, secret: Option<String>) -> WebhookNotifier {
+        WebhookNotifier {
+            client: reqwest::Client::new(),
+            url,
+            secret,
+        }
+    }
+}
+
+/**
+ * Hex HMAC-SHA256 of a webhook body
+ */
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), ApiErrors> {
+        let body = serde_json::to_vec(notification).map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+        }
+
+        request
+            .body(body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|err| ApiErrors::ServerError(format!("Webhook delivery failed: {}", err)))?;
+        Ok(())
+    }
+}
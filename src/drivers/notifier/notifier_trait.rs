@@ -0,0 +1,13 @@
+use crate::shared::types::{ApiErrors, Notification};
+
+#[cfg(test)]
+use mockall::automock;
+
+/**
+ * Delivers notifications to users, ie by email or to a webhook
+ */
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), ApiErrors>;
+}
@@ -0,0 +1,250 @@
+/*
+    Copy of a MongoDB database into the SQL storage, to move an existing deployment over. Every
+    collection is read as the MongoDB backend stores it and written in a single transaction, so a
+    copy that fails leaves the SQL database as empty as it was
+*/
+use rocket::futures::TryStreamExt;
+use serde::de::DeserializeOwned;
+use sqlx::AnyConnection;
+
+use super::{
+    db_error, get_int, insert_attachment_row, insert_audit_event_row, insert_chunk_row,
+    insert_collection_row, insert_emergency_access_row, insert_invitation_row, insert_member_row,
+    insert_organization_row, insert_record_row, insert_saved_search_row, insert_send_row,
+    insert_share_row, insert_user_row, SqlStorage,
+};
+use crate::{
+    drivers::mongodb::MongoClient,
+    shared::{
+        env_config::get_db_name,
+        types::{
+            ApiErrors, Attachment, AttachmentChunk, AuditEvent, EmergencyAccess, OrgCollection,
+            OrgInvitation, OrgMember, Organization, Record, RecordShare, SavedSearch, SecretSend,
+            User,
+        },
+    },
+};
+
+/**
+ * A document of a MongoDB collection and the row it is copied to
+ */
+#[async_trait]
+trait CopyRow: DeserializeOwned + Unpin + Send + Sync {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors>;
+}
+
+#[async_trait]
+impl CopyRow for User {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_user_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for Record {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_record_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for RecordShare {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_share_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for Organization {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_organization_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for OrgMember {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_member_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for OrgInvitation {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_invitation_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for OrgCollection {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_collection_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for EmergencyAccess {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_emergency_access_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for Attachment {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_attachment_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for AttachmentChunk {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_chunk_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for SecretSend {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_send_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for AuditEvent {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_audit_event_row(conn, self).await
+    }
+}
+
+#[async_trait]
+impl CopyRow for SavedSearch {
+    async fn insert(&self, conn: &mut AnyConnection) -> Result<(), ApiErrors> {
+        insert_saved_search_row(conn, self).await
+    }
+}
+
+/**
+ * Copy every document of a collection, returns how many were copied
+ */
+async fn copy_collection<T: CopyRow>(
+    mongo: &MongoClient,
+    conn: &mut AnyConnection,
+    collection: &str,
+) -> Result<u64, ApiErrors> {
+    let mongo_error =
+        |err: mongodb::error::Error| ApiErrors::ServerError(format!("{}: {}", collection, err));
+    let mut documents = mongo
+        .get_client()
+        .database(&get_db_name())
+        .collection::<T>(collection)
+        .find(None, None)
+        .await
+        .map_err(mongo_error)?;
+    let mut copied = 0;
+    while let Some(document) = documents.try_next().await.map_err(mongo_error)? {
+        document.insert(conn).await?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+/// Tables of the SQL storage, they all have to be empty before a copy
+const TABLES: [&str; 13] = [
+    "users",
+    "records",
+    "record_shares",
+    "organizations",
+    "org_members",
+    "org_invitations",
+    "org_collections",
+    "emergency_access",
+    "attachments",
+    "attachment_chunks",
+    "sends",
+    "audit_log",
+    "saved_searches",
+];
+
+/**
+ * Copy every collection of the MongoDB database into an empty SQL database, returns how many
+ * documents each collection had
+ */
+pub async fn copy_from_mongodb(
+    mongo: &MongoClient,
+    sql: &SqlStorage,
+) -> Result<Vec<(&'static str, u64)>, ApiErrors> {
+    let mut tx = sql.pool.begin().await.map_err(db_error)?;
+    for table in TABLES {
+        let row = sqlx::query(&format!(
+            "SELECT CAST(COUNT(*) AS TEXT) AS count FROM {}",
+            table
+        ))
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        if get_int(&row, "count")? > 0 {
+            return Err(ApiErrors::BadRequest(format!(
+                "The {} table is not empty",
+                table
+            )));
+        }
+    }
+
+    let copied = vec![
+        (
+            "users",
+            copy_collection::<User>(mongo, &mut tx, "users").await?,
+        ),
+        (
+            "records",
+            copy_collection::<Record>(mongo, &mut tx, "records").await?,
+        ),
+        (
+            "record_shares",
+            copy_collection::<RecordShare>(mongo, &mut tx, "record_shares").await?,
+        ),
+        (
+            "organizations",
+            copy_collection::<Organization>(mongo, &mut tx, "organizations").await?,
+        ),
+        (
+            "org_members",
+            copy_collection::<OrgMember>(mongo, &mut tx, "org_members").await?,
+        ),
+        (
+            "org_invitations",
+            copy_collection::<OrgInvitation>(mongo, &mut tx, "org_invitations").await?,
+        ),
+        (
+            "org_collections",
+            copy_collection::<OrgCollection>(mongo, &mut tx, "org_collections").await?,
+        ),
+        (
+            "emergency_access",
+            copy_collection::<EmergencyAccess>(mongo, &mut tx, "emergency_access").await?,
+        ),
+        (
+            "attachments.files",
+            copy_collection::<Attachment>(mongo, &mut tx, "attachments.files").await?,
+        ),
+        (
+            "attachments.chunks",
+            copy_collection::<AttachmentChunk>(mongo, &mut tx, "attachments.chunks").await?,
+        ),
+        (
+            "sends",
+            copy_collection::<SecretSend>(mongo, &mut tx, "sends").await?,
+        ),
+        (
+            "audit_log",
+            copy_collection::<AuditEvent>(mongo, &mut tx, "audit_log").await?,
+        ),
+        (
+            "saved_searches",
+            copy_collection::<SavedSearch>(mongo, &mut tx, "saved_searches").await?,
+        ),
+    ];
+    tx.commit().await.map_err(db_error)?;
+    Ok(copied)
+}
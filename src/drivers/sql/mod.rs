@@ -0,0 +1,2152 @@
+/*
+    Storage on a SQL database through sqlx, SQLite for a single node or an embedded setup and
+    PostgreSQL for production. It follows the semantics of the MongoDB backend: the same sort
+    orders, not found errors and revision checks. Deletes cascade here rather than in the schema,
+    as they do on MongoDB. The schema is in `migrations/` and brought up to date on connect
+*/
+pub mod copy;
+mod page_clause;
+
+use bson::{oid::ObjectId, spec::BinarySubtype, Binary, DateTime};
+use rocket::{
+    futures::{stream, TryStreamExt},
+    serde::json::serde_json::{self, Value},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{
+    any::{AnyArguments, AnyPoolOptions, AnyRow},
+    migrate::Migrator,
+    Any, AnyConnection, AnyPool, Arguments, Decode, Encode, Row, Type, TypeInfo, ValueRef,
+};
+
+use crate::{
+    drivers::storage::storage_trait::{AttachmentChunks, RecordStream, Storage},
+    modules::search_module::{relevance::relevance, SearchParams},
+    shared::{
+        pagination::PageRequest,
+        types::{
+            ApiErrors, Attachment, AttachmentChunk, AttachmentMetadata, AuditEvent, AuthUser,
+            BreachStatus, EmergencyAccess, EmergencyStatus, OrgCollection, OrgInvitation,
+            OrgMember, OrgRole, Organization, Record, RecordShare, RecordWrite, RecordWriteResult,
+            SavedSearch, SecretSend, UpdateRecord, UpdateUser, User,
+        },
+    },
+};
+
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Records read at once when they are streamed
+const BATCH_SIZE: i64 = 100;
+
+/*
+    Columns of each table as they are read. The Any driver of sqlx 0.7.1 reads every SQLite
+    integer as 32 bits, so integer columns are read as text and parsed, which keeps dates in
+    milliseconds and sizes whole on both databases
+*/
+const RECORD_COLUMNS: &str = "id, record_type, user_id, key, secret, service, password, email, username, \
+    CAST(two_factor AS TEXT) AS two_factor, CAST(revision AS TEXT) AS revision, \
+    CAST(created_at AS TEXT) AS created_at, CAST(updated_at AS TEXT) AS updated_at, \
+    CAST(password_changed_at AS TEXT) AS password_changed_at, \
+    CAST(breach_password_count AS TEXT) AS breach_password_count, \
+    CAST(breach_email_count AS TEXT) AS breach_email_count, CAST(breach_checked_at AS TEXT) AS breach_checked_at, \
+    CAST(rotation_period AS TEXT) AS rotation_period, CAST(rotation_reminded_at AS TEXT) AS rotation_reminded_at, \
+    uris, tags, org_id, collection_id";
+const SHARE_COLUMNS: &str =
+    "id, record_id, owner_id, owner_username, grantee_id, grantee_username, permission, \
+    CAST(created_at AS TEXT) AS created_at";
+const ORGANIZATION_COLUMNS: &str = "id, name, created_by, CAST(created_at AS TEXT) AS created_at";
+const MEMBER_COLUMNS: &str =
+    "id, org_id, org_name, user_id, username, role, CAST(joined_at AS TEXT) AS joined_at";
+const INVITATION_COLUMNS: &str =
+    "id, org_id, org_name, user_id, username, role, invited_by, CAST(created_at AS TEXT) AS created_at";
+const COLLECTION_COLUMNS: &str = "id, org_id, name, CAST(created_at AS TEXT) AS created_at";
+const EMERGENCY_ACCESS_COLUMNS: &str =
+    "id, grantor_id, grantor_username, grantee_id, grantee_username, access, \
+    CAST(wait_days AS TEXT) AS wait_days, status, CAST(requested_at AS TEXT) AS requested_at, \
+    CAST(approved_at AS TEXT) AS approved_at, CAST(created_at AS TEXT) AS created_at";
+const ATTACHMENT_COLUMNS: &str =
+    "id, record_id, user_id, uploaded_by, content_type, CAST(size AS TEXT) AS size, \
+    key, filename, CAST(length AS TEXT) AS length, CAST(chunk_size AS TEXT) AS chunk_size, \
+    CAST(upload_date AS TEXT) AS upload_date";
+const CHUNK_COLUMNS: &str = "id, files_id, CAST(n AS TEXT) AS n, data";
+const SEND_COLUMNS: &str = "id, user_id, text, file_name, file_content, password, \
+    CAST(max_views AS TEXT) AS max_views, CAST(views_left AS TEXT) AS views_left, \
+    CAST(expires_at AS TEXT) AS expires_at, CAST(created_at AS TEXT) AS created_at";
+const AUDIT_EVENT_COLUMNS: &str =
+    "id, action, owner_id, actor_id, target_id, CAST(created_at AS TEXT) AS created_at";
+const SAVED_SEARCH_COLUMNS: &str = "id, user_id, name, query, mode, types, sort, \
+    CAST(created_at AS TEXT) AS created_at, CAST(updated_at AS TEXT) AS updated_at";
+
+/**
+ * Arguments of a query built at runtime, every value gets the next numbered placeholder
+ */
+#[derive(Default)]
+struct Binds<'q> {
+    args: AnyArguments<'q>,
+    count: usize,
+}
+
+impl<'q> Binds<'q> {
+    /**
+     * Bind a value, returns its placeholder
+     */
+    fn push<T: 'q + Send + Encode<'q, Any> + Type<Any>>(&mut self, value: T) -> String {
+        self.args.add(value);
+        self.count += 1;
+        format!("${}", self.count)
+    }
+
+    /**
+     * Bind a value that can be missing, returns its placeholder or `NULL`. The Any driver binds a
+     * null without its type, so a null is written in the query instead: a prepared statement is
+     * cached by its text and has to get values of the same type every time
+     */
+    fn push_optional<T: 'q + Send + Encode<'q, Any> + Type<Any>>(
+        &mut self,
+        value: Option<T>,
+    ) -> String {
+        match value {
+            Some(value) => self.push(value),
+            None => "NULL".to_string(),
+        }
+    }
+
+    /**
+     * Bind a list of ids, returns their placeholders for an `IN`
+     */
+    fn push_ids(&mut self, ids: &[ObjectId]) -> String {
+        ids.iter()
+            .map(|id| self.push(id.to_hex()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn db_error(err: sqlx::Error) -> ApiErrors {
+    ApiErrors::ServerError(err.to_string())
+}
+
+fn not_found(message: &str) -> ApiErrors {
+    ApiErrors::NotFound(message.to_string())
+}
+
+fn required_id(id: Option<ObjectId>) -> Result<String, ApiErrors> {
+    id.map(|id| id.to_hex())
+        .ok_or_else(|| ApiErrors::ServerError("Object id was not found".to_string()))
+}
+
+fn optional_id(id: Option<ObjectId>) -> Option<String> {
+    id.map(|id| id.to_hex())
+}
+
+fn optional_millis(date: Option<DateTime>) -> Option<i64> {
+    date.map(|date| date.timestamp_millis())
+}
+
+/**
+ * Name an enum is stored by, the same as in MongoDB
+ */
+fn enum_text<T: Serialize>(value: &T) -> Result<String, ApiErrors> {
+    match serde_json::to_value(value).map_err(|err| ApiErrors::ServerError(err.to_string()))? {
+        Value::String(text) => Ok(text),
+        _ => Err(ApiErrors::ServerError("Value is not an enum".to_string())),
+    }
+}
+
+fn json_text<T: Serialize>(value: &T) -> Result<String, ApiErrors> {
+    serde_json::to_string(value).map_err(|err| ApiErrors::ServerError(err.to_string()))
+}
+
+fn optional_json_text<T: Serialize>(value: &Option<T>) -> Result<Option<String>, ApiErrors> {
+    value.as_ref().map(json_text).transpose()
+}
+
+fn get<'r, T: Decode<'r, Any> + Type<Any>>(row: &'r AnyRow, column: &str) -> Result<T, ApiErrors> {
+    row.try_get(column).map_err(db_error)
+}
+
+/**
+ * A nullable column. The Any driver of sqlx 0.7.1 does not report nulls to `Option`, they are told
+ * apart by their type instead
+ */
+fn get_optional<'r, T: Decode<'r, Any> + Type<Any>>(
+    row: &'r AnyRow,
+    column: &str,
+) -> Result<Option<T>, ApiErrors> {
+    let value = row.try_get_raw(column).map_err(db_error)?;
+    if value.type_info().name() == "NULL" {
+        return Ok(None);
+    }
+    get(row, column).map(Some)
+}
+
+/**
+ * An integer column, read as text
+ */
+fn get_optional_int(row: &AnyRow, column: &str) -> Result<Option<i64>, ApiErrors> {
+    get_optional::<String>(row, column)?
+        .map(|text| {
+            text.parse()
+                .map_err(|_| ApiErrors::ServerError(format!("{} is not an integer", column)))
+        })
+        .transpose()
+}
+
+fn get_int(row: &AnyRow, column: &str) -> Result<i64, ApiErrors> {
+    get_optional_int(row, column)?
+        .ok_or_else(|| ApiErrors::ServerError(format!("{} is null", column)))
+}
+
+fn get_optional_id(row: &AnyRow, column: &str) -> Result<Option<ObjectId>, ApiErrors> {
+    get_optional::<String>(row, column)?
+        .map(|hex| ObjectId::parse_str(hex).map_err(|err| ApiErrors::ServerError(err.to_string())))
+        .transpose()
+}
+
+fn get_id(row: &AnyRow, column: &str) -> Result<ObjectId, ApiErrors> {
+    get_optional_id(row, column)?
+        .ok_or_else(|| ApiErrors::ServerError(format!("{} is null", column)))
+}
+
+fn get_optional_date(row: &AnyRow, column: &str) -> Result<Option<DateTime>, ApiErrors> {
+    Ok(get_optional_int(row, column)?.map(DateTime::from_millis))
+}
+
+fn get_date(row: &AnyRow, column: &str) -> Result<DateTime, ApiErrors> {
+    Ok(DateTime::from_millis(get_int(row, column)?))
+}
+
+fn get_enum<T: DeserializeOwned>(row: &AnyRow, column: &str) -> Result<T, ApiErrors> {
+    serde_json::from_value(Value::String(get(row, column)?))
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))
+}
+
+fn get_json<T: DeserializeOwned>(row: &AnyRow, column: &str) -> Result<Option<T>, ApiErrors> {
+    get_optional::<String>(row, column)?
+        .map(|text| {
+            serde_json::from_str(&text).map_err(|err| ApiErrors::ServerError(err.to_string()))
+        })
+        .transpose()
+}
+
+fn user_of(row: &AnyRow) -> Result<User, ApiErrors> {
+    Ok(User {
+        id: get_optional_id(row, "id")?,
+        name: get(row, "name")?,
+        email: get(row, "email")?,
+        username: get(row, "username")?,
+        password: get(row, "password")?,
+    })
+}
+
+fn auth_user_of(row: &AnyRow) -> Result<AuthUser, ApiErrors> {
+    Ok(AuthUser {
+        id: get(row, "id")?,
+        name: get(row, "name")?,
+        email: get(row, "email")?,
+        username: get(row, "username")?,
+    })
+}
+
+fn record_of(row: &AnyRow) -> Result<Record, ApiErrors> {
+    let breach = match get_optional_date(row, "breach_checked_at")? {
+        Some(checked_at) => Some(BreachStatus {
+            password_count: get_optional_int(row, "breach_password_count")?.unwrap_or(0),
+            email_count: get_optional_int(row, "breach_email_count")?.unwrap_or(0),
+            checked_at,
+        }),
+        None => None,
+    };
+    Ok(Record {
+        record_type: get_enum(row, "record_type")?,
+        id: get_optional_id(row, "id")?,
+        user_id: get_optional_id(row, "user_id")?,
+        key: get_optional(row, "key")?,
+        secret: get_optional(row, "secret")?,
+        service: get_optional(row, "service")?,
+        password: get_optional(row, "password")?,
+        email: get_optional(row, "email")?,
+        username: get_optional(row, "username")?,
+        two_factor: get_optional_int(row, "two_factor")?.map(|two_factor| two_factor != 0),
+        revision: get_optional_int(row, "revision")?,
+        created_at: get_optional_date(row, "created_at")?,
+        updated_at: get_optional_date(row, "updated_at")?,
+        password_changed_at: get_optional_date(row, "password_changed_at")?,
+        breach,
+        rotation_period: get_optional_int(row, "rotation_period")?,
+        rotation_reminded_at: get_optional_date(row, "rotation_reminded_at")?,
+        uris: get_json(row, "uris")?,
+        tags: get_json(row, "tags")?,
+        org_id: get_optional_id(row, "org_id")?,
+        collection_id: get_optional_id(row, "collection_id")?,
+    })
+}
+
+fn share_of(row: &AnyRow) -> Result<RecordShare, ApiErrors> {
+    Ok(RecordShare {
+        id: get_optional_id(row, "id")?,
+        record_id: get_id(row, "record_id")?,
+        owner_id: get_id(row, "owner_id")?,
+        owner_username: get(row, "owner_username")?,
+        grantee_id: get_id(row, "grantee_id")?,
+        grantee_username: get(row, "grantee_username")?,
+        permission: get_enum(row, "permission")?,
+        created_at: get_date(row, "created_at")?,
+    })
+}
+
+fn organization_of(row: &AnyRow) -> Result<Organization, ApiErrors> {
+    Ok(Organization {
+        id: get_optional_id(row, "id")?,
+        name: get(row, "name")?,
+        created_by: get_id(row, "created_by")?,
+        created_at: get_date(row, "created_at")?,
+    })
+}
+
+fn member_of(row: &AnyRow) -> Result<OrgMember, ApiErrors> {
+    Ok(OrgMember {
+        id: get_optional_id(row, "id")?,
+        org_id: get_id(row, "org_id")?,
+        org_name: get(row, "org_name")?,
+        user_id: get_id(row, "user_id")?,
+        username: get(row, "username")?,
+        role: get_enum(row, "role")?,
+        joined_at: get_date(row, "joined_at")?,
+    })
+}
+
+fn invitation_of(row: &AnyRow) -> Result<OrgInvitation, ApiErrors> {
+    Ok(OrgInvitation {
+        id: get_optional_id(row, "id")?,
+        org_id: get_id(row, "org_id")?,
+        org_name: get(row, "org_name")?,
+        user_id: get_id(row, "user_id")?,
+        username: get(row, "username")?,
+        role: get_enum(row, "role")?,
+        invited_by: get(row, "invited_by")?,
+        created_at: get_date(row, "created_at")?,
+    })
+}
+
+fn collection_of(row: &AnyRow) -> Result<OrgCollection, ApiErrors> {
+    Ok(OrgCollection {
+        id: get_optional_id(row, "id")?,
+        org_id: get_id(row, "org_id")?,
+        name: get(row, "name")?,
+        created_at: get_date(row, "created_at")?,
+    })
+}
+
+fn emergency_access_of(row: &AnyRow) -> Result<EmergencyAccess, ApiErrors> {
+    Ok(EmergencyAccess {
+        id: get_optional_id(row, "id")?,
+        grantor_id: get_id(row, "grantor_id")?,
+        grantor_username: get(row, "grantor_username")?,
+        grantee_id: get_id(row, "grantee_id")?,
+        grantee_username: get(row, "grantee_username")?,
+        access: get_enum(row, "access")?,
+        wait_days: get_int(row, "wait_days")?,
+        status: get_enum(row, "status")?,
+        requested_at: get_optional_date(row, "requested_at")?,
+        approved_at: get_optional_date(row, "approved_at")?,
+        created_at: get_date(row, "created_at")?,
+    })
+}
+
+fn attachment_of(row: &AnyRow) -> Result<Attachment, ApiErrors> {
+    Ok(Attachment {
+        id: get_id(row, "id")?,
+        length: get_int(row, "length")?,
+        chunk_size: get_int(row, "chunk_size")? as i32,
+        upload_date: get_date(row, "upload_date")?,
+        filename: get(row, "filename")?,
+        metadata: AttachmentMetadata {
+            record_id: get_id(row, "record_id")?,
+            user_id: get_id(row, "user_id")?,
+            uploaded_by: get_id(row, "uploaded_by")?,
+            content_type: get(row, "content_type")?,
+            size: get_int(row, "size")?,
+            key: get(row, "key")?,
+        },
+    })
+}
+
+fn chunk_of(row: &AnyRow) -> Result<AttachmentChunk, ApiErrors> {
+    Ok(AttachmentChunk {
+        id: get_optional_id(row, "id")?,
+        files_id: get_id(row, "files_id")?,
+        n: get_int(row, "n")? as i32,
+        data: Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: get(row, "data")?,
+        },
+    })
+}
+
+fn send_of(row: &AnyRow) -> Result<SecretSend, ApiErrors> {
+    Ok(SecretSend {
+        id: get(row, "id")?,
+        user_id: get_id(row, "user_id")?,
+        text: get_optional(row, "text")?,
+        file_name: get_optional(row, "file_name")?,
+        file_content: get_optional(row, "file_content")?,
+        password: get_optional(row, "password")?,
+        max_views: get_int(row, "max_views")?,
+        views_left: get_int(row, "views_left")?,
+        expires_at: get_date(row, "expires_at")?,
+        created_at: get_date(row, "created_at")?,
+    })
+}
+
+fn audit_event_of(row: &AnyRow) -> Result<AuditEvent, ApiErrors> {
+    Ok(AuditEvent {
+        id: get_optional_id(row, "id")?,
+        action: get_enum(row, "action")?,
+        owner_id: get_id(row, "owner_id")?,
+        actor_id: get_optional_id(row, "actor_id")?,
+        target_id: get_optional_id(row, "target_id")?,
+        created_at: get_date(row, "created_at")?,
+    })
+}
+
+fn saved_search_of(row: &AnyRow) -> Result<SavedSearch, ApiErrors> {
+    Ok(SavedSearch {
+        id: get_optional_id(row, "id")?,
+        user_id: get_id(row, "user_id")?,
+        name: get(row, "name")?,
+        query: get_optional(row, "query")?,
+        mode: get_optional(row, "mode")?,
+        types: get_json(row, "types")?.unwrap_or_default(),
+        sort: get_optional(row, "sort")?,
+        created_at: get_date(row, "created_at")?,
+        updated_at: get_date(row, "updated_at")?,
+    })
+}
+
+fn rows_of<T>(
+    rows: Vec<AnyRow>,
+    of: impl Fn(&AnyRow) -> Result<T, ApiErrors>,
+) -> Result<Vec<T>, ApiErrors> {
+    rows.iter().map(of).collect()
+}
+
+/*
+    Row writers, shared by the storage and the copy from MongoDB. Ids have to be set
+*/
+
+async fn insert_user_row(conn: &mut AnyConnection, user: &User) -> Result<(), ApiErrors> {
+    sqlx::query(
+        "INSERT INTO users (id, name, email, username, password) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(required_id(user.id)?)
+    .bind(user.name.clone())
+    .bind(user.email.clone())
+    .bind(user.username.clone())
+    .bind(user.password.clone())
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_record_row(conn: &mut AnyConnection, record: &Record) -> Result<(), ApiErrors> {
+    let breach = record.breach.as_ref();
+    let mut binds = Binds::default();
+    let values = [
+        binds.push(required_id(record.id)?),
+        binds.push(enum_text(&record.record_type)?),
+        binds.push_optional(optional_id(record.user_id)),
+        binds.push_optional(record.key.clone()),
+        binds.push_optional(record.secret.clone()),
+        binds.push_optional(record.service.clone()),
+        binds.push_optional(record.password.clone()),
+        binds.push_optional(record.email.clone()),
+        binds.push_optional(record.username.clone()),
+        binds.push_optional(record.two_factor.map(i64::from)),
+        binds.push_optional(record.revision),
+        binds.push_optional(optional_millis(record.created_at)),
+        binds.push_optional(optional_millis(record.updated_at)),
+        binds.push_optional(optional_millis(record.password_changed_at)),
+        binds.push_optional(breach.map(|breach| breach.password_count)),
+        binds.push_optional(breach.map(|breach| breach.email_count)),
+        binds.push_optional(breach.map(|breach| breach.checked_at.timestamp_millis())),
+        binds.push_optional(record.rotation_period),
+        binds.push_optional(optional_millis(record.rotation_reminded_at)),
+        binds.push_optional(optional_json_text(&record.uris)?),
+        binds.push_optional(optional_json_text(&record.tags)?),
+        binds.push_optional(optional_id(record.org_id)),
+        binds.push_optional(optional_id(record.collection_id)),
+    ]
+    .join(", ");
+    let sql = format!(
+        "INSERT INTO records (id, record_type, user_id, key, secret, service, password, email, username, two_factor, \
+         revision, created_at, updated_at, password_changed_at, breach_password_count, breach_email_count, \
+         breach_checked_at, rotation_period, rotation_reminded_at, uris, tags, org_id, collection_id) \
+         VALUES ({})",
+        values
+    );
+    sqlx::query_with(&sql, binds.args)
+        .execute(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_share_row(conn: &mut AnyConnection, share: &RecordShare) -> Result<(), ApiErrors> {
+    sqlx::query(
+        "INSERT INTO record_shares (id, record_id, owner_id, owner_username, grantee_id, grantee_username, permission, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(required_id(share.id)?)
+    .bind(share.record_id.to_hex())
+    .bind(share.owner_id.to_hex())
+    .bind(share.owner_username.clone())
+    .bind(share.grantee_id.to_hex())
+    .bind(share.grantee_username.clone())
+    .bind(enum_text(&share.permission)?)
+    .bind(share.created_at.timestamp_millis())
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_organization_row(
+    conn: &mut AnyConnection,
+    org: &Organization,
+) -> Result<(), ApiErrors> {
+    sqlx::query(
+        "INSERT INTO organizations (id, name, created_by, created_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(required_id(org.id)?)
+    .bind(org.name.clone())
+    .bind(org.created_by.to_hex())
+    .bind(org.created_at.timestamp_millis())
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_member_row(conn: &mut AnyConnection, member: &OrgMember) -> Result<(), ApiErrors> {
+    sqlx::query(
+        "INSERT INTO org_members (id, org_id, org_name, user_id, username, role, joined_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(required_id(member.id)?)
+    .bind(member.org_id.to_hex())
+    .bind(member.org_name.clone())
+    .bind(member.user_id.to_hex())
+    .bind(member.username.clone())
+    .bind(enum_text(&member.role)?)
+    .bind(member.joined_at.timestamp_millis())
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_invitation_row(
+    conn: &mut AnyConnection,
+    invitation: &OrgInvitation,
+) -> Result<(), ApiErrors> {
+    sqlx::query(
+        "INSERT INTO org_invitations (id, org_id, org_name, user_id, username, role, invited_by, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(required_id(invitation.id)?)
+    .bind(invitation.org_id.to_hex())
+    .bind(invitation.org_name.clone())
+    .bind(invitation.user_id.to_hex())
+    .bind(invitation.username.clone())
+    .bind(enum_text(&invitation.role)?)
+    .bind(invitation.invited_by.clone())
+    .bind(invitation.created_at.timestamp_millis())
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_collection_row(
+    conn: &mut AnyConnection,
+    collection: &OrgCollection,
+) -> Result<(), ApiErrors> {
+    sqlx::query(
+        "INSERT INTO org_collections (id, org_id, name, created_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(required_id(collection.id)?)
+    .bind(collection.org_id.to_hex())
+    .bind(collection.name.clone())
+    .bind(collection.created_at.timestamp_millis())
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_emergency_access_row(
+    conn: &mut AnyConnection,
+    access: &EmergencyAccess,
+) -> Result<(), ApiErrors> {
+    let mut binds = Binds::default();
+    let values = [
+        binds.push(required_id(access.id)?),
+        binds.push(access.grantor_id.to_hex()),
+        binds.push(access.grantor_username.clone()),
+        binds.push(access.grantee_id.to_hex()),
+        binds.push(access.grantee_username.clone()),
+        binds.push(enum_text(&access.access)?),
+        binds.push(access.wait_days),
+        binds.push(enum_text(&access.status)?),
+        binds.push_optional(optional_millis(access.requested_at)),
+        binds.push_optional(optional_millis(access.approved_at)),
+        binds.push(access.created_at.timestamp_millis()),
+    ]
+    .join(", ");
+    let sql = format!(
+        "INSERT INTO emergency_access (id, grantor_id, grantor_username, grantee_id, grantee_username, access, \
+         wait_days, status, requested_at, approved_at, created_at) \
+         VALUES ({})",
+        values
+    );
+    sqlx::query_with(&sql, binds.args)
+        .execute(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_attachment_row(
+    conn: &mut AnyConnection,
+    attachment: &Attachment,
+) -> Result<(), ApiErrors> {
+    sqlx::query(
+        "INSERT INTO attachments (id, record_id, user_id, uploaded_by, content_type, size, key, filename, length, \
+         chunk_size, upload_date) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+    )
+    .bind(attachment.id.to_hex())
+    .bind(attachment.metadata.record_id.to_hex())
+    .bind(attachment.metadata.user_id.to_hex())
+    .bind(attachment.metadata.uploaded_by.to_hex())
+    .bind(attachment.metadata.content_type.clone())
+    .bind(attachment.metadata.size)
+    .bind(attachment.metadata.key.clone())
+    .bind(attachment.filename.clone())
+    .bind(attachment.length)
+    .bind(i64::from(attachment.chunk_size))
+    .bind(attachment.upload_date.timestamp_millis())
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_chunk_row(
+    conn: &mut AnyConnection,
+    chunk: &AttachmentChunk,
+) -> Result<(), ApiErrors> {
+    sqlx::query("INSERT INTO attachment_chunks (id, files_id, n, data) VALUES ($1, $2, $3, $4)")
+        .bind(required_id(chunk.id)?)
+        .bind(chunk.files_id.to_hex())
+        .bind(i64::from(chunk.n))
+        .bind(chunk.data.bytes.clone())
+        .execute(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_send_row(conn: &mut AnyConnection, send: &SecretSend) -> Result<(), ApiErrors> {
+    let mut binds = Binds::default();
+    let values = [
+        binds.push(send.id.clone()),
+        binds.push(send.user_id.to_hex()),
+        binds.push_optional(send.text.clone()),
+        binds.push_optional(send.file_name.clone()),
+        binds.push_optional(send.file_content.clone()),
+        binds.push_optional(send.password.clone()),
+        binds.push(send.max_views),
+        binds.push(send.views_left),
+        binds.push(send.expires_at.timestamp_millis()),
+        binds.push(send.created_at.timestamp_millis()),
+    ]
+    .join(", ");
+    let sql = format!(
+        "INSERT INTO sends (id, user_id, text, file_name, file_content, password, max_views, views_left, expires_at, \
+         created_at) VALUES ({})",
+        values
+    );
+    sqlx::query_with(&sql, binds.args)
+        .execute(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_audit_event_row(
+    conn: &mut AnyConnection,
+    event: &AuditEvent,
+) -> Result<(), ApiErrors> {
+    let mut binds = Binds::default();
+    let values = [
+        binds.push(required_id(event.id)?),
+        binds.push(enum_text(&event.action)?),
+        binds.push(event.owner_id.to_hex()),
+        binds.push_optional(optional_id(event.actor_id)),
+        binds.push_optional(optional_id(event.target_id)),
+        binds.push(event.created_at.timestamp_millis()),
+    ]
+    .join(", ");
+    let sql = format!(
+        "INSERT INTO audit_log (id, action, owner_id, actor_id, target_id, created_at) VALUES ({})",
+        values
+    );
+    sqlx::query_with(&sql, binds.args)
+        .execute(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+async fn insert_saved_search_row(
+    conn: &mut AnyConnection,
+    search: &SavedSearch,
+) -> Result<(), ApiErrors> {
+    let mut binds = Binds::default();
+    let values = [
+        binds.push(required_id(search.id)?),
+        binds.push(search.user_id.to_hex()),
+        binds.push(search.name.clone()),
+        binds.push_optional(search.query.clone()),
+        binds.push_optional(search.mode.clone()),
+        binds.push(json_text(&search.types)?),
+        binds.push_optional(search.sort.clone()),
+        binds.push(search.created_at.timestamp_millis()),
+        binds.push(search.updated_at.timestamp_millis()),
+    ]
+    .join(", ");
+    let sql = format!(
+        "INSERT INTO saved_searches (id, user_id, name, query, mode, types, sort, created_at, updated_at) \
+         VALUES ({})",
+        values
+    );
+    sqlx::query_with(&sql, binds.args)
+        .execute(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/**
+ * Set a column of a record patch, null fields are unset
+ */
+fn patch_column<'q, T: 'q + Send + Encode<'q, Any> + Type<Any>>(
+    set: &mut Vec<String>,
+    binds: &mut Binds<'q>,
+    column: &str,
+    value: Option<Option<T>>,
+) {
+    match value {
+        Some(Some(value)) => set.push(format!("{} = {}", column, binds.push(value))),
+        Some(None) => set.push(format!("{} = NULL", column)),
+        None => {}
+    }
+}
+
+fn patch_json<T: Serialize>(value: Option<Option<T>>) -> Result<Option<Option<String>>, ApiErrors> {
+    value.map(|value| optional_json_text(&value)).transpose()
+}
+
+/**
+ * Condition on a single record owned by the user, optionally pinned to a revision.
+ * Records created before revisions existed have no revision and count as revision 0
+ */
+fn record_condition(
+    binds: &mut Binds,
+    record_id: ObjectId,
+    user_id: ObjectId,
+    expected_revision: Option<i64>,
+) -> String {
+    let mut condition = format!(
+        "id = {} AND user_id = {}",
+        binds.push(record_id.to_hex()),
+        binds.push(user_id.to_hex())
+    );
+    if let Some(revision) = expected_revision {
+        condition.push_str(&format!(
+            " AND COALESCE(revision, 0) = {}",
+            binds.push(revision)
+        ));
+    }
+    condition
+}
+
+/**
+ * Work out why a conditional write matched nothing
+ */
+async fn missed_write_error(
+    conn: &mut AnyConnection,
+    record_id: ObjectId,
+    user_id: ObjectId,
+    expected_revision: Option<i64>,
+) -> ApiErrors {
+    if expected_revision.is_none() {
+        return not_found("Record not found");
+    }
+    let exists = sqlx::query(
+        "SELECT CAST(COUNT(*) AS TEXT) AS count FROM records WHERE id = $1 AND user_id = $2",
+    )
+    .bind(record_id.to_hex())
+    .bind(user_id.to_hex())
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(db_error)
+    .and_then(|row| get_int(&row, "count"));
+    match exists {
+        Ok(0) => not_found("Record not found"),
+        Ok(_) => ApiErrors::PreconditionFailed("Record has been modified".to_string()),
+        Err(err) => err,
+    }
+}
+
+/**
+ * Apply a record patch, returns the revision of the record after it
+ */
+async fn update_record_row(
+    conn: &mut AnyConnection,
+    updated_record: UpdateRecord,
+    record_id: ObjectId,
+    user_id: ObjectId,
+    expected_revision: Option<i64>,
+) -> Result<i64, ApiErrors> {
+    let now = DateTime::now().timestamp_millis();
+    let mut binds = Binds::default();
+    let mut set = vec![format!("updated_at = {}", binds.push(now))];
+    if matches!(updated_record.password, Some(Some(_))) {
+        set.push(format!("password_changed_at = {}", binds.push(now)));
+    }
+    // A breach check only holds for the credentials that were checked
+    if updated_record.password.is_some() || updated_record.email.is_some() {
+        set.push(
+            "breach_password_count = NULL, breach_email_count = NULL, breach_checked_at = NULL"
+                .to_string(),
+        );
+    }
+    let uris = patch_json(updated_record.uris)?;
+    let tags = patch_json(updated_record.tags)?;
+    let fields = [
+        ("email", updated_record.email),
+        ("password", updated_record.password),
+        ("username", updated_record.username),
+        ("service", updated_record.service),
+        ("key", updated_record.key),
+        ("secret", updated_record.secret),
+        ("uris", uris),
+        ("tags", tags),
+    ];
+    for (column, value) in fields {
+        patch_column(&mut set, &mut binds, column, value);
+    }
+    patch_column(
+        &mut set,
+        &mut binds,
+        "two_factor",
+        updated_record.two_factor.map(|value| value.map(i64::from)),
+    );
+    patch_column(
+        &mut set,
+        &mut binds,
+        "rotation_period",
+        updated_record.rotation_period,
+    );
+    // Every write bumps the revision so concurrent writers can detect each other
+    set.push("revision = COALESCE(revision, 0) + 1".to_string());
+
+    let condition = record_condition(&mut binds, record_id, user_id, expected_revision);
+    let sql = format!(
+        "UPDATE records SET {} WHERE {} RETURNING CAST(revision AS TEXT) AS revision",
+        set.join(", "),
+        condition
+    );
+    match sqlx::query_with(&sql, binds.args)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(db_error)?
+    {
+        Some(row) => get_int(&row, "revision"),
+        None => Err(missed_write_error(conn, record_id, user_id, expected_revision).await),
+    }
+}
+
+/**
+ * Remove what belongs to a deleted record, its shares and attachments
+ */
+async fn delete_record_dependents(
+    conn: &mut AnyConnection,
+    record_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    for sql in [
+        "DELETE FROM record_shares WHERE record_id = $1",
+        "DELETE FROM attachment_chunks WHERE files_id IN (SELECT id FROM attachments WHERE record_id = $1)",
+        "DELETE FROM attachments WHERE record_id = $1",
+    ] {
+        sqlx::query(sql).bind(record_id.to_hex()).execute(&mut *conn).await.map_err(db_error)?;
+    }
+    Ok(())
+}
+
+async fn delete_record_row(
+    conn: &mut AnyConnection,
+    record_id: ObjectId,
+    user_id: ObjectId,
+    expected_revision: Option<i64>,
+) -> Result<(), ApiErrors> {
+    let mut binds = Binds::default();
+    let sql = format!(
+        "DELETE FROM records WHERE {}",
+        record_condition(&mut binds, record_id, user_id, expected_revision)
+    );
+    let res = sqlx::query_with(&sql, binds.args)
+        .execute(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    if res.rows_affected() == 0 {
+        return Err(missed_write_error(conn, record_id, user_id, expected_revision).await);
+    }
+    delete_record_dependents(conn, record_id).await
+}
+
+/**
+ * Conditions of the records a search matches, on every page
+ */
+fn search_conditions(params: &SearchParams, binds: &mut Binds) -> Result<Vec<String>, ApiErrors> {
+    // Personal records of the user, the ones shared with them and those of their organizations
+    let mut reachable = vec![format!(
+        "(user_id = {} AND org_id IS NULL)",
+        binds.push(params.user_id.to_hex())
+    )];
+    if !params.shared_record_ids.is_empty() {
+        reachable.push(format!(
+            "id IN ({})",
+            binds.push_ids(&params.shared_record_ids)
+        ));
+    }
+    if !params.org_ids.is_empty() {
+        reachable.push(format!("org_id IN ({})", binds.push_ids(&params.org_ids)));
+    }
+    let mut conditions = vec![format!("({})", reachable.join(" OR "))];
+
+    let record_types = [&params.password_record, &params.secret_record]
+        .into_iter()
+        .flatten()
+        .map(|record_type| enum_text(record_type).map(|record_type| binds.push(record_type)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !record_types.is_empty() {
+        conditions.push(format!("record_type IN ({})", record_types.join(", ")));
+    }
+    Ok(conditions)
+}
+
+/**
+ * Storage on a pool of connections to SQLite or PostgreSQL, every clone shares the pool
+ */
+#[derive(Clone)]
+pub struct SqlStorage {
+    pool: AnyPool,
+}
+
+impl SqlStorage {
+    /**
+     * Connect to the `sqlite` or `postgres` database at `url` and bring its schema up to date
+     */
+    pub async fn connect(storage: &str, url: &str) -> Result<SqlStorage, ApiErrors> {
+        let (migrator, options) = match storage {
+            // SQLite takes one writer at a time, a single connection that is kept open queues them
+            // up and keeps an in-memory database alive
+            "sqlite" if url.starts_with("sqlite:") => (
+                &SQLITE_MIGRATIONS,
+                AnyPoolOptions::new()
+                    .max_connections(1)
+                    .idle_timeout(None)
+                    .max_lifetime(None),
+            ),
+            "postgres" if url.starts_with("postgres:") || url.starts_with("postgresql:") => {
+                (&POSTGRES_MIGRATIONS, AnyPoolOptions::new())
+            }
+            _ => {
+                return Err(ApiErrors::ServerError(format!(
+                    "DATABASE_URL is not a {} database",
+                    storage
+                )))
+            }
+        };
+        sqlx::any::install_default_drivers();
+        let pool = options.connect(url).await.map_err(db_error)?;
+        migrator
+            .run(&pool)
+            .await
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(SqlStorage { pool })
+    }
+
+    async fn connection(&self) -> Result<sqlx::pool::PoolConnection<Any>, ApiErrors> {
+        self.pool.acquire().await.map_err(db_error)
+    }
+
+    async fn fetch_all<T>(
+        &self,
+        sql: &str,
+        id: ObjectId,
+        of: impl Fn(&AnyRow) -> Result<T, ApiErrors>,
+    ) -> Result<Vec<T>, ApiErrors> {
+        let rows = sqlx::query(sql)
+            .bind(id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        rows_of(rows, of)
+    }
+
+    async fn fetch_optional<T>(
+        &self,
+        sql: &str,
+        id: ObjectId,
+        of: impl Fn(&AnyRow) -> Result<T, ApiErrors>,
+    ) -> Result<Option<T>, ApiErrors> {
+        sqlx::query(sql)
+            .bind(id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?
+            .as_ref()
+            .map(of)
+            .transpose()
+    }
+
+    /**
+     * Run a statement on a single id, not found when it matched nothing
+     */
+    async fn execute_one(
+        &self,
+        sql: &str,
+        id: ObjectId,
+        not_found_message: &str,
+    ) -> Result<(), ApiErrors> {
+        let res = sqlx::query(sql)
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found(not_found_message));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for SqlStorage {
+    async fn email_exists(&self, email: &str) -> Result<bool, ApiErrors> {
+        let row = sqlx::query("SELECT CAST(COUNT(*) AS TEXT) AS count FROM users WHERE email = $1")
+            .bind(email.to_string())
+            .fetch_one(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(get_int(&row, "count")? > 0)
+    }
+
+    async fn username_exists(&self, username: &str) -> Result<bool, ApiErrors> {
+        let row =
+            sqlx::query("SELECT CAST(COUNT(*) AS TEXT) AS count FROM users WHERE username = $1")
+                .bind(username.to_string())
+                .fetch_one(&self.pool)
+                .await
+                .map_err(db_error)?;
+        Ok(get_int(&row, "count")? > 0)
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<ObjectId, ApiErrors> {
+        let id = user.id.unwrap_or_default();
+        insert_user_row(
+            &mut *self.connection().await?,
+            &User {
+                id: Some(id),
+                ..user.clone()
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn get_user(&self, email: &str) -> Result<User, ApiErrors> {
+        sqlx::query("SELECT * FROM users WHERE email = $1")
+            .bind(email.to_string())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?
+            .as_ref()
+            .map(user_of)
+            .ok_or_else(|| ApiErrors::BadRequest("User not found".to_string()))?
+    }
+
+    async fn insert_record(&self, record: Record) -> Result<ObjectId, ApiErrors> {
+        let id = record.id.unwrap_or_default();
+        insert_record_row(
+            &mut *self.connection().await?,
+            &Record {
+                id: Some(id),
+                ..record
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn get_record(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Record, ApiErrors> {
+        sqlx::query(&format!(
+            "SELECT {RECORD_COLUMNS} FROM records WHERE id = $1 AND user_id = $2"
+        ))
+        .bind(record_id.to_hex())
+        .bind(user_id.to_hex())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?
+        .as_ref()
+        .map(record_of)
+        .ok_or_else(|| not_found("Record not found"))?
+    }
+
+    async fn get_record_by_id(&self, record_id: ObjectId) -> Result<Record, ApiErrors> {
+        self.fetch_optional(
+            &format!("SELECT {RECORD_COLUMNS} FROM records WHERE id = $1"),
+            record_id,
+            record_of,
+        )
+        .await?
+        .ok_or_else(|| not_found("Record not found"))
+    }
+
+    async fn get_all_user_records(&self, user_id: ObjectId) -> Result<RecordStream, ApiErrors> {
+        // Records are read in batches by id, no connection is held between two batches
+        let batches = stream::try_unfold(
+            (self.pool.clone(), String::new(), false),
+            move |(pool, after, done)| async move {
+                if done {
+                    return Ok(None);
+                }
+                let rows = sqlx::query(
+                    &format!("SELECT {RECORD_COLUMNS} FROM records WHERE user_id = $1 AND org_id IS NULL AND id > $2 ORDER BY id LIMIT $3"),
+                )
+                .bind(user_id.to_hex())
+                .bind(after)
+                .bind(BATCH_SIZE)
+                .fetch_all(&pool)
+                .await
+                .map_err(db_error)?;
+                let records = rows_of(rows, record_of)?;
+                let done = (records.len() as i64) < BATCH_SIZE;
+                let after = match records.last() {
+                    Some(record) => required_id(record.id)?,
+                    None => return Ok(None),
+                };
+                Ok(Some((stream::iter(records.into_iter().map(Ok)), (pool, after, done))))
+            },
+        )
+        .try_flatten();
+        Ok(Box::pin(batches))
+    }
+
+    async fn get_user_records_page(
+        &self,
+        user_id: ObjectId,
+        page: PageRequest,
+    ) -> Result<Vec<Record>, ApiErrors> {
+        let mut binds = Binds::default();
+        let mut conditions = vec![format!(
+            "user_id = {} AND org_id IS NULL",
+            binds.push(user_id.to_hex())
+        )];
+        conditions.extend(page_clause::page_condition(&page, &mut binds)?);
+        // One record more than the limit tells whether another page follows
+        let sql = format!(
+            "SELECT {RECORD_COLUMNS} FROM records WHERE {} ORDER BY {} LIMIT {}",
+            conditions.join(" AND "),
+            page_clause::page_order(&page),
+            binds.push(page.limit + 1)
+        );
+        let rows = sqlx::query_with(&sql, binds.args)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        rows_of(rows, record_of)
+    }
+
+    async fn count_user_records(&self, user_id: ObjectId) -> Result<u64, ApiErrors> {
+        let row = sqlx::query("SELECT CAST(COUNT(*) AS TEXT) AS count FROM records WHERE user_id = $1 AND org_id IS NULL")
+            .bind(user_id.to_hex())
+            .fetch_one(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(get_int(&row, "count")? as u64)
+    }
+
+    async fn get_user_record_metadata(&self, user_id: ObjectId) -> Result<Vec<Record>, ApiErrors> {
+        let records = self
+            .fetch_all(&format!("SELECT {RECORD_COLUMNS} FROM records WHERE user_id = $1 AND org_id IS NULL ORDER BY id"), user_id, record_of)
+            .await?;
+        Ok(records
+            .into_iter()
+            .map(|record| Record {
+                password: None,
+                secret: None,
+                ..record
+            })
+            .collect())
+    }
+
+    async fn delete_record(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<(), ApiErrors> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        delete_record_row(&mut tx, record_id, user_id, expected_revision).await?;
+        tx.commit().await.map_err(db_error)
+    }
+
+    async fn update_record(
+        &self,
+        updated_record: UpdateRecord,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<i64, ApiErrors> {
+        update_record_row(
+            &mut *self.connection().await?,
+            updated_record,
+            record_id,
+            user_id,
+            expected_revision,
+        )
+        .await
+    }
+
+    async fn write_records_atomically(
+        &self,
+        writes: Vec<RecordWrite>,
+    ) -> Result<Vec<RecordWriteResult>, ApiErrors> {
+        // Nothing is committed unless every write succeeds, the transaction rolls back when dropped
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        let mut results = Vec::with_capacity(writes.len());
+        for write in writes {
+            let result = match write {
+                RecordWrite::Insert(record) => {
+                    let id = record.id.unwrap_or_default();
+                    insert_record_row(
+                        &mut tx,
+                        &Record {
+                            id: Some(id),
+                            ..record
+                        },
+                    )
+                    .await?;
+                    RecordWriteResult::Inserted(id)
+                }
+                RecordWrite::Update {
+                    record_id,
+                    owner_id,
+                    updated_record,
+                    expected_revision,
+                } => RecordWriteResult::Updated(
+                    update_record_row(
+                        &mut tx,
+                        updated_record,
+                        record_id,
+                        owner_id,
+                        expected_revision,
+                    )
+                    .await?,
+                ),
+                RecordWrite::Delete {
+                    record_id,
+                    owner_id,
+                    expected_revision,
+                } => {
+                    delete_record_row(&mut tx, record_id, owner_id, expected_revision).await?;
+                    RecordWriteResult::Deleted
+                }
+            };
+            results.push(result);
+        }
+        tx.commit().await.map_err(db_error)?;
+        Ok(results)
+    }
+
+    async fn set_record_breach(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        revision: Option<i64>,
+        breach: BreachStatus,
+    ) -> Result<(), ApiErrors> {
+        sqlx::query(
+            "UPDATE records SET breach_password_count = $1, breach_email_count = $2, breach_checked_at = $3 \
+             WHERE id = $4 AND user_id = $5 AND COALESCE(revision, 0) = $6",
+        )
+        .bind(breach.password_count)
+        .bind(breach.email_count)
+        .bind(breach.checked_at.timestamp_millis())
+        .bind(record_id.to_hex())
+        .bind(user_id.to_hex())
+        .bind(revision.unwrap_or(0))
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn set_rotation_reminded(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        reminded_at: DateTime,
+    ) -> Result<(), ApiErrors> {
+        sqlx::query("UPDATE records SET rotation_reminded_at = $1 WHERE id = $2 AND user_id = $3")
+            .bind(reminded_at.timestamp_millis())
+            .bind(record_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn get_record_owner_ids(&self) -> Result<Vec<ObjectId>, ApiErrors> {
+        let rows = sqlx::query(
+            "SELECT DISTINCT user_id FROM records WHERE user_id IS NOT NULL ORDER BY user_id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+        rows_of(rows, |row| get_id(row, "user_id"))
+    }
+
+    async fn upsert_record_share(&self, share: RecordShare) -> Result<ObjectId, ApiErrors> {
+        // Sharing the record again with the same user keeps the share and replaces its permission
+        let row = sqlx::query(
+            "INSERT INTO record_shares (id, record_id, owner_id, owner_username, grantee_id, grantee_username, permission, \
+             created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+             ON CONFLICT (record_id, grantee_id) DO UPDATE SET permission = excluded.permission RETURNING id",
+        )
+        .bind(ObjectId::new().to_hex())
+        .bind(share.record_id.to_hex())
+        .bind(share.owner_id.to_hex())
+        .bind(share.owner_username)
+        .bind(share.grantee_id.to_hex())
+        .bind(share.grantee_username)
+        .bind(enum_text(&share.permission)?)
+        .bind(share.created_at.timestamp_millis())
+        .fetch_one(&self.pool)
+        .await
+        .map_err(db_error)?;
+        get_id(&row, "id")
+    }
+
+    async fn get_record_share(
+        &self,
+        record_id: ObjectId,
+        grantee_id: ObjectId,
+    ) -> Result<Option<RecordShare>, ApiErrors> {
+        sqlx::query(&format!(
+            "SELECT {SHARE_COLUMNS} FROM record_shares WHERE record_id = $1 AND grantee_id = $2"
+        ))
+        .bind(record_id.to_hex())
+        .bind(grantee_id.to_hex())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?
+        .as_ref()
+        .map(share_of)
+        .transpose()
+    }
+
+    async fn get_shares_with_user(
+        &self,
+        grantee_id: ObjectId,
+    ) -> Result<Vec<RecordShare>, ApiErrors> {
+        self.fetch_all(&format!("SELECT {SHARE_COLUMNS} FROM record_shares WHERE grantee_id = $1 ORDER BY created_at DESC"), grantee_id, share_of)
+            .await
+    }
+
+    async fn get_shares_by_user(&self, owner_id: ObjectId) -> Result<Vec<RecordShare>, ApiErrors> {
+        self.fetch_all(&format!("SELECT {SHARE_COLUMNS} FROM record_shares WHERE owner_id = $1 ORDER BY created_at DESC"), owner_id, share_of)
+            .await
+    }
+
+    async fn delete_record_share(
+        &self,
+        share_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiErrors> {
+        let res = sqlx::query(
+            "DELETE FROM record_shares WHERE id = $1 AND (owner_id = $2 OR grantee_id = $2)",
+        )
+        .bind(share_id.to_hex())
+        .bind(user_id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("Share not found"));
+        }
+        Ok(())
+    }
+
+    async fn create_organization(
+        &self,
+        org: Organization,
+        owner_username: &str,
+    ) -> Result<ObjectId, ApiErrors> {
+        let org_id = org.id.unwrap_or_default();
+        let owner = OrgMember {
+            id: Some(ObjectId::new()),
+            org_id,
+            org_name: org.name.clone(),
+            user_id: org.created_by,
+            username: owner_username.to_string(),
+            role: OrgRole::Owner,
+            joined_at: org.created_at,
+        };
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        insert_organization_row(
+            &mut tx,
+            &Organization {
+                id: Some(org_id),
+                ..org
+            },
+        )
+        .await?;
+        insert_member_row(&mut tx, &owner).await?;
+        tx.commit().await.map_err(db_error)?;
+        Ok(org_id)
+    }
+
+    async fn get_organization(&self, org_id: ObjectId) -> Result<Organization, ApiErrors> {
+        self.fetch_optional(
+            &format!("SELECT {ORGANIZATION_COLUMNS} FROM organizations WHERE id = $1"),
+            org_id,
+            organization_of,
+        )
+        .await?
+        .ok_or_else(|| not_found("Organization not found"))
+    }
+
+    async fn delete_organization(&self, org_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        for sql in [
+            "DELETE FROM attachment_chunks WHERE files_id IN \
+             (SELECT id FROM attachments WHERE record_id IN (SELECT id FROM records WHERE org_id = $1))",
+            "DELETE FROM attachments WHERE record_id IN (SELECT id FROM records WHERE org_id = $1)",
+            "DELETE FROM records WHERE org_id = $1",
+            "DELETE FROM org_collections WHERE org_id = $1",
+            "DELETE FROM org_invitations WHERE org_id = $1",
+            "DELETE FROM org_members WHERE org_id = $1",
+        ] {
+            sqlx::query(sql)
+                .bind(org_id.to_hex())
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+        let res = sqlx::query("DELETE FROM organizations WHERE id = $1")
+            .bind(org_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("Organization not found"));
+        }
+        tx.commit().await.map_err(db_error)
+    }
+
+    async fn get_org_member(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<OrgMember>, ApiErrors> {
+        sqlx::query(&format!(
+            "SELECT {MEMBER_COLUMNS} FROM org_members WHERE org_id = $1 AND user_id = $2"
+        ))
+        .bind(org_id.to_hex())
+        .bind(user_id.to_hex())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?
+        .as_ref()
+        .map(member_of)
+        .transpose()
+    }
+
+    async fn get_org_members(&self, org_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors> {
+        self.fetch_all(
+            &format!(
+                "SELECT {MEMBER_COLUMNS} FROM org_members WHERE org_id = $1 ORDER BY joined_at"
+            ),
+            org_id,
+            member_of,
+        )
+        .await
+    }
+
+    async fn get_user_memberships(&self, user_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors> {
+        self.fetch_all(
+            &format!(
+                "SELECT {MEMBER_COLUMNS} FROM org_members WHERE user_id = $1 ORDER BY org_name"
+            ),
+            user_id,
+            member_of,
+        )
+        .await
+    }
+
+    async fn insert_org_member(&self, member: OrgMember) -> Result<ObjectId, ApiErrors> {
+        let id = member.id.unwrap_or_default();
+        insert_member_row(
+            &mut *self.connection().await?,
+            &OrgMember {
+                id: Some(id),
+                ..member
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn update_org_member_role(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+        role: OrgRole,
+    ) -> Result<(), ApiErrors> {
+        let res =
+            sqlx::query("UPDATE org_members SET role = $1 WHERE org_id = $2 AND user_id = $3")
+                .bind(enum_text(&role)?)
+                .bind(org_id.to_hex())
+                .bind(user_id.to_hex())
+                .execute(&self.pool)
+                .await
+                .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("Member not found"));
+        }
+        Ok(())
+    }
+
+    async fn delete_org_member(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiErrors> {
+        let res = sqlx::query("DELETE FROM org_members WHERE org_id = $1 AND user_id = $2")
+            .bind(org_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("Member not found"));
+        }
+        Ok(())
+    }
+
+    async fn insert_org_invitation(
+        &self,
+        invitation: OrgInvitation,
+    ) -> Result<ObjectId, ApiErrors> {
+        let id = invitation.id.unwrap_or_default();
+        insert_invitation_row(
+            &mut *self.connection().await?,
+            &OrgInvitation {
+                id: Some(id),
+                ..invitation
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn get_org_invitation(
+        &self,
+        invitation_id: ObjectId,
+    ) -> Result<OrgInvitation, ApiErrors> {
+        self.fetch_optional(
+            &format!("SELECT {INVITATION_COLUMNS} FROM org_invitations WHERE id = $1"),
+            invitation_id,
+            invitation_of,
+        )
+        .await?
+        .ok_or_else(|| not_found("Invitation not found"))
+    }
+
+    async fn get_org_invitations(&self, org_id: ObjectId) -> Result<Vec<OrgInvitation>, ApiErrors> {
+        self.fetch_all(&format!("SELECT {INVITATION_COLUMNS} FROM org_invitations WHERE org_id = $1 ORDER BY created_at DESC"), org_id, invitation_of)
+            .await
+    }
+
+    async fn get_user_invitations(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<OrgInvitation>, ApiErrors> {
+        self.fetch_all(&format!("SELECT {INVITATION_COLUMNS} FROM org_invitations WHERE user_id = $1 ORDER BY created_at DESC"), user_id, invitation_of)
+            .await
+    }
+
+    async fn delete_org_invitation(&self, invitation_id: ObjectId) -> Result<(), ApiErrors> {
+        self.execute_one(
+            "DELETE FROM org_invitations WHERE id = $1",
+            invitation_id,
+            "Invitation not found",
+        )
+        .await
+    }
+
+    async fn insert_org_collection(
+        &self,
+        collection: OrgCollection,
+    ) -> Result<ObjectId, ApiErrors> {
+        let id = collection.id.unwrap_or_default();
+        insert_collection_row(
+            &mut *self.connection().await?,
+            &OrgCollection {
+                id: Some(id),
+                ..collection
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn get_org_collections(&self, org_id: ObjectId) -> Result<Vec<OrgCollection>, ApiErrors> {
+        self.fetch_all(
+            &format!(
+                "SELECT {COLLECTION_COLUMNS} FROM org_collections WHERE org_id = $1 ORDER BY name"
+            ),
+            org_id,
+            collection_of,
+        )
+        .await
+    }
+
+    async fn delete_org_collection(
+        &self,
+        org_id: ObjectId,
+        collection_id: ObjectId,
+    ) -> Result<(), ApiErrors> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        let res = sqlx::query("DELETE FROM org_collections WHERE id = $1 AND org_id = $2")
+            .bind(collection_id.to_hex())
+            .bind(org_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("Collection not found"));
+        }
+        sqlx::query(
+            "UPDATE records SET collection_id = NULL WHERE org_id = $1 AND collection_id = $2",
+        )
+        .bind(org_id.to_hex())
+        .bind(collection_id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)
+    }
+
+    async fn get_org_records(
+        &self,
+        org_id: ObjectId,
+        collection_id: Option<ObjectId>,
+    ) -> Result<Vec<Record>, ApiErrors> {
+        let mut binds = Binds::default();
+        let mut sql = format!(
+            "SELECT {RECORD_COLUMNS} FROM records WHERE org_id = {}",
+            binds.push(org_id.to_hex())
+        );
+        if let Some(collection_id) = collection_id {
+            sql.push_str(&format!(
+                " AND collection_id = {}",
+                binds.push(collection_id.to_hex())
+            ));
+        }
+        sql.push_str(" ORDER BY service ASC NULLS FIRST, key ASC NULLS FIRST, id ASC");
+        let rows = sqlx::query_with(&sql, binds.args)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        rows_of(rows, record_of)
+    }
+
+    async fn insert_emergency_access(
+        &self,
+        access: EmergencyAccess,
+    ) -> Result<ObjectId, ApiErrors> {
+        let id = access.id.unwrap_or_default();
+        insert_emergency_access_row(
+            &mut *self.connection().await?,
+            &EmergencyAccess {
+                id: Some(id),
+                ..access
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn get_emergency_access(
+        &self,
+        access_id: ObjectId,
+    ) -> Result<EmergencyAccess, ApiErrors> {
+        self.fetch_optional(
+            &format!("SELECT {EMERGENCY_ACCESS_COLUMNS} FROM emergency_access WHERE id = $1"),
+            access_id,
+            emergency_access_of,
+        )
+        .await?
+        .ok_or_else(|| not_found("Emergency access not found"))
+    }
+
+    async fn get_emergency_contacts(
+        &self,
+        grantor_id: ObjectId,
+    ) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        self.fetch_all(
+            &format!("SELECT {EMERGENCY_ACCESS_COLUMNS} FROM emergency_access WHERE grantor_id = $1 ORDER BY created_at"),
+            grantor_id,
+            emergency_access_of,
+        )
+        .await
+    }
+
+    async fn get_emergency_grants(
+        &self,
+        grantee_id: ObjectId,
+    ) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        self.fetch_all(
+            &format!("SELECT {EMERGENCY_ACCESS_COLUMNS} FROM emergency_access WHERE grantee_id = $1 ORDER BY created_at"),
+            grantee_id,
+            emergency_access_of,
+        )
+        .await
+    }
+
+    async fn get_requested_emergency_accesses(&self) -> Result<Vec<EmergencyAccess>, ApiErrors> {
+        let rows = sqlx::query(&format!("SELECT {EMERGENCY_ACCESS_COLUMNS} FROM emergency_access WHERE status = $1 ORDER BY requested_at"))
+            .bind(enum_text(&EmergencyStatus::Requested)?)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        rows_of(rows, emergency_access_of)
+    }
+
+    async fn set_emergency_status(
+        &self,
+        access_id: ObjectId,
+        expected: EmergencyStatus,
+        status: EmergencyStatus,
+        at: DateTime,
+    ) -> Result<bool, ApiErrors> {
+        let mut binds = Binds::default();
+        let mut set = vec![format!("status = {}", binds.push(enum_text(&status)?))];
+        match status {
+            EmergencyStatus::Requested => set.push(format!(
+                "requested_at = {}",
+                binds.push(at.timestamp_millis())
+            )),
+            EmergencyStatus::Approved => set.push(format!(
+                "approved_at = {}",
+                binds.push(at.timestamp_millis())
+            )),
+            EmergencyStatus::Idle => {
+                set.push("requested_at = NULL, approved_at = NULL".to_string())
+            }
+        }
+        // Only moves the access when it is still in the expected status, so concurrent answers do not both apply
+        let sql = format!(
+            "UPDATE emergency_access SET {} WHERE id = {} AND status = {}",
+            set.join(", "),
+            binds.push(access_id.to_hex()),
+            binds.push(enum_text(&expected)?)
+        );
+        let res = sqlx::query_with(&sql, binds.args)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn delete_emergency_access(&self, access_id: ObjectId) -> Result<(), ApiErrors> {
+        self.execute_one(
+            "DELETE FROM emergency_access WHERE id = $1",
+            access_id,
+            "Emergency access not found",
+        )
+        .await
+    }
+
+    async fn insert_attachment_chunk(&self, chunk: AttachmentChunk) -> Result<(), ApiErrors> {
+        let chunk = AttachmentChunk {
+            id: Some(chunk.id.unwrap_or_default()),
+            ..chunk
+        };
+        insert_chunk_row(&mut *self.connection().await?, &chunk).await
+    }
+
+    async fn insert_attachment(&self, attachment: Attachment) -> Result<(), ApiErrors> {
+        insert_attachment_row(&mut *self.connection().await?, &attachment).await
+    }
+
+    async fn get_attachment(
+        &self,
+        attachment_id: ObjectId,
+        record_id: ObjectId,
+    ) -> Result<Attachment, ApiErrors> {
+        sqlx::query(&format!(
+            "SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE id = $1 AND record_id = $2"
+        ))
+        .bind(attachment_id.to_hex())
+        .bind(record_id.to_hex())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?
+        .as_ref()
+        .map(attachment_of)
+        .ok_or_else(|| not_found("Attachment not found"))?
+    }
+
+    async fn get_record_attachments(
+        &self,
+        record_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiErrors> {
+        self.fetch_all(&format!("SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE record_id = $1 ORDER BY upload_date"), record_id, attachment_of)
+            .await
+    }
+
+    async fn get_attachment_chunks(
+        &self,
+        attachment_id: ObjectId,
+    ) -> Result<AttachmentChunks, ApiErrors> {
+        // One chunk is read at a time, as the previous one is consumed
+        let chunks = stream::try_unfold(
+            (self.pool.clone(), -1_i64),
+            move |(pool, after)| async move {
+                let row = sqlx::query(&format!("SELECT {CHUNK_COLUMNS} FROM attachment_chunks WHERE files_id = $1 AND n > $2 ORDER BY n LIMIT 1"))
+                .bind(attachment_id.to_hex())
+                .bind(after)
+                .fetch_optional(&pool)
+                .await
+                .map_err(db_error)?;
+                match row {
+                    Some(row) => {
+                        let chunk = chunk_of(&row)?;
+                        let n = i64::from(chunk.n);
+                        Ok(Some((chunk, (pool, n))))
+                    }
+                    None => Ok(None),
+                }
+            },
+        );
+        Ok(Box::pin(chunks))
+    }
+
+    async fn get_used_attachment_bytes(&self, user_id: ObjectId) -> Result<i64, ApiErrors> {
+        // PostgreSQL sums BIGINT into NUMERIC, it is cast back
+        let row = sqlx::query("SELECT CAST(COALESCE(SUM(size), 0) AS TEXT) AS used FROM attachments WHERE user_id = $1")
+            .bind(user_id.to_hex())
+            .fetch_one(&self.pool)
+            .await
+            .map_err(db_error)?;
+        get_int(&row, "used")
+    }
+
+    async fn delete_attachment(&self, attachment_id: ObjectId) -> Result<(), ApiErrors> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        for sql in [
+            "DELETE FROM attachment_chunks WHERE files_id = $1",
+            "DELETE FROM attachments WHERE id = $1",
+        ] {
+            sqlx::query(sql)
+                .bind(attachment_id.to_hex())
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+        tx.commit().await.map_err(db_error)
+    }
+
+    async fn insert_send(&self, send: SecretSend) -> Result<(), ApiErrors> {
+        insert_send_row(&mut *self.connection().await?, &send).await
+    }
+
+    async fn get_send(&self, send_id: &str) -> Result<SecretSend, ApiErrors> {
+        sqlx::query(&format!("SELECT {SEND_COLUMNS} FROM sends WHERE id = $1"))
+            .bind(send_id.to_string())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?
+            .as_ref()
+            .map(send_of)
+            .ok_or_else(|| not_found("Send not found"))?
+    }
+
+    async fn consume_send(
+        &self,
+        send_id: &str,
+        now: DateTime,
+    ) -> Result<Option<SecretSend>, ApiErrors> {
+        // The view is taken in a single statement, so two readers cannot both take the last one
+        let row = sqlx::query(
+            &format!("UPDATE sends SET views_left = views_left - 1 WHERE id = $1 AND views_left > 0 AND expires_at > $2 RETURNING {SEND_COLUMNS}"),
+        )
+        .bind(send_id.to_string())
+        .bind(now.timestamp_millis())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?;
+        let viewed = match row {
+            Some(row) => send_of(&row)?,
+            None => return Ok(None),
+        };
+        if viewed.views_left <= 0 {
+            sqlx::query("DELETE FROM sends WHERE id = $1 AND views_left <= 0")
+                .bind(send_id.to_string())
+                .execute(&self.pool)
+                .await
+                .map_err(db_error)?;
+        }
+        // The send as it was before the view
+        Ok(Some(SecretSend {
+            views_left: viewed.views_left + 1,
+            ..viewed
+        }))
+    }
+
+    async fn delete_expired_sends(&self, now: DateTime) -> Result<u64, ApiErrors> {
+        let res = sqlx::query("DELETE FROM sends WHERE expires_at <= $1 OR views_left <= 0")
+            .bind(now.timestamp_millis())
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(res.rows_affected())
+    }
+
+    async fn insert_audit_event(&self, event: AuditEvent) -> Result<ObjectId, ApiErrors> {
+        let id = event.id.unwrap_or_default();
+        insert_audit_event_row(
+            &mut *self.connection().await?,
+            &AuditEvent {
+                id: Some(id),
+                ..event
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn get_audit_events(&self, target_id: ObjectId) -> Result<Vec<AuditEvent>, ApiErrors> {
+        self.fetch_all(&format!("SELECT {AUDIT_EVENT_COLUMNS} FROM audit_log WHERE target_id = $1 ORDER BY created_at"), target_id, audit_event_of)
+            .await
+    }
+
+    async fn search_records(&self, params: SearchParams) -> Result<Vec<Record>, ApiErrors> {
+        let mut binds = Binds::default();
+        let mut conditions = search_conditions(&params, &mut binds)?;
+        conditions.extend(page_clause::page_condition(&params.page, &mut binds)?);
+        let mut sql = format!(
+            "SELECT {RECORD_COLUMNS} FROM records WHERE {} ORDER BY {}",
+            conditions.join(" AND "),
+            page_clause::page_order(&params.page)
+        );
+        // One record more than the limit tells whether another page follows
+        let wanted = params.page.limit as usize + 1;
+        let query = match &params.query {
+            Some(query) => query,
+            None => {
+                sql.push_str(&format!(" LIMIT {}", binds.push(params.page.limit + 1)));
+                let rows = sqlx::query_with(&sql, binds.args)
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(db_error)?;
+                return rows_of(rows, record_of);
+            }
+        };
+        // The query is matched the way records are ranked, on the rows read in order until the page is full
+        let mut rows = sqlx::query_with(&sql, binds.args).fetch(&self.pool);
+        let mut records = Vec::with_capacity(wanted);
+        while let Some(row) = rows.try_next().await.map_err(db_error)? {
+            let record = record_of(&row)?;
+            if relevance(query, &record).is_some() {
+                records.push(record);
+                if records.len() == wanted {
+                    break;
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    async fn count_search_records(&self, params: SearchParams) -> Result<u64, ApiErrors> {
+        let mut binds = Binds::default();
+        let conditions = search_conditions(&params, &mut binds)?.join(" AND ");
+        let query = match &params.query {
+            Some(query) => query,
+            None => {
+                let sql = format!(
+                    "SELECT CAST(COUNT(*) AS TEXT) AS count FROM records WHERE {}",
+                    conditions
+                );
+                let row = sqlx::query_with(&sql, binds.args)
+                    .fetch_one(&self.pool)
+                    .await
+                    .map_err(db_error)?;
+                return Ok(get_int(&row, "count")? as u64);
+            }
+        };
+        let sql = format!("SELECT {RECORD_COLUMNS} FROM records WHERE {}", conditions);
+        let mut rows = sqlx::query_with(&sql, binds.args).fetch(&self.pool);
+        let mut count = 0;
+        while let Some(row) = rows.try_next().await.map_err(db_error)? {
+            if relevance(query, &record_of(&row)?).is_some() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    async fn insert_saved_search(&self, search: SavedSearch) -> Result<ObjectId, ApiErrors> {
+        let id = search.id.unwrap_or_default();
+        insert_saved_search_row(
+            &mut *self.connection().await?,
+            &SavedSearch {
+                id: Some(id),
+                ..search
+            },
+        )
+        .await?;
+        Ok(id)
+    }
+
+    async fn get_saved_search(
+        &self,
+        search_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<SavedSearch, ApiErrors> {
+        sqlx::query(&format!(
+            "SELECT {SAVED_SEARCH_COLUMNS} FROM saved_searches WHERE id = $1 AND user_id = $2"
+        ))
+        .bind(search_id.to_hex())
+        .bind(user_id.to_hex())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?
+        .as_ref()
+        .map(saved_search_of)
+        .ok_or_else(|| not_found("Saved search not found"))?
+    }
+
+    async fn get_saved_searches(&self, user_id: ObjectId) -> Result<Vec<SavedSearch>, ApiErrors> {
+        self.fetch_all(&format!("SELECT {SAVED_SEARCH_COLUMNS} FROM saved_searches WHERE user_id = $1 ORDER BY name"), user_id, saved_search_of)
+            .await
+    }
+
+    async fn replace_saved_search(&self, search: SavedSearch) -> Result<(), ApiErrors> {
+        let search_id = search
+            .id
+            .ok_or_else(|| not_found("Saved search not found"))?;
+        let res = sqlx::query(
+            "UPDATE saved_searches SET name = $1, query = $2, mode = $3, types = $4, sort = $5, created_at = $6, \
+             updated_at = $7 WHERE id = $8 AND user_id = $9",
+        )
+        .bind(search.name.clone())
+        .bind(search.query.clone())
+        .bind(search.mode.clone())
+        .bind(json_text(&search.types)?)
+        .bind(search.sort.clone())
+        .bind(search.created_at.timestamp_millis())
+        .bind(search.updated_at.timestamp_millis())
+        .bind(search_id.to_hex())
+        .bind(search.user_id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("Saved search not found"));
+        }
+        Ok(())
+    }
+
+    async fn delete_saved_search(
+        &self,
+        search_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiErrors> {
+        let res = sqlx::query("DELETE FROM saved_searches WHERE id = $1 AND user_id = $2")
+            .bind(search_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("Saved search not found"));
+        }
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<AuthUser, ApiErrors> {
+        self.fetch_optional("SELECT * FROM users WHERE id = $1", user_id, auth_user_of)
+            .await?
+            .ok_or_else(|| not_found("User not found"))
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<AuthUser, ApiErrors> {
+        sqlx::query("SELECT * FROM users WHERE username = $1")
+            .bind(username.to_string())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?
+            .as_ref()
+            .map(auth_user_of)
+            .ok_or_else(|| not_found("User not found"))?
+    }
+
+    async fn update_user_fields(
+        &self,
+        user_id: ObjectId,
+        update_user: UpdateUser,
+    ) -> Result<(), ApiErrors> {
+        // Fields left out keep their value
+        let mut binds = Binds::default();
+        let sql = format!(
+            "UPDATE users SET email = COALESCE({}, email), password = COALESCE({}, password) WHERE id = {}",
+            binds.push_optional(update_user.email),
+            binds.push_optional(update_user.new_password),
+            binds.push(user_id.to_hex())
+        );
+        let res = sqlx::query_with(&sql, binds.args)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        if res.rows_affected() == 0 {
+            return Err(not_found("User not found"));
+        }
+        Ok(())
+    }
+}
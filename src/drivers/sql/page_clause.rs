@@ -0,0 +1,57 @@
+use bson::Bson;
+
+use super::Binds;
+use crate::shared::{
+    pagination::{PageCursor, PageRequest, SortDirection},
+    types::ApiErrors,
+};
+
+/**
+ * Condition of the records after the cursor of a page, None on the first page. Records without the
+ * sort field come first in ascending order and last in descending order, as MongoDB sorts them
+ */
+pub fn page_condition(page: &PageRequest, binds: &mut Binds) -> Result<Option<String>, ApiErrors> {
+    let (value, id) = match &page.cursor {
+        Some(PageCursor::After { value, id, .. }) => (value.clone(), *id),
+        Some(PageCursor::Offset { .. }) | None => return Ok(None),
+    };
+    let sort = match page.sort {
+        Some(sort) => sort,
+        None => return Ok(Some(format!("id > {}", binds.push(id.to_hex())))),
+    };
+    let column = sort.field.path();
+    let value = match value {
+        Bson::Null => None,
+        Bson::String(value) => Some(binds.push(value)),
+        Bson::DateTime(value) => Some(binds.push(value.timestamp_millis())),
+        _ => return Err(ApiErrors::BadRequest("Cursor is not valid".to_string())),
+    };
+    let id = binds.push(id.to_hex());
+    Ok(Some(match (sort.direction, value) {
+        (SortDirection::Asc, None) => {
+            format!("({column} IS NOT NULL OR ({column} IS NULL AND id > {id}))")
+        }
+        (SortDirection::Asc, Some(value)) => {
+            format!("({column} > {value} OR ({column} = {value} AND id > {id}))")
+        }
+        (SortDirection::Desc, None) => format!("({column} IS NULL AND id < {id})"),
+        (SortDirection::Desc, Some(value)) => {
+            format!(
+                "({column} < {value} OR ({column} = {value} AND id < {id}) OR {column} IS NULL)"
+            )
+        }
+    }))
+}
+
+/**
+ * Order of the records of a page, by id after the sort field
+ */
+pub fn page_order(page: &PageRequest) -> String {
+    match page.sort {
+        Some(sort) => match sort.direction {
+            SortDirection::Asc => format!("{} ASC NULLS FIRST, id ASC", sort.field.path()),
+            SortDirection::Desc => format!("{} DESC NULLS LAST, id DESC", sort.field.path()),
+        },
+        None => "id ASC".to_string(),
+    }
+}
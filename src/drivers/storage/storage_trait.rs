@@ -0,0 +1,213 @@
+use std::pin::Pin;
+
+use bson::{oid::ObjectId, DateTime};
+use rocket::futures::Stream;
+use crate::{shared::types::{ApiErrors, Attachment, AttachmentChunk, AuditEvent, SecretSend, BreachStatus, EmergencyAccess, EmergencyStatus, OrgCollection, OrgInvitation, OrgMember, OrgRole, Organization, User, Record, RecordShare, RecordWrite, RecordWriteResult, SavedSearch, UpdateRecord, AuthUser, UpdateUser}, modules::search_module::SearchParams, shared::pagination::PageRequest};
+
+#[cfg(test)]
+use mockall::automock;
+
+/**
+ * Chunks of an attachment streamed from storage
+ */
+pub type AttachmentChunks = Pin<Box<dyn Stream<Item = Result<AttachmentChunk, ApiErrors>> + Send>>;
+
+/**
+ * Records streamed from storage, for reads that can be too large to hold at once
+ */
+pub type RecordStream = Pin<Box<dyn Stream<Item = Result<Record, ApiErrors>> + Send>>;
+
+/**
+ * Where the API keeps its data. Every backend implements it with the domain types of
+ * `shared::types`, so modules do not depend on the database they run against
+ */
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Auth Methods
+    async fn email_exists(&self, email: &str) -> Result<bool, ApiErrors>;
+    async fn username_exists(&self, username: &str) -> Result<bool, ApiErrors>;
+    async fn insert_user(&self, user: &User) -> Result<ObjectId, ApiErrors>;
+    async fn get_user(&self, email: &str) -> Result<User, ApiErrors>;
+
+    // Password Record Methods
+    async fn insert_record(&self, record: Record) -> Result<ObjectId, ApiErrors>;
+    async fn get_record(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Record, ApiErrors>;
+    /// A record whoever it belongs to, only for authorization checks
+    async fn get_record_by_id(&self, record_id: ObjectId) -> Result<Record, ApiErrors>;
+    /// Every personal record of the user, records of their organizations are not included
+    async fn get_all_user_records(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<RecordStream, ApiErrors>;
+    /// A page of personal records of the user, with one record more than the limit to tell
+    /// whether another page follows
+    async fn get_user_records_page(
+        &self,
+        user_id: ObjectId,
+        page: PageRequest,
+    ) -> Result<Vec<Record>, ApiErrors>;
+    async fn count_user_records(&self, user_id: ObjectId) -> Result<u64, ApiErrors>;
+    /// Every personal record of the user without its password or secret
+    async fn get_user_record_metadata(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<Record>, ApiErrors>;
+    async fn delete_record(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<(), ApiErrors>;
+    /// Returns the revision of the record after the update
+    async fn update_record(
+        &self,
+        updated_record: UpdateRecord,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        expected_revision: Option<i64>,
+    ) -> Result<i64, ApiErrors>;
+    /// Apply every write in a single transaction, nothing is stored if one of them fails
+    async fn write_records_atomically(
+        &self,
+        writes: Vec<RecordWrite>,
+    ) -> Result<Vec<RecordWriteResult>, ApiErrors>;
+
+    /// Store the breach check of a record without bumping its revision.
+    /// Nothing is stored if the record changed since it was read at `revision`
+    async fn set_record_breach(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        revision: Option<i64>,
+        breach: BreachStatus,
+    ) -> Result<(), ApiErrors>;
+    /// Store when the last rotation reminder of a record was sent, without bumping its revision
+    async fn set_rotation_reminded(
+        &self,
+        record_id: ObjectId,
+        user_id: ObjectId,
+        reminded_at: DateTime,
+    ) -> Result<(), ApiErrors>;
+    /// Id of every user that has at least one record
+    async fn get_record_owner_ids(&self) -> Result<Vec<ObjectId>, ApiErrors>;
+
+    // Sharing
+    /// Share a record, sharing it again with the same user replaces the permission
+    async fn upsert_record_share(&self, share: RecordShare) -> Result<ObjectId, ApiErrors>;
+    /// The grant a user has on a record they do not own
+    async fn get_record_share(
+        &self,
+        record_id: ObjectId,
+        grantee_id: ObjectId,
+    ) -> Result<Option<RecordShare>, ApiErrors>;
+    async fn get_shares_with_user(&self, grantee_id: ObjectId) -> Result<Vec<RecordShare>, ApiErrors>;
+    async fn get_shares_by_user(&self, owner_id: ObjectId) -> Result<Vec<RecordShare>, ApiErrors>;
+    /// Either side of a share can remove it
+    async fn delete_record_share(&self, share_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors>;
+
+    // Organizations
+    /// Store the organization along with its creator as owner
+    async fn create_organization(&self, org: Organization, owner_username: &str) -> Result<ObjectId, ApiErrors>;
+    async fn get_organization(&self, org_id: ObjectId) -> Result<Organization, ApiErrors>;
+    /// Remove the organization with its members, invitations, collections and records
+    async fn delete_organization(&self, org_id: ObjectId) -> Result<(), ApiErrors>;
+    async fn get_org_member(&self, org_id: ObjectId, user_id: ObjectId) -> Result<Option<OrgMember>, ApiErrors>;
+    async fn get_org_members(&self, org_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors>;
+    async fn get_user_memberships(&self, user_id: ObjectId) -> Result<Vec<OrgMember>, ApiErrors>;
+    async fn insert_org_member(&self, member: OrgMember) -> Result<ObjectId, ApiErrors>;
+    async fn update_org_member_role(&self, org_id: ObjectId, user_id: ObjectId, role: OrgRole) -> Result<(), ApiErrors>;
+    async fn delete_org_member(&self, org_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors>;
+    async fn insert_org_invitation(&self, invitation: OrgInvitation) -> Result<ObjectId, ApiErrors>;
+    async fn get_org_invitation(&self, invitation_id: ObjectId) -> Result<OrgInvitation, ApiErrors>;
+    async fn get_org_invitations(&self, org_id: ObjectId) -> Result<Vec<OrgInvitation>, ApiErrors>;
+    async fn get_user_invitations(&self, user_id: ObjectId) -> Result<Vec<OrgInvitation>, ApiErrors>;
+    async fn delete_org_invitation(&self, invitation_id: ObjectId) -> Result<(), ApiErrors>;
+    async fn insert_org_collection(&self, collection: OrgCollection) -> Result<ObjectId, ApiErrors>;
+    async fn get_org_collections(&self, org_id: ObjectId) -> Result<Vec<OrgCollection>, ApiErrors>;
+    /// Records of the collection are kept in the organization without a collection
+    async fn delete_org_collection(&self, org_id: ObjectId, collection_id: ObjectId) -> Result<(), ApiErrors>;
+    async fn get_org_records(
+        &self,
+        org_id: ObjectId,
+        collection_id: Option<ObjectId>,
+    ) -> Result<Vec<Record>, ApiErrors>;
+
+    // Emergency access
+    async fn insert_emergency_access(&self, access: EmergencyAccess) -> Result<ObjectId, ApiErrors>;
+    async fn get_emergency_access(&self, access_id: ObjectId) -> Result<EmergencyAccess, ApiErrors>;
+    /// Contacts the user has trusted with their vault
+    async fn get_emergency_contacts(&self, grantor_id: ObjectId) -> Result<Vec<EmergencyAccess>, ApiErrors>;
+    /// Vaults the user is an emergency contact for
+    async fn get_emergency_grants(&self, grantee_id: ObjectId) -> Result<Vec<EmergencyAccess>, ApiErrors>;
+    async fn get_requested_emergency_accesses(&self) -> Result<Vec<EmergencyAccess>, ApiErrors>;
+    /// Move an access to `status` if it is still in `expected`, returns false when it was not
+    async fn set_emergency_status(
+        &self,
+        access_id: ObjectId,
+        expected: EmergencyStatus,
+        status: EmergencyStatus,
+        at: DateTime,
+    ) -> Result<bool, ApiErrors>;
+    async fn delete_emergency_access(&self, access_id: ObjectId) -> Result<(), ApiErrors>;
+
+    // Attachments
+    /// Chunks are written before their file, a file is only visible once it is complete
+    async fn insert_attachment_chunk(&self, chunk: AttachmentChunk) -> Result<(), ApiErrors>;
+    async fn insert_attachment(&self, attachment: Attachment) -> Result<(), ApiErrors>;
+    async fn get_attachment(&self, attachment_id: ObjectId, record_id: ObjectId) -> Result<Attachment, ApiErrors>;
+    async fn get_record_attachments(&self, record_id: ObjectId) -> Result<Vec<Attachment>, ApiErrors>;
+    /// The chunks of a file in order, read as they are consumed
+    async fn get_attachment_chunks(&self, attachment_id: ObjectId) -> Result<AttachmentChunks, ApiErrors>;
+    /// Bytes of attachments counted against the quota of a user
+    async fn get_used_attachment_bytes(&self, user_id: ObjectId) -> Result<i64, ApiErrors>;
+    /// Delete a file and its chunks, also cleans up the chunks of an upload that did not finish
+    async fn delete_attachment(&self, attachment_id: ObjectId) -> Result<(), ApiErrors>;
+
+    // Sends
+    async fn insert_send(&self, send: SecretSend) -> Result<(), ApiErrors>;
+    async fn get_send(&self, send_id: &str) -> Result<SecretSend, ApiErrors>;
+    /// Use up one view of a send that has views left and has not expired, returns the send as it was
+    /// before the view. The send is deleted with its last view
+    async fn consume_send(&self, send_id: &str, now: DateTime) -> Result<Option<SecretSend>, ApiErrors>;
+    /// Delete the sends that expired or have no views left, returns how many were deleted
+    async fn delete_expired_sends(&self, now: DateTime) -> Result<u64, ApiErrors>;
+
+    // Audit
+    async fn insert_audit_event(&self, event: AuditEvent) -> Result<ObjectId, ApiErrors>;
+    /// Events of a target, oldest first
+    async fn get_audit_events(&self, target_id: ObjectId) -> Result<Vec<AuditEvent>, ApiErrors>;
+
+    // Search
+    /// Up to one record more than the limit of the page, to tell whether another page follows
+    async fn search_records(
+        &self,
+        params: SearchParams,
+    ) -> Result<Vec<Record>, ApiErrors>;
+    /// Records the search matches on every page
+    async fn count_search_records(&self, params: SearchParams) -> Result<u64, ApiErrors>;
+    async fn insert_saved_search(&self, search: SavedSearch) -> Result<ObjectId, ApiErrors>;
+    async fn get_saved_search(&self, search_id: ObjectId, user_id: ObjectId) -> Result<SavedSearch, ApiErrors>;
+    /// Saved searches of a user by name
+    async fn get_saved_searches(&self, user_id: ObjectId) -> Result<Vec<SavedSearch>, ApiErrors>;
+    /// Replace a saved search of its user
+    async fn replace_saved_search(&self, search: SavedSearch) -> Result<(), ApiErrors>;
+    async fn delete_saved_search(&self, search_id: ObjectId, user_id: ObjectId) -> Result<(), ApiErrors>;
+
+    // User Modules
+    async fn get_user_by_id(
+        &self,
+        user_id: ObjectId
+    ) -> Result<AuthUser, ApiErrors>;
+    async fn get_user_by_username(&self, username: &str) -> Result<AuthUser, ApiErrors>;
+
+    async fn update_user_fields(
+        &self,
+        user_id: ObjectId,
+        update_user: UpdateUser
+    ) -> Result<(), ApiErrors>;
+}
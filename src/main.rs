@@ -1,9 +1,26 @@
 #[macro_use]
 extern crate rocket;
 extern crate dotenv;
+use crate::{
+    drivers::{
+        breach::{breach_trait::BreachSource, LocalBreachSource},
+        notifier::notifier_from_env,
+        storage::storage_trait::Storage,
+    },
+    modules::user_module,
+    shared::env_config::{
+        get_breach_check_interval_hours, get_breach_emails_dir, get_breach_passwords_dir,
+        get_database_url, get_emergency_check_interval_hours, get_equivalent_domains_path,
+        get_public_suffix_list_path, get_rotation_check_interval_hours, get_rotation_reminder_days,
+        get_send_purge_interval_hours, get_storage,
+    },
+};
 use dotenv::dotenv;
-use crate::{drivers::mongodb::mongo_trait::TMongoClient, modules::user_module};
-use modules::{auth_module, record_module, search_module};
+use modules::{
+    auth_module, emergency_module, generate_module, org_module, record_module, search_module,
+    send_module, share_module,
+};
+use rocket::{fairing::AdHoc, Build, Rocket};
 pub mod drivers;
 pub mod modules;
 pub mod shared;
@@ -16,20 +33,131 @@ fn index() -> &'static str {
     "Welcome to the Password Manager API"
 }
 
-#[launch]
-async fn rocket() -> _ {
+#[rocket::main]
+async fn main() {
     dotenv().ok();
 
-    let mut db = drivers::mongodb::MongoClient::new();
-    db.connect().await;
-    
+    if std::env::args().nth(1).as_deref() == Some("migrate-from-mongodb") {
+        migrate_from_mongodb().await;
+        return;
+    }
+
+    let rocket = match get_storage().as_str() {
+        "mongodb" => {
+            let mut db = drivers::mongodb::MongoClient::new();
+            db.connect().await;
+            build_rocket(db)
+        }
+        storage @ ("sqlite" | "postgres") => {
+            let db = drivers::sql::SqlStorage::connect(storage, &get_database_url())
+                .await
+                .expect("There was an error connecting to the database");
+            build_rocket(db)
+        }
+        "memory" => build_rocket(drivers::memory::MemoryStorage::new()),
+        storage => panic!(
+            "STORAGE {} is not supported, it must be mongodb, sqlite, postgres or memory",
+            storage
+        ),
+    };
+
     println!("Password manager api is now listening on port 8000");
 
+    let _ = rocket
+        .launch()
+        .await
+        .expect("There was an error launching the server");
+}
+
+/**
+ * Copy the MongoDB database of MONGODB_URI and DB_NAME into the empty SQL database of STORAGE and DATABASE_URL
+ */
+async fn migrate_from_mongodb() {
+    let mut mongo = drivers::mongodb::MongoClient::new();
+    mongo.connect().await;
+    let sql = drivers::sql::SqlStorage::connect(&get_storage(), &get_database_url())
+        .await
+        .expect("There was an error connecting to the SQL database");
+
+    match drivers::sql::copy::copy_from_mongodb(&mongo, &sql).await {
+        Ok(copied) => {
+            for (collection, count) in copied {
+                println!("{}: {} documents copied", collection, count);
+            }
+        }
+        Err(err) => panic!("Nothing was copied: {:?}", err),
+    }
+}
+
+/**
+ * The api on top of a storage backend, the background tasks each get a handle on the same storage
+ */
+fn build_rocket<S: Storage + Clone + 'static>(db: S) -> Rocket<Build> {
+    let breach_source =
+        LocalBreachSource::new(&get_breach_passwords_dir(), &get_breach_emails_dir());
+    let breach_monitor = (db.clone(), breach_source.clone());
+    let rotation_reminders = (db.clone(), notifier_from_env());
+    let emergency_auto_approval = (db.clone(), notifier_from_env());
+    let send_purge = db.clone();
+
     rocket::build()
-        .manage(Box::new(db) as Box<dyn TMongoClient>)
+        .manage(Box::new(db) as Box<dyn Storage>)
+        .manage(Box::new(breach_source) as Box<dyn BreachSource>)
+        .manage(notifier_from_env())
+        .manage(generate_module::component::Wordlist::load(
+            Some(&shared::env_config::get_wordlist_path()),
+        ))
+        .manage(record_module::uri_match::UriMatcher::load(
+            get_public_suffix_list_path().as_deref(),
+            &get_equivalent_domains_path(),
+        ))
         .mount("/", routes![index])
         .mount("/auth/", auth_module::api())
         .mount("/search", search_module::api())
         .mount("/record", record_module::api())
+        .mount("/share", share_module::api())
+        .mount("/org", org_module::api())
+        .mount("/emergency", emergency_module::api())
+        .mount("/send", send_module::api())
         .mount("/user", user_module::api())
+        .mount("/generate", generate_module::api())
+        .attach(AdHoc::on_liftoff("Breach monitor", |_| {
+            Box::pin(async move {
+                let (db, source) = breach_monitor;
+                record_module::breach::spawn_breach_monitor(
+                    Box::new(db),
+                    Box::new(source),
+                    get_breach_check_interval_hours(),
+                );
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Rotation reminders", |_| {
+            Box::pin(async move {
+                let (db, notifier) = rotation_reminders;
+                record_module::rotation::spawn_rotation_reminders(
+                    Box::new(db),
+                    notifier,
+                    get_rotation_check_interval_hours(),
+                    get_rotation_reminder_days(),
+                );
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Emergency access auto approval", |_| {
+            Box::pin(async move {
+                let (db, notifier) = emergency_auto_approval;
+                emergency_module::auto_approval::spawn_emergency_auto_approval(
+                    Box::new(db),
+                    notifier,
+                    get_emergency_check_interval_hours(),
+                );
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Send purge", |_| {
+            Box::pin(async move {
+                send_module::purge::spawn_send_purge(
+                    Box::new(send_purge),
+                    get_send_purge_interval_hours(),
+                );
+            })
+        }))
 }
@@ -2,13 +2,14 @@ use crate::{
     shared::{
         jwt_service::sign_token,
         types::{ApiErrors, AuthResponse, AuthUser, LoginForm, RegistrationForm, User},
-    }, drivers::mongodb::mongo_trait::TMongoClient,
+    }, drivers::storage::storage_trait::Storage,
 };
+use bson::oid::ObjectId;
 use pwhash::bcrypt;
 use rocket::State;
 
 pub async fn register(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     register_form: &mut RegistrationForm,
 ) -> Result<AuthResponse, ApiErrors> {
     /*
@@ -56,7 +57,7 @@ pub async fn register(
 }
 
 pub async fn login(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     info: LoginForm,
 ) -> Result<AuthResponse, ApiErrors> {
     let err_msg = String::from("Email or password is incorrect");
@@ -91,3 +92,17 @@ pub async fn login(
         token: sign_token(&id.to_string())?,
     })
 }
+
+/**
+ * Check a password against the one stored for the user
+ */
+pub async fn verify_password(
+    db: &dyn Storage,
+    user_id: ObjectId,
+    password: &str,
+) -> Result<bool, ApiErrors> {
+    let user = db.get_user_by_id(user_id).await?;
+    let user_password = db.get_user(&user.email).await?.password;
+
+    Ok(bcrypt::verify(password, &user_password))
+}
@@ -1,11 +1,11 @@
-use crate::drivers::mongodb::mongo_trait::TMongoClient;
+use crate::drivers::storage::storage_trait::Storage;
 use crate::shared::types::RegistrationForm;
 use crate::shared::types::{ApiErrors, AuthResponse, LoginForm};
 use rocket::{serde::json::Json, State};
 
 #[post("/login", data = "<login_form>")]
 pub async fn login(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     login_form: Json<LoginForm>,
 ) -> Result<Json<AuthResponse>, ApiErrors> {
     let response = auth_component::login(db, login_form.0).await?;
@@ -14,7 +14,7 @@ pub async fn login(
 
 #[post("/register", data = "<registration_form>")]
 pub async fn register(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     mut registration_form: Json<RegistrationForm>,
 ) -> Result<Json<AuthResponse>, ApiErrors> {
     let response = auth_component::register(db, &mut registration_form.0).await?;
@@ -26,3 +26,4 @@ pub fn api() -> Vec<rocket::Route> {
 }
 
 pub mod auth_component;
+pub mod reauth;
@@ -0,0 +1,70 @@
+use bson::oid::ObjectId;
+use rocket::{
+    http::Status,
+    outcome::try_outcome,
+    request::{self, FromRequest, Outcome},
+    Request,
+};
+
+use super::auth_component::verify_password;
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    shared::{jwt_service::Token, types::ApiErrors},
+};
+
+/**
+ * A valid bearer token whose owner also sent their account password in the
+ * `X-Account-Password` header. Used to guard routes that hand out the whole vault
+ */
+#[derive(Debug)]
+pub struct Reauthenticated {
+    pub id: ObjectId,
+}
+
+/**
+ * Who the plaintext passwords and secrets of a list are revealed to, they need the account password again
+ */
+pub fn reveal_to(include_secrets: Option<bool>, reauth: Result<Reauthenticated, ApiErrors>) -> Result<Option<ObjectId>, ApiErrors> {
+    match include_secrets.unwrap_or(false) {
+        true => Ok(Some(reauth?.id)),
+        false => Ok(None),
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Reauthenticated {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let token = try_outcome!(request.guard::<Token>().await);
+
+        let password = match request.headers().get_one("X-Account-Password") {
+            Some(password) => password,
+            None => {
+                return Outcome::Failure((
+                    Status::Unauthorized,
+                    ApiErrors::Unauthorized("Re-authentication is required".to_string()),
+                ))
+            }
+        };
+
+        let db = match request.rocket().state::<Box<dyn Storage>>() {
+            Some(db) => db,
+            None => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    ApiErrors::ServerError("Database is not managed".to_string()),
+                ))
+            }
+        };
+
+        match verify_password(db.as_ref(), token.id, password).await {
+            Ok(true) => Outcome::Success(Reauthenticated { id: token.id }),
+            Ok(false) => Outcome::Failure((
+                Status::Unauthorized,
+                ApiErrors::Unauthorized("Password is incorrect".to_string()),
+            )),
+            Err(err) => Outcome::Failure((err.status(), err)),
+        }
+    }
+}
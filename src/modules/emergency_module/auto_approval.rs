@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use bson::DateTime;
+use rocket::tokio::{self, time};
+
+use crate::drivers::{notifier::notifier_trait::Notifier, storage::storage_trait::Storage};
+
+use super::component::approve_due_requests;
+
+/**
+ * Approve the requests whose waiting period is over now and then every `interval_hours`,
+ * an interval of 0 turns the job off. Pending requests are still approved when the contact uses them
+ */
+pub fn spawn_emergency_auto_approval(
+    db: Box<dyn Storage>,
+    notifier: Box<dyn Notifier>,
+    interval_hours: u64,
+) {
+    if interval_hours == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(interval_hours * 60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(err) = approve_due_requests(db.as_ref(), notifier.as_ref(), DateTime::now()).await {
+                println!("Emergency access auto approval failed: {:?}", err);
+            }
+        }
+    });
+}
@@ -0,0 +1,463 @@
+use bson::{oid::ObjectId, DateTime};
+use pwhash::bcrypt;
+use rocket::{futures::stream::StreamExt, serde::json::serde_json::json};
+
+use crate::{
+    drivers::{notifier::notifier_trait::Notifier, storage::storage_trait::Storage},
+    modules::record_module::component::{reveal_or_mask, to_response_record},
+    shared::{
+        audit::{record_event, to_response_event},
+        env_config::get_emergency_wait_days,
+        types::{
+            ApiErrors, AuditAction, EmergencyAccess, EmergencyAccessType, EmergencyStatus,
+            NewEmergencyContact, Notification, NotificationEvent, ResponseAuditEvent,
+            ResponseEmergencyAccess, ResponseRecord, UpdateUser,
+        },
+    },
+};
+
+pub const MAX_WAIT_DAYS: i64 = 90;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+fn to_rfc3339(date: DateTime) -> Result<String, ApiErrors> {
+    date.try_to_rfc3339_string()
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))
+}
+
+/**
+ * When a pending request is approved if the grantor does not answer it
+ */
+pub fn auto_approve_at(access: &EmergencyAccess) -> Option<DateTime> {
+    if access.status != EmergencyStatus::Requested {
+        return None;
+    }
+    let requested_at = access.requested_at?;
+    Some(DateTime::from_millis(
+        requested_at.timestamp_millis() + access.wait_days * MILLIS_PER_DAY,
+    ))
+}
+
+pub fn to_response_access(access: EmergencyAccess) -> Result<ResponseEmergencyAccess, ApiErrors> {
+    Ok(ResponseEmergencyAccess {
+        id: access.id.map(|id| id.to_string()).unwrap_or_default(),
+        auto_approve_at: auto_approve_at(&access).map(to_rfc3339).transpose()?,
+        grantor_username: access.grantor_username,
+        grantee_username: access.grantee_username,
+        access: access.access,
+        wait_days: access.wait_days,
+        status: access.status,
+        requested_at: access.requested_at.map(to_rfc3339).transpose()?,
+        approved_at: access.approved_at.map(to_rfc3339).transpose()?,
+        created_at: to_rfc3339(access.created_at)?,
+    })
+}
+
+/**
+ * Tell a user about a step of an emergency access. Delivery failures are logged,
+ * the step itself is already recorded in the audit log
+ */
+async fn notify(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    user_id: ObjectId,
+    event: NotificationEvent,
+    subject: String,
+    access: &EmergencyAccess,
+) {
+    let email = match db.get_user_by_id(user_id).await {
+        Ok(user) => user.email,
+        Err(err) => {
+            println!("Emergency access notification for user {} failed: {:?}", user_id, err);
+            return;
+        }
+    };
+    let data = match to_response_access(access.clone()) {
+        Ok(access) => json!(access),
+        Err(err) => {
+            println!("Emergency access notification for user {} failed: {:?}", user_id, err);
+            return;
+        }
+    };
+    let notification = Notification {
+        event,
+        user_id: user_id.to_string(),
+        email: Some(email),
+        message: subject.clone(),
+        subject,
+        data,
+        created_at: to_rfc3339(DateTime::now()).unwrap_or_default(),
+    };
+    if let Err(err) = notifier.notify(&notification).await {
+        println!("Emergency access notification for user {} failed: {:?}", user_id, err);
+    }
+}
+
+/**
+ * Get an access the user is part of, the other side of an access cannot tell it exists
+ */
+async fn get_access_of(
+    db: &dyn Storage,
+    access_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<EmergencyAccess, ApiErrors> {
+    let access = db.get_emergency_access(access_id).await?;
+    if access.grantor_id != user_id && access.grantee_id != user_id {
+        return Err(ApiErrors::NotFound("Emergency access not found".to_string()));
+    }
+    Ok(access)
+}
+
+/**
+ * Add an emergency contact to the vault of the grantor, returns the id of the access
+ */
+pub async fn add_contact(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    grantor_id: ObjectId,
+    new_contact: NewEmergencyContact,
+) -> Result<ObjectId, ApiErrors> {
+    let username = new_contact.username.trim();
+    if username.is_empty() {
+        return Err(ApiErrors::BadRequest("Username is required".to_string()));
+    }
+    let wait_days = new_contact.wait_days.unwrap_or_else(get_emergency_wait_days);
+    if !(1..=MAX_WAIT_DAYS).contains(&wait_days) {
+        return Err(ApiErrors::BadRequest(format!(
+            "Wait days must be between 1 and {}",
+            MAX_WAIT_DAYS
+        )));
+    }
+
+    let grantee = db.get_user_by_username(username).await?;
+    let grantee_id = ObjectId::parse_str(&grantee.id)
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+    if grantee_id == grantor_id {
+        return Err(ApiErrors::BadRequest("A user cannot be their own emergency contact".to_string()));
+    }
+    let contacts = db.get_emergency_contacts(grantor_id).await?;
+    if contacts.iter().any(|contact| contact.grantee_id == grantee_id) {
+        return Err(ApiErrors::BadRequest("User is already an emergency contact".to_string()));
+    }
+    let grantor = db.get_user_by_id(grantor_id).await?;
+
+    let mut access = EmergencyAccess {
+        id: None,
+        grantor_id,
+        grantor_username: grantor.username,
+        grantee_id,
+        grantee_username: grantee.username,
+        access: new_contact.access,
+        wait_days,
+        status: EmergencyStatus::Idle,
+        requested_at: None,
+        approved_at: None,
+        created_at: DateTime::now(),
+    };
+    let access_id = db.insert_emergency_access(access.clone()).await?;
+    access.id = Some(access_id);
+
+    record_event(db, AuditAction::EmergencyContactAdded, grantor_id, Some(grantor_id), Some(access_id)).await?;
+    notify(
+        db,
+        notifier,
+        grantee_id,
+        NotificationEvent::EmergencyContactAdded,
+        format!("{} added you as an emergency contact", access.grantor_username),
+        &access,
+    )
+    .await;
+    Ok(access_id)
+}
+
+pub async fn get_contacts(
+    db: &dyn Storage,
+    grantor_id: ObjectId,
+) -> Result<Vec<ResponseEmergencyAccess>, ApiErrors> {
+    db.get_emergency_contacts(grantor_id)
+        .await?
+        .into_iter()
+        .map(to_response_access)
+        .collect()
+}
+
+pub async fn get_grants(
+    db: &dyn Storage,
+    grantee_id: ObjectId,
+) -> Result<Vec<ResponseEmergencyAccess>, ApiErrors> {
+    db.get_emergency_grants(grantee_id)
+        .await?
+        .into_iter()
+        .map(to_response_access)
+        .collect()
+}
+
+/**
+ * Remove an emergency contact, either the grantor or the contact can do it
+ */
+pub async fn remove_access(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    access_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    let access = get_access_of(db, access_id, user_id).await?;
+    db.delete_emergency_access(access_id).await?;
+    record_event(db, AuditAction::EmergencyContactRemoved, access.grantor_id, Some(user_id), Some(access_id)).await?;
+
+    let other = if user_id == access.grantor_id { access.grantee_id } else { access.grantor_id };
+    notify(
+        db,
+        notifier,
+        other,
+        NotificationEvent::EmergencyContactRemoved,
+        format!(
+            "{} is no longer an emergency contact of {}",
+            access.grantee_username, access.grantor_username
+        ),
+        &access,
+    )
+    .await;
+    Ok(())
+}
+
+/**
+ * The emergency contact asks for access to the vault
+ */
+pub async fn request_access(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    access_id: ObjectId,
+    grantee_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    let mut access = get_access_of(db, access_id, grantee_id).await?;
+    if access.grantee_id != grantee_id {
+        return Err(ApiErrors::Forbidden("Only the emergency contact can request access".to_string()));
+    }
+    let now = DateTime::now();
+    if !db.set_emergency_status(access_id, EmergencyStatus::Idle, EmergencyStatus::Requested, now).await? {
+        return Err(ApiErrors::BadRequest("Access has already been requested".to_string()));
+    }
+    access.status = EmergencyStatus::Requested;
+    access.requested_at = Some(now);
+
+    record_event(db, AuditAction::EmergencyAccessRequested, access.grantor_id, Some(grantee_id), Some(access_id)).await?;
+    notify(
+        db,
+        notifier,
+        access.grantor_id,
+        NotificationEvent::EmergencyAccessRequested,
+        format!(
+            "{} requested emergency access to your vault, it is approved in {} days unless you reject it",
+            access.grantee_username, access.wait_days
+        ),
+        &access,
+    )
+    .await;
+    Ok(())
+}
+
+/**
+ * The grantor answers a pending request
+ */
+pub async fn answer_request(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    access_id: ObjectId,
+    grantor_id: ObjectId,
+    approve: bool,
+) -> Result<(), ApiErrors> {
+    let mut access = get_access_of(db, access_id, grantor_id).await?;
+    if access.grantor_id != grantor_id {
+        return Err(ApiErrors::Forbidden("Only the owner of the vault can answer a request".to_string()));
+    }
+    let (status, action, event, answer) = if approve {
+        (EmergencyStatus::Approved, AuditAction::EmergencyAccessApproved, NotificationEvent::EmergencyAccessApproved, "approved")
+    } else {
+        (EmergencyStatus::Idle, AuditAction::EmergencyAccessRejected, NotificationEvent::EmergencyAccessRejected, "rejected")
+    };
+    let now = DateTime::now();
+    if !db.set_emergency_status(access_id, EmergencyStatus::Requested, status, now).await? {
+        return Err(ApiErrors::BadRequest("There is no pending request".to_string()));
+    }
+    access.status = status;
+    if approve {
+        access.approved_at = Some(now);
+    } else {
+        access.requested_at = None;
+    }
+
+    record_event(db, action, grantor_id, Some(grantor_id), Some(access_id)).await?;
+    notify(
+        db,
+        notifier,
+        access.grantee_id,
+        event,
+        format!("{} {} your emergency access request", access.grantor_username, answer),
+        &access,
+    )
+    .await;
+    Ok(())
+}
+
+/**
+ * Approve a request whose waiting period is over, returns false if it was not due or was answered meanwhile
+ */
+async fn auto_approve(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    access: &mut EmergencyAccess,
+    now: DateTime,
+) -> Result<bool, ApiErrors> {
+    let access_id = match (access.id, auto_approve_at(access)) {
+        (Some(access_id), Some(approve_at)) if approve_at <= now => access_id,
+        _ => return Ok(false),
+    };
+    if !db.set_emergency_status(access_id, EmergencyStatus::Requested, EmergencyStatus::Approved, now).await? {
+        return Ok(false);
+    }
+    access.status = EmergencyStatus::Approved;
+    access.approved_at = Some(now);
+
+    record_event(db, AuditAction::EmergencyAccessAutoApproved, access.grantor_id, None, Some(access_id)).await?;
+    let subject = format!(
+        "The emergency access of {} to the vault of {} was approved after {} days without an answer",
+        access.grantee_username, access.grantor_username, access.wait_days
+    );
+    for user_id in [access.grantor_id, access.grantee_id] {
+        notify(db, notifier, user_id, NotificationEvent::EmergencyAccessApproved, subject.clone(), access).await;
+    }
+    Ok(true)
+}
+
+/**
+ * Approve every request whose waiting period is over, returns how many were approved
+ */
+pub async fn approve_due_requests(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    now: DateTime,
+) -> Result<usize, ApiErrors> {
+    let mut approved = 0;
+    for mut access in db.get_requested_emergency_accesses().await? {
+        if auto_approve(db, notifier, &mut access, now).await? {
+            approved += 1;
+        }
+    }
+    Ok(approved)
+}
+
+/**
+ * An access of the contact that is approved, a request past its waiting period is approved on the spot
+ */
+async fn get_approved_access(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    access_id: ObjectId,
+    grantee_id: ObjectId,
+) -> Result<EmergencyAccess, ApiErrors> {
+    let mut access = get_access_of(db, access_id, grantee_id).await?;
+    if access.grantee_id != grantee_id {
+        return Err(ApiErrors::Forbidden("Only the emergency contact can use the access".to_string()));
+    }
+    auto_approve(db, notifier, &mut access, DateTime::now()).await?;
+    if access.status != EmergencyStatus::Approved {
+        return Err(ApiErrors::Forbidden("Emergency access has not been approved".to_string()));
+    }
+    Ok(access)
+}
+
+/**
+ * The records of the grantor, for a contact whose access was approved. Passwords and
+ * secrets are masked unless they are revealed to `reveal_to`
+ */
+pub async fn view_vault(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    access_id: ObjectId,
+    grantee_id: ObjectId,
+    reveal_to: Option<ObjectId>,
+) -> Result<Vec<ResponseRecord>, ApiErrors> {
+    let access = get_approved_access(db, notifier, access_id, grantee_id).await?;
+    let mut stream = db.get_all_user_records(access.grantor_id).await?;
+    let mut records = Vec::new();
+    while let Some(record) = stream.next().await {
+        records.push(to_response_record(reveal_or_mask(db, record?, reveal_to).await?)?);
+    }
+
+    record_event(db, AuditAction::EmergencyVaultViewed, access.grantor_id, Some(grantee_id), Some(access_id)).await?;
+    notify(
+        db,
+        notifier,
+        access.grantor_id,
+        NotificationEvent::EmergencyVaultViewed,
+        format!("{} viewed your vault through emergency access", access.grantee_username),
+        &access,
+    )
+    .await;
+    Ok(records)
+}
+
+/**
+ * Set a new login password for the grantor. The access goes back to idle, the contact
+ * has to request it again to use it once more
+ */
+pub async fn takeover(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    access_id: ObjectId,
+    grantee_id: ObjectId,
+    new_password: String,
+) -> Result<(), ApiErrors> {
+    let mut access = get_approved_access(db, notifier, access_id, grantee_id).await?;
+    if access.access != EmergencyAccessType::Takeover {
+        return Err(ApiErrors::Forbidden("Emergency access is view only".to_string()));
+    }
+    if new_password.is_empty() {
+        return Err(ApiErrors::BadRequest("New password is required".to_string()));
+    }
+
+    let password = bcrypt::hash(new_password).map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+    db.update_user_fields(
+        access.grantor_id,
+        UpdateUser {
+            email: None,
+            new_password: Some(password),
+            password: String::new(),
+        },
+    )
+    .await?;
+    db.set_emergency_status(access_id, EmergencyStatus::Approved, EmergencyStatus::Idle, DateTime::now())
+        .await?;
+    access.status = EmergencyStatus::Idle;
+    access.requested_at = None;
+    access.approved_at = None;
+
+    record_event(db, AuditAction::EmergencyTakeover, access.grantor_id, Some(grantee_id), Some(access_id)).await?;
+    notify(
+        db,
+        notifier,
+        access.grantor_id,
+        NotificationEvent::EmergencyTakeover,
+        format!("{} set a new password for your account through emergency access", access.grantee_username),
+        &access,
+    )
+    .await;
+    Ok(())
+}
+
+/**
+ * Every recorded step of an access, oldest first
+ */
+pub async fn get_history(
+    db: &dyn Storage,
+    access_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<Vec<ResponseAuditEvent>, ApiErrors> {
+    get_access_of(db, access_id, user_id).await?;
+    db.get_audit_events(access_id)
+        .await?
+        .into_iter()
+        .map(to_response_event)
+        .collect()
+}
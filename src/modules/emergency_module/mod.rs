@@ -0,0 +1,172 @@
+pub mod auto_approval;
+pub mod component;
+
+use bson::oid::ObjectId;
+use mongodb::bson::doc;
+use rocket::{http::Status, serde::json::Json, State};
+
+use crate::{
+    drivers::{notifier::notifier_trait::Notifier, storage::storage_trait::Storage},
+    modules::auth_module::reauth::{reveal_to, Reauthenticated},
+    shared::{
+        jwt_service::Token,
+        types::{
+            ApiErrors, CreatedResponse, EmergencyTakeover, NewEmergencyContact, ResponseAuditEvent,
+            ResponseEmergencyAccess, ResponseRecord,
+        },
+    },
+};
+
+/*
+    Routes in this file:
+    POST /emergency/contacts -> Add an emergency contact to the vault of the user
+    GET /emergency/contacts -> Get the emergency contacts of the user
+    GET /emergency/granted -> Get the vaults the user is an emergency contact for
+    DELETE /emergency/:id -> Remove an emergency contact, from either side
+    POST /emergency/:id/request -> Request access to a vault as its emergency contact
+    POST /emergency/:id/approve -> Approve a pending request
+    POST /emergency/:id/reject -> Reject a pending request
+    GET /emergency/:id/vault?include_secrets= -> Get the records of a vault once access is approved
+    POST /emergency/:id/takeover -> Set a new login password for the vault owner
+    GET /emergency/:id/history -> Get every recorded step of an emergency access
+*/
+
+fn parse_id(id: String) -> Result<ObjectId, ApiErrors> {
+    ObjectId::parse_str(id).map_err(|_| ApiErrors::BadRequest("ID is not formatted correctly".to_string()))
+}
+
+#[post("/contacts", data = "<new_contact>")]
+pub async fn add_contact(
+    db: &State<Box<dyn Storage>>,
+    notifier: &State<Box<dyn Notifier>>,
+    new_contact: Json<NewEmergencyContact>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let access_id = component::add_contact(db.inner().as_ref(), notifier.inner().as_ref(), token.id, new_contact.0).await?;
+
+    Ok(CreatedResponse {
+        id: Json(doc! { "id": access_id.to_string() }),
+    })
+}
+
+#[get("/contacts")]
+pub async fn get_contacts(
+    db: &State<Box<dyn Storage>>,
+    token: Token,
+) -> Result<Json<Vec<ResponseEmergencyAccess>>, ApiErrors> {
+    let contacts = component::get_contacts(db.inner().as_ref(), token.id).await?;
+    Ok(Json(contacts))
+}
+
+#[get("/granted")]
+pub async fn get_granted(
+    db: &State<Box<dyn Storage>>,
+    token: Token,
+) -> Result<Json<Vec<ResponseEmergencyAccess>>, ApiErrors> {
+    let grants = component::get_grants(db.inner().as_ref(), token.id).await?;
+    Ok(Json(grants))
+}
+
+#[delete("/<id>")]
+pub async fn remove_access(
+    db: &State<Box<dyn Storage>>,
+    notifier: &State<Box<dyn Notifier>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::remove_access(db.inner().as_ref(), notifier.inner().as_ref(), parse_id(id)?, token.id).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/<id>/request")]
+pub async fn request_access(
+    db: &State<Box<dyn Storage>>,
+    notifier: &State<Box<dyn Notifier>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::request_access(db.inner().as_ref(), notifier.inner().as_ref(), parse_id(id)?, token.id).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/<id>/approve")]
+pub async fn approve_request(
+    db: &State<Box<dyn Storage>>,
+    notifier: &State<Box<dyn Notifier>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::answer_request(db.inner().as_ref(), notifier.inner().as_ref(), parse_id(id)?, token.id, true).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/<id>/reject")]
+pub async fn reject_request(
+    db: &State<Box<dyn Storage>>,
+    notifier: &State<Box<dyn Notifier>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::answer_request(db.inner().as_ref(), notifier.inner().as_ref(), parse_id(id)?, token.id, false).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/<id>/vault?<include_secrets>")]
+pub async fn view_vault(
+    db: &State<Box<dyn Storage>>,
+    notifier: &State<Box<dyn Notifier>>,
+    id: String,
+    include_secrets: Option<bool>,
+    reauth: Result<Reauthenticated, ApiErrors>,
+    token: Token,
+) -> Result<Json<Vec<ResponseRecord>>, ApiErrors> {
+    // Plaintext passwords and secrets need the account password again
+    let reveal_to = reveal_to(include_secrets, reauth)?;
+    let records =
+        component::view_vault(db.inner().as_ref(), notifier.inner().as_ref(), parse_id(id)?, token.id, reveal_to).await?;
+    Ok(Json(records))
+}
+
+#[post("/<id>/takeover", data = "<takeover>")]
+pub async fn takeover(
+    db: &State<Box<dyn Storage>>,
+    notifier: &State<Box<dyn Notifier>>,
+    id: String,
+    takeover: Json<EmergencyTakeover>,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::takeover(
+        db.inner().as_ref(),
+        notifier.inner().as_ref(),
+        parse_id(id)?,
+        token.id,
+        takeover.0.new_password,
+    )
+    .await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/<id>/history")]
+pub async fn get_history(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Json<Vec<ResponseAuditEvent>>, ApiErrors> {
+    let history = component::get_history(db.inner().as_ref(), parse_id(id)?, token.id).await?;
+    Ok(Json(history))
+}
+
+pub fn api() -> Vec<rocket::Route> {
+    rocket::routes![
+        add_contact,
+        get_contacts,
+        get_granted,
+        remove_access,
+        request_access,
+        approve_request,
+        reject_request,
+        view_vault,
+        takeover,
+        get_history
+    ]
+}
@@ -0,0 +1,193 @@
+use std::fs;
+
+use rand::{rngs::OsRng, seq::SliceRandom, Rng};
+
+use crate::shared::types::{ApiErrors, GeneratedSecret, PassphraseOptions, PasswordOptions};
+
+static LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+static UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+static DIGITS: &str = "0123456789";
+static SYMBOLS: &str = "!@#$%^&*()-_=+[]{};:,.<>/?~|";
+static AMBIGUOUS: &str = "Il1O0o|";
+
+static MIN_PASSWORD_LENGTH: usize = 4;
+static MAX_PASSWORD_LENGTH: usize = 128;
+static MIN_PASSPHRASE_WORDS: usize = 3;
+static MAX_PASSPHRASE_WORDS: usize = 20;
+static MAX_SEPARATOR_LENGTH: usize = 5;
+/// Used for passphrases when the list of `PASSPHRASE_WORDLIST` cannot be read
+static BUNDLED_WORDLIST: &str = include_str!("../../../bip39_english_wordlist.txt");
+
+/**
+ * Words used for passphrases, loaded once at startup.
+ * Lines can either be a single word or EFF diceware lines, ie `11111 abacus`
+ */
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    pub fn new(words: Vec<String>) -> Wordlist {
+        let mut words: Vec<String> = words.into_iter().filter(|word| !word.is_empty()).collect();
+        words.sort();
+        words.dedup();
+        Wordlist { words }
+    }
+
+    pub fn parse(contents: &str) -> Wordlist {
+        Wordlist::new(
+            contents
+                .lines()
+                .filter_map(|line| line.split_whitespace().last())
+                .map(|word| word.to_string())
+                .collect(),
+        )
+    }
+
+    /**
+     * The wordlist at `path` when one is set and can be read, the bundled one otherwise
+     */
+    pub fn load(path: Option<&str>) -> Wordlist {
+        let custom = path.and_then(|path| match fs::read_to_string(path) {
+            Ok(contents) => Some(Wordlist::parse(&contents)).filter(|wordlist| wordlist.len() >= 2),
+            Err(err) => {
+                println!(
+                    "Passphrase wordlist {} could not be read, the smaller bundled one is used: {}",
+                    path, err
+                );
+                None
+            }
+        });
+        custom.unwrap_or_else(Wordlist::bundled)
+    }
+
+    /**
+     * The BIP-39 English wordlist, 2048 words. It is only a fallback, its words are worth 11 bits
+     * each against 12.9 for the 7776 words of the EFF large wordlist
+     */
+    pub fn bundled() -> Wordlist {
+        Wordlist::parse(BUNDLED_WORDLIST)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+fn class(chars: &str, exclude_ambiguous: bool) -> Vec<char> {
+    chars
+        .chars()
+        .filter(|c| !exclude_ambiguous || !AMBIGUOUS.contains(*c))
+        .collect()
+}
+
+/**
+ * Generate a random password with a CSPRNG
+ */
+pub fn generate_password(options: &PasswordOptions) -> Result<GeneratedSecret, ApiErrors> {
+    if options.length < MIN_PASSWORD_LENGTH || options.length > MAX_PASSWORD_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Length must be between {} and {}",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        )));
+    }
+
+    let classes = [
+        ("lowercase", options.lowercase, options.min_lowercase, LOWERCASE),
+        ("uppercase", options.uppercase, options.min_uppercase, UPPERCASE),
+        ("digits", options.digits, options.min_digits, DIGITS),
+        ("symbols", options.symbols, options.min_symbols, SYMBOLS),
+    ];
+
+    let mut pool = Vec::new();
+    let mut required = Vec::new();
+    for (name, enabled, min, chars) in classes {
+        if !enabled {
+            if min.unwrap_or(0) > 0 {
+                return Err(ApiErrors::BadRequest(format!("A min count was given for {} but they are disabled", name)));
+            }
+            continue;
+        }
+        let chars = class(chars, options.exclude_ambiguous);
+        required.push((chars.clone(), min.unwrap_or(1)));
+        pool.extend(chars);
+    }
+    if pool.is_empty() {
+        return Err(ApiErrors::BadRequest("At least one character class is required".to_string()));
+    }
+    if required.iter().map(|(_, min)| min).sum::<usize>() > options.length {
+        return Err(ApiErrors::BadRequest("Min counts add up to more than the length".to_string()));
+    }
+
+    let mut rng = OsRng;
+    let mut password: Vec<char> = Vec::with_capacity(options.length);
+    for (chars, min) in &required {
+        for _ in 0..*min {
+            password.push(chars[rng.gen_range(0..chars.len())]);
+        }
+    }
+    while password.len() < options.length {
+        password.push(pool[rng.gen_range(0..pool.len())]);
+    }
+    // The required characters were pushed first, move them to random positions
+    password.shuffle(&mut rng);
+
+    Ok(GeneratedSecret {
+        value: password.into_iter().collect(),
+        entropy_bits: options.length as f64 * (pool.len() as f64).log2(),
+    })
+}
+
+/**
+ * Generate a diceware style passphrase with a CSPRNG
+ */
+pub fn generate_passphrase(options: &PassphraseOptions, wordlist: &Wordlist) -> Result<GeneratedSecret, ApiErrors> {
+    if wordlist.len() < 2 {
+        return Err(ApiErrors::ServerError("Passphrase wordlist is not loaded".to_string()));
+    }
+    if options.words < MIN_PASSPHRASE_WORDS || options.words > MAX_PASSPHRASE_WORDS {
+        return Err(ApiErrors::BadRequest(format!(
+            "Words must be between {} and {}",
+            MIN_PASSPHRASE_WORDS, MAX_PASSPHRASE_WORDS
+        )));
+    }
+    if options.separator.chars().count() > MAX_SEPARATOR_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Separator can be at most {} characters",
+            MAX_SEPARATOR_LENGTH
+        )));
+    }
+
+    let mut rng = OsRng;
+    let mut words: Vec<String> = (0..options.words)
+        .map(|_| wordlist.words[rng.gen_range(0..wordlist.len())].clone())
+        .collect();
+    if options.capitalize {
+        words = words
+            .into_iter()
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => word,
+                }
+            })
+            .collect();
+    }
+
+    let mut entropy_bits = options.words as f64 * (wordlist.len() as f64).log2();
+    if options.include_number {
+        let index = rng.gen_range(0..words.len());
+        words[index].push_str(&rng.gen_range(0..10).to_string());
+        entropy_bits += 10_f64.log2() + (words.len() as f64).log2();
+    }
+
+    Ok(GeneratedSecret {
+        value: words.join(&options.separator),
+        entropy_bits,
+    })
+}
@@ -0,0 +1,38 @@
+use rocket::{serde::json::Json, State};
+
+use crate::shared::{
+    jwt_service::Token,
+    types::{ApiErrors, GeneratedSecret, PassphraseOptions, PasswordOptions},
+};
+use component::Wordlist;
+
+pub mod component;
+
+/*
+    Routes in this file:
+    POST /generate/password -> Generate a random password
+    POST /generate/passphrase -> Generate a random passphrase
+*/
+
+#[post("/password", data = "<options>")]
+pub async fn generate_password(
+    options: Option<Json<PasswordOptions>>,
+    _token: Token,
+) -> Result<Json<GeneratedSecret>, ApiErrors> {
+    let options = options.map(|options| options.0).unwrap_or_default();
+    Ok(Json(component::generate_password(&options)?))
+}
+
+#[post("/passphrase", data = "<options>")]
+pub async fn generate_passphrase(
+    wordlist: &State<Wordlist>,
+    options: Option<Json<PassphraseOptions>>,
+    _token: Token,
+) -> Result<Json<GeneratedSecret>, ApiErrors> {
+    let options = options.map(|options| options.0).unwrap_or_default();
+    Ok(Json(component::generate_passphrase(&options, wordlist)?))
+}
+
+pub fn api() -> Vec<rocket::Route> {
+    rocket::routes![generate_password, generate_passphrase]
+}
@@ -1,4 +1,9 @@
 pub mod auth_module;
+pub mod emergency_module;
+pub mod generate_module;
+pub mod org_module;
 pub mod record_module;
 pub mod search_module;
+pub mod send_module;
+pub mod share_module;
 pub mod user_module;
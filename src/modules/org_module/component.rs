@@ -0,0 +1,378 @@
+use bson::{oid::ObjectId, DateTime};
+use rocket::State;
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    modules::record_module::component::{reveal_or_mask, to_response_record},
+    shared::{
+        policy::{authorize, Action, Resource},
+        types::{
+            ApiErrors, NewCollection, NewInvitation, NewOrganization, OrgCollection, OrgInvitation,
+            OrgMember, OrgRole, Organization, ResponseCollection, ResponseInvitation, ResponseMember,
+            ResponseOrganization, ResponseRecord,
+        },
+    },
+};
+
+pub const MAX_NAME_LENGTH: usize = 100;
+
+fn validate_name(name: &str) -> Result<String, ApiErrors> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Name must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    Ok(name.to_string())
+}
+
+fn to_rfc3339(date: DateTime) -> Result<String, ApiErrors> {
+    date.try_to_rfc3339_string()
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))
+}
+
+fn object_id(id: Option<ObjectId>) -> Result<String, ApiErrors> {
+    id.map(|id| id.to_string())
+        .ok_or_else(|| ApiErrors::ServerError("Object id was not found".to_string()))
+}
+
+/**
+ * Only owners can hand out or take away the owner role
+ */
+fn check_owner_change(actor_role: OrgRole, target_role: OrgRole) -> Result<(), ApiErrors> {
+    if target_role == OrgRole::Owner && actor_role != OrgRole::Owner {
+        return Err(ApiErrors::Forbidden("Only owners can manage owners".to_string()));
+    }
+    Ok(())
+}
+
+/**
+ * An organization always keeps at least one owner
+ */
+async fn check_not_last_owner(db: &dyn Storage, org_id: ObjectId, member: &OrgMember) -> Result<(), ApiErrors> {
+    if member.role != OrgRole::Owner {
+        return Ok(());
+    }
+    let owners = db
+        .get_org_members(org_id)
+        .await?
+        .into_iter()
+        .filter(|member| member.role == OrgRole::Owner)
+        .count();
+    if owners <= 1 {
+        return Err(ApiErrors::BadRequest("An organization needs at least one owner".to_string()));
+    }
+    Ok(())
+}
+
+pub async fn create_organization(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    new_org: NewOrganization,
+) -> Result<ObjectId, ApiErrors> {
+    let name = validate_name(&new_org.name)?;
+    let user = db.get_user_by_id(user_id).await?;
+
+    db.create_organization(
+        Organization {
+            id: None,
+            name,
+            created_by: user_id,
+            created_at: DateTime::now(),
+        },
+        &user.username,
+    )
+    .await
+}
+
+pub async fn get_user_organizations(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+) -> Result<Vec<ResponseOrganization>, ApiErrors> {
+    db.get_user_memberships(user_id)
+        .await?
+        .into_iter()
+        .map(|member| {
+            Ok(ResponseOrganization {
+                id: member.org_id.to_string(),
+                name: member.org_name,
+                role: member.role,
+                joined_at: to_rfc3339(member.joined_at)?,
+            })
+        })
+        .collect()
+}
+
+pub async fn delete_organization(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::DeleteOrganization, Resource::Organization(org_id)).await?;
+    db.delete_organization(org_id).await
+}
+
+pub async fn get_members(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+) -> Result<Vec<ResponseMember>, ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::Read, Resource::Organization(org_id)).await?;
+    db.get_org_members(org_id)
+        .await?
+        .into_iter()
+        .map(|member| {
+            Ok(ResponseMember {
+                user_id: member.user_id.to_string(),
+                username: member.username,
+                role: member.role,
+                joined_at: to_rfc3339(member.joined_at)?,
+            })
+        })
+        .collect()
+}
+
+pub async fn update_member(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+    member_id: ObjectId,
+    role: OrgRole,
+) -> Result<(), ApiErrors> {
+    let access = authorize(db.inner().as_ref(), user_id, Action::ManageMembers, Resource::Organization(org_id)).await?;
+    let actor_role = access.role.unwrap_or(OrgRole::ReadOnly);
+    let member = db
+        .get_org_member(org_id, member_id)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Member not found".to_string()))?;
+
+    check_owner_change(actor_role, member.role)?;
+    check_owner_change(actor_role, role)?;
+    if role != OrgRole::Owner {
+        check_not_last_owner(db.inner().as_ref(), org_id, &member).await?;
+    }
+    db.update_org_member_role(org_id, member_id, role).await
+}
+
+/**
+ * Remove a member, members can also remove themselves to leave the organization
+ */
+pub async fn remove_member(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+    member_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    let action = if user_id == member_id { Action::Read } else { Action::ManageMembers };
+    let access = authorize(db.inner().as_ref(), user_id, action, Resource::Organization(org_id)).await?;
+    let member = db
+        .get_org_member(org_id, member_id)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Member not found".to_string()))?;
+
+    if user_id != member_id {
+        check_owner_change(access.role.unwrap_or(OrgRole::ReadOnly), member.role)?;
+    }
+    check_not_last_owner(db.inner().as_ref(), org_id, &member).await?;
+    db.delete_org_member(org_id, member_id).await
+}
+
+pub async fn invite_member(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+    invitation: NewInvitation,
+) -> Result<ObjectId, ApiErrors> {
+    let access = authorize(db.inner().as_ref(), user_id, Action::ManageMembers, Resource::Organization(org_id)).await?;
+    check_owner_change(access.role.unwrap_or(OrgRole::ReadOnly), invitation.role)?;
+
+    let invitee = db.get_user_by_username(invitation.username.trim()).await?;
+    let invitee_id = ObjectId::parse_str(&invitee.id)
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+    if db.get_org_member(org_id, invitee_id).await?.is_some() {
+        return Err(ApiErrors::BadRequest(format!("{} is already a member", invitee.username)));
+    }
+    let pending = db.get_org_invitations(org_id).await?;
+    if pending.iter().any(|pending| pending.user_id == invitee_id) {
+        return Err(ApiErrors::BadRequest(format!("{} has already been invited", invitee.username)));
+    }
+
+    let org = db.get_organization(org_id).await?;
+    let inviter = db.get_user_by_id(user_id).await?;
+    db.insert_org_invitation(OrgInvitation {
+        id: None,
+        org_id,
+        org_name: org.name,
+        user_id: invitee_id,
+        username: invitee.username,
+        role: invitation.role,
+        invited_by: inviter.username,
+        created_at: DateTime::now(),
+    })
+    .await
+}
+
+fn to_response_invitation(invitation: OrgInvitation) -> Result<ResponseInvitation, ApiErrors> {
+    Ok(ResponseInvitation {
+        id: object_id(invitation.id)?,
+        org_id: invitation.org_id.to_string(),
+        org_name: invitation.org_name,
+        username: invitation.username,
+        role: invitation.role,
+        invited_by: invitation.invited_by,
+        created_at: to_rfc3339(invitation.created_at)?,
+    })
+}
+
+pub async fn get_org_invitations(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+) -> Result<Vec<ResponseInvitation>, ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::ManageMembers, Resource::Organization(org_id)).await?;
+    db.get_org_invitations(org_id)
+        .await?
+        .into_iter()
+        .map(to_response_invitation)
+        .collect()
+}
+
+pub async fn cancel_invitation(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+    invitation_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::ManageMembers, Resource::Organization(org_id)).await?;
+    let invitation = db.get_org_invitation(invitation_id).await?;
+    if invitation.org_id != org_id {
+        return Err(ApiErrors::NotFound("Invitation not found".to_string()));
+    }
+    db.delete_org_invitation(invitation_id).await
+}
+
+pub async fn get_user_invitations(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+) -> Result<Vec<ResponseInvitation>, ApiErrors> {
+    db.get_user_invitations(user_id)
+        .await?
+        .into_iter()
+        .map(to_response_invitation)
+        .collect()
+}
+
+/**
+ * Get an invitation addressed to the user, other users' invitations are not found
+ */
+async fn own_invitation(
+    db: &dyn Storage,
+    user_id: ObjectId,
+    invitation_id: ObjectId,
+) -> Result<OrgInvitation, ApiErrors> {
+    let invitation = db.get_org_invitation(invitation_id).await?;
+    if invitation.user_id != user_id {
+        return Err(ApiErrors::NotFound("Invitation not found".to_string()));
+    }
+    Ok(invitation)
+}
+
+pub async fn accept_invitation(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    invitation_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    let invitation = own_invitation(db.inner().as_ref(), user_id, invitation_id).await?;
+    if db.get_org_member(invitation.org_id, user_id).await?.is_none() {
+        db.insert_org_member(OrgMember {
+            id: None,
+            org_id: invitation.org_id,
+            org_name: invitation.org_name,
+            user_id,
+            username: invitation.username,
+            role: invitation.role,
+            joined_at: DateTime::now(),
+        })
+        .await?;
+    }
+    db.delete_org_invitation(invitation_id).await
+}
+
+pub async fn decline_invitation(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    invitation_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    own_invitation(db.inner().as_ref(), user_id, invitation_id).await?;
+    db.delete_org_invitation(invitation_id).await
+}
+
+pub async fn create_collection(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+    new_collection: NewCollection,
+) -> Result<ObjectId, ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::ManageCollections, Resource::Organization(org_id)).await?;
+    let name = validate_name(&new_collection.name)?;
+    let collections = db.get_org_collections(org_id).await?;
+    if collections.iter().any(|collection| collection.name.eq_ignore_ascii_case(&name)) {
+        return Err(ApiErrors::BadRequest(format!("A collection named {} already exists", name)));
+    }
+    db.insert_org_collection(OrgCollection {
+        id: None,
+        org_id,
+        name,
+        created_at: DateTime::now(),
+    })
+    .await
+}
+
+pub async fn get_collections(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+) -> Result<Vec<ResponseCollection>, ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::Read, Resource::Organization(org_id)).await?;
+    db.get_org_collections(org_id)
+        .await?
+        .into_iter()
+        .map(|collection| {
+            Ok(ResponseCollection {
+                id: object_id(collection.id)?,
+                org_id: collection.org_id.to_string(),
+                name: collection.name,
+                created_at: to_rfc3339(collection.created_at)?,
+            })
+        })
+        .collect()
+}
+
+pub async fn delete_collection(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+    collection_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::ManageCollections, Resource::Organization(org_id)).await?;
+    db.delete_org_collection(org_id, collection_id).await
+}
+
+/**
+ * Records of an organization, passwords and secrets are masked unless they are revealed to `reveal_to`
+ */
+pub async fn get_org_records(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    org_id: ObjectId,
+    collection_id: Option<ObjectId>,
+    reveal_to: Option<ObjectId>,
+) -> Result<Vec<ResponseRecord>, ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::Read, Resource::Organization(org_id)).await?;
+    let mut records = Vec::new();
+    for record in db.get_org_records(org_id, collection_id).await? {
+        records.push(to_response_record(reveal_or_mask(db.inner().as_ref(), record, reveal_to).await?)?);
+    }
+    Ok(records)
+}
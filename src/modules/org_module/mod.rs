@@ -0,0 +1,239 @@
+pub mod component;
+
+use bson::oid::ObjectId;
+use mongodb::bson::doc;
+use rocket::{http::Status, serde::json::Json, State};
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    modules::auth_module::reauth::{reveal_to, Reauthenticated},
+    shared::{
+        jwt_service::Token,
+        types::{
+            ApiErrors, CreatedResponse, NewCollection, NewInvitation, NewOrganization, ResponseCollection,
+            ResponseInvitation, ResponseMember, ResponseOrganization, ResponseRecord, UpdateMember,
+        },
+    },
+};
+
+/*
+    Routes in this file:
+    POST /org -> Create an organization, the creator becomes its owner
+    GET /org -> Get the organizations of the user
+    DELETE /org/:org_id -> Delete an organization with its records
+    GET /org/:org_id/members -> Get the members of an organization
+    PATCH /org/:org_id/members/:user_id -> Change the role of a member
+    DELETE /org/:org_id/members/:user_id -> Remove a member, or leave the organization
+    POST /org/:org_id/invitations -> Invite a user by username
+    GET /org/:org_id/invitations -> Get the pending invitations of an organization
+    DELETE /org/:org_id/invitations/:id -> Cancel an invitation
+    GET /org/invitations -> Get the invitations sent to the user
+    POST /org/invitations/:id/accept -> Join the organization of an invitation
+    POST /org/invitations/:id/decline -> Decline an invitation
+    POST /org/:org_id/collections -> Create a collection
+    GET /org/:org_id/collections -> Get the collections of an organization
+    DELETE /org/:org_id/collections/:id -> Delete a collection, its records stay in the organization
+    GET /org/:org_id/records?collection_id=&include_secrets= -> Get the records of an organization
+*/
+
+fn parse_id(id: &str) -> Result<ObjectId, ApiErrors> {
+    ObjectId::parse_str(id).map_err(|_| ApiErrors::BadRequest("ID is not formatted correctly".to_string()))
+}
+
+fn created(id: ObjectId) -> CreatedResponse {
+    CreatedResponse {
+        id: Json(doc! { "id": id.to_string() }),
+    }
+}
+
+#[post("/", data = "<new_org>")]
+pub async fn create_organization(
+    db: &State<Box<dyn Storage>>,
+    new_org: Json<NewOrganization>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let org_id = component::create_organization(db, token.id, new_org.0).await?;
+    Ok(created(org_id))
+}
+
+#[get("/")]
+pub async fn get_organizations(
+    db: &State<Box<dyn Storage>>,
+    token: Token,
+) -> Result<Json<Vec<ResponseOrganization>>, ApiErrors> {
+    let orgs = component::get_user_organizations(db, token.id).await?;
+    Ok(Json(orgs))
+}
+
+#[delete("/<org_id>")]
+pub async fn delete_organization(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::delete_organization(db, token.id, parse_id(&org_id)?).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/<org_id>/members")]
+pub async fn get_members(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    token: Token,
+) -> Result<Json<Vec<ResponseMember>>, ApiErrors> {
+    let members = component::get_members(db, token.id, parse_id(&org_id)?).await?;
+    Ok(Json(members))
+}
+
+#[patch("/<org_id>/members/<user_id>", data = "<update>")]
+pub async fn update_member(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    user_id: String,
+    update: Json<UpdateMember>,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::update_member(db, token.id, parse_id(&org_id)?, parse_id(&user_id)?, update.role).await?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/<org_id>/members/<user_id>")]
+pub async fn remove_member(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    user_id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::remove_member(db, token.id, parse_id(&org_id)?, parse_id(&user_id)?).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/<org_id>/invitations", data = "<invitation>")]
+pub async fn invite_member(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    invitation: Json<NewInvitation>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let invitation_id = component::invite_member(db, token.id, parse_id(&org_id)?, invitation.0).await?;
+    Ok(created(invitation_id))
+}
+
+#[get("/<org_id>/invitations")]
+pub async fn get_org_invitations(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    token: Token,
+) -> Result<Json<Vec<ResponseInvitation>>, ApiErrors> {
+    let invitations = component::get_org_invitations(db, token.id, parse_id(&org_id)?).await?;
+    Ok(Json(invitations))
+}
+
+#[delete("/<org_id>/invitations/<id>")]
+pub async fn cancel_invitation(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::cancel_invitation(db, token.id, parse_id(&org_id)?, parse_id(&id)?).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/invitations")]
+pub async fn get_user_invitations(
+    db: &State<Box<dyn Storage>>,
+    token: Token,
+) -> Result<Json<Vec<ResponseInvitation>>, ApiErrors> {
+    let invitations = component::get_user_invitations(db, token.id).await?;
+    Ok(Json(invitations))
+}
+
+#[post("/invitations/<id>/accept")]
+pub async fn accept_invitation(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::accept_invitation(db, token.id, parse_id(&id)?).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/invitations/<id>/decline")]
+pub async fn decline_invitation(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::decline_invitation(db, token.id, parse_id(&id)?).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/<org_id>/collections", data = "<new_collection>")]
+pub async fn create_collection(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    new_collection: Json<NewCollection>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let collection_id = component::create_collection(db, token.id, parse_id(&org_id)?, new_collection.0).await?;
+    Ok(created(collection_id))
+}
+
+#[get("/<org_id>/collections")]
+pub async fn get_collections(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    token: Token,
+) -> Result<Json<Vec<ResponseCollection>>, ApiErrors> {
+    let collections = component::get_collections(db, token.id, parse_id(&org_id)?).await?;
+    Ok(Json(collections))
+}
+
+#[delete("/<org_id>/collections/<id>")]
+pub async fn delete_collection(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    component::delete_collection(db, token.id, parse_id(&org_id)?, parse_id(&id)?).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/<org_id>/records?<collection_id>&<include_secrets>")]
+pub async fn get_org_records(
+    db: &State<Box<dyn Storage>>,
+    org_id: String,
+    collection_id: Option<String>,
+    include_secrets: Option<bool>,
+    reauth: Result<Reauthenticated, ApiErrors>,
+    token: Token,
+) -> Result<Json<Vec<ResponseRecord>>, ApiErrors> {
+    let collection_id = collection_id.as_deref().map(parse_id).transpose()?;
+    // Plaintext passwords and secrets need the account password again
+    let reveal_to = reveal_to(include_secrets, reauth)?;
+    let records = component::get_org_records(db, token.id, parse_id(&org_id)?, collection_id, reveal_to).await?;
+    Ok(Json(records))
+}
+
+pub fn api() -> Vec<rocket::Route> {
+    rocket::routes![
+        create_organization,
+        get_organizations,
+        delete_organization,
+        get_members,
+        update_member,
+        remove_member,
+        invite_member,
+        get_org_invitations,
+        cancel_invitation,
+        get_user_invitations,
+        accept_invitation,
+        decline_invitation,
+        create_collection,
+        get_collections,
+        delete_collection,
+        get_org_records
+    ]
+}
@@ -0,0 +1,267 @@
+/*
+    Files attached to records, stored in GridFS (`attachments.files` and `attachments.chunks`).
+    Every file gets its own key, chunks are encrypted one at a time on upload and decrypted one
+    at a time on download so a file is never held in memory as a whole
+*/
+use std::{io, pin::Pin};
+
+use bson::{oid::ObjectId, spec::BinarySubtype, Binary, DateTime};
+use rocket::{
+    data::ToByteUnit,
+    futures::{stream, Stream, StreamExt},
+    http::ContentType,
+    response::{self, Responder},
+    tokio::io::{AsyncRead, AsyncReadExt},
+    Data, Request, Response, State,
+};
+use tokio_util::io::StreamReader;
+
+use crate::{
+    drivers::storage::storage_trait::{AttachmentChunks, Storage},
+    shared::{
+        encryption::{decrypt_chunk, decrypt_file_key, encrypt_chunk, new_file_key},
+        env_config::get_attachment_quota_bytes,
+        policy::{authorize, Action, Resource},
+        types::{ApiErrors, Attachment, AttachmentChunk, AttachmentMetadata, ResponseAttachment},
+    },
+};
+
+/// Size of a stored chunk, the GridFS default
+pub const CHUNK_SIZE: usize = 255 * 1024;
+/// Size of a chunk before encryption, AES-GCM adds a 16 byte tag
+const PLAIN_CHUNK_SIZE: usize = CHUNK_SIZE - 16;
+pub const MAX_FILE_NAME_LENGTH: usize = 255;
+
+/**
+ * A download, its chunks are decrypted as the response is written
+ */
+pub struct AttachmentDownload {
+    pub attachment: Attachment,
+    pub body: Pin<Box<dyn AsyncRead + Send>>,
+}
+
+impl<'r> Responder<'r, 'static> for AttachmentDownload {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let content_type =
+            ContentType::parse_flexible(&self.attachment.metadata.content_type).unwrap_or(ContentType::Binary);
+        let file_name: String = self
+            .attachment
+            .filename
+            .chars()
+            .filter(|c| !c.is_control() && *c != '"' && *c != '\\')
+            .collect();
+        Response::build()
+            .header(content_type)
+            .raw_header("Content-Disposition", format!("attachment; filename=\"{}\"", file_name))
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .raw_header("Cache-Control", "no-store")
+            .streamed_body(self.body)
+            .ok()
+    }
+}
+
+pub fn to_response_attachment(attachment: Attachment) -> Result<ResponseAttachment, ApiErrors> {
+    Ok(ResponseAttachment {
+        id: attachment.id.to_string(),
+        record_id: attachment.metadata.record_id.to_string(),
+        file_name: attachment.filename,
+        content_type: attachment.metadata.content_type,
+        size: attachment.metadata.size,
+        uploaded_at: attachment
+            .upload_date
+            .try_to_rfc3339_string()
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+    })
+}
+
+/**
+ * Read up to one chunk, shorter only at the end of the file
+ */
+async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>, ApiErrors> {
+    let mut chunk = Vec::with_capacity(PLAIN_CHUNK_SIZE);
+    reader
+        .take(PLAIN_CHUNK_SIZE as u64)
+        .read_to_end(&mut chunk)
+        .await
+        .map_err(|err| ApiErrors::BadRequest(format!("File could not be read: {}", err)))?;
+    Ok(chunk)
+}
+
+/**
+ * Encrypt and store the chunks of a file, returns its size before and after encryption.
+ * One chunk is read ahead to know which one is the last
+ */
+async fn write_chunks<R: AsyncRead + Unpin>(
+    db: &dyn Storage,
+    attachment_id: ObjectId,
+    key: &[u8; 32],
+    reader: &mut R,
+    max_bytes: i64,
+) -> Result<(i64, i64), ApiErrors> {
+    let mut chunk = read_chunk(reader).await?;
+    if chunk.is_empty() {
+        return Err(ApiErrors::BadRequest("File is empty".to_string()));
+    }
+    let (mut n, mut size, mut length) = (0u64, 0i64, 0i64);
+    loop {
+        size += chunk.len() as i64;
+        if size > max_bytes {
+            return Err(ApiErrors::PayloadTooLarge("Attachment storage quota exceeded".to_string()));
+        }
+        let next = read_chunk(reader).await?;
+        let last = next.is_empty();
+        let data = encrypt_chunk(key, n, last, &chunk)?;
+        length += data.len() as i64;
+        db.insert_attachment_chunk(AttachmentChunk {
+            id: None,
+            files_id: attachment_id,
+            n: n as i32,
+            data: Binary { subtype: BinarySubtype::Generic, bytes: data },
+        })
+        .await?;
+        if last {
+            return Ok((size, length));
+        }
+        chunk = next;
+        n += 1;
+    }
+}
+
+/**
+ * Attach a file to a record, returns the id of the attachment. The file counts against the
+ * quota of the owner of the record
+ */
+pub async fn upload_attachment(
+    db: &State<Box<dyn Storage>>,
+    record_id: ObjectId,
+    user_id: ObjectId,
+    file_name: String,
+    content_type: String,
+    data: Data<'_>,
+) -> Result<ObjectId, ApiErrors> {
+    let file_name = file_name.trim().to_string();
+    if file_name.is_empty() || file_name.chars().count() > MAX_FILE_NAME_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "File name must be between 1 and {} characters",
+            MAX_FILE_NAME_LENGTH
+        )));
+    }
+    let access = authorize(db.inner().as_ref(), user_id, Action::Write, Resource::Record(record_id)).await?;
+    let quota = get_attachment_quota_bytes();
+    let remaining = quota - db.get_used_attachment_bytes(access.owner_id).await?;
+    if remaining <= 0 {
+        return Err(ApiErrors::PayloadTooLarge("Attachment storage quota exceeded".to_string()));
+    }
+
+    let attachment_id = ObjectId::new();
+    let (key, stored_key) = new_file_key();
+    // One byte over the quota is enough to tell the file does not fit
+    let mut reader = data.open((remaining as u64 + 1).bytes());
+    let (size, length) = match write_chunks(db.inner().as_ref(), attachment_id, &key, &mut reader, remaining).await {
+        Ok(sizes) => sizes,
+        Err(err) => {
+            if let Err(cleanup_err) = db.delete_attachment(attachment_id).await {
+                println!("Chunks of attachment {} were not removed: {:?}", attachment_id, cleanup_err);
+            }
+            return Err(err);
+        }
+    };
+
+    db.insert_attachment(Attachment {
+        id: attachment_id,
+        length,
+        chunk_size: CHUNK_SIZE as i32,
+        upload_date: DateTime::now(),
+        filename: file_name,
+        metadata: AttachmentMetadata {
+            record_id,
+            user_id: access.owner_id,
+            uploaded_by: user_id,
+            content_type,
+            size,
+            key: stored_key,
+        },
+    })
+    .await?;
+
+    // Uploads running at the same time each saw the quota left before any of them finished,
+    // the ones that tip the total over it are removed again
+    if db.get_used_attachment_bytes(access.owner_id).await? > quota {
+        db.delete_attachment(attachment_id).await?;
+        return Err(ApiErrors::PayloadTooLarge("Attachment storage quota exceeded".to_string()));
+    }
+    Ok(attachment_id)
+}
+
+pub async fn get_attachments(
+    db: &State<Box<dyn Storage>>,
+    record_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<Vec<ResponseAttachment>, ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::Read, Resource::Record(record_id)).await?;
+    db.get_record_attachments(record_id)
+        .await?
+        .into_iter()
+        .map(to_response_attachment)
+        .collect()
+}
+
+/**
+ * Decrypt chunks in order as they are read. A missing, reordered or tampered chunk ends
+ * the download with an error instead of a file that looks complete
+ */
+fn decrypt_chunks(
+    chunks: AttachmentChunks,
+    key: [u8; 32],
+    chunk_count: i64,
+) -> impl Stream<Item = io::Result<io::Cursor<Vec<u8>>>> + Send {
+    stream::unfold((chunks, 0i64), move |(mut chunks, n)| async move {
+        if n >= chunk_count {
+            return None;
+        }
+        let decrypted = match chunks.next().await {
+            Some(Ok(chunk)) if chunk.n as i64 == n => {
+                decrypt_chunk(&key, n as u64, n == chunk_count - 1, &chunk.data.bytes)
+            }
+            Some(Ok(_)) | None => Err(ApiErrors::ServerError(format!("Chunk {} of the file is missing", n))),
+            Some(Err(err)) => Err(err),
+        };
+        match decrypted {
+            Ok(bytes) => Some((Ok(io::Cursor::new(bytes)), (chunks, n + 1))),
+            Err(err) => Some((
+                Err(io::Error::new(io::ErrorKind::InvalidData, err.message())),
+                (chunks, chunk_count),
+            )),
+        }
+    })
+}
+
+pub async fn download_attachment(
+    db: &State<Box<dyn Storage>>,
+    record_id: ObjectId,
+    attachment_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<AttachmentDownload, ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::Read, Resource::Record(record_id)).await?;
+    let attachment = db.get_attachment(attachment_id, record_id).await?;
+    let key = decrypt_file_key(&attachment.metadata.key)?;
+    let chunk_size = attachment.chunk_size.max(1) as i64;
+    let chunk_count = (attachment.length + chunk_size - 1) / chunk_size;
+    let chunks = db.get_attachment_chunks(attachment_id).await?;
+
+    Ok(AttachmentDownload {
+        attachment,
+        body: Box::pin(StreamReader::new(decrypt_chunks(chunks, key, chunk_count))),
+    })
+}
+
+pub async fn delete_attachment(
+    db: &State<Box<dyn Storage>>,
+    record_id: ObjectId,
+    attachment_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    authorize(db.inner().as_ref(), user_id, Action::Write, Resource::Record(record_id)).await?;
+    db.get_attachment(attachment_id, record_id).await?;
+    db.delete_attachment(attachment_id).await
+}
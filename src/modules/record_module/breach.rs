@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use bson::{oid::ObjectId, DateTime};
+use rocket::tokio::{self, time};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    drivers::{
+        breach::{breach_trait::BreachSource, sha1_hex},
+        storage::storage_trait::Storage,
+    },
+    shared::types::{ApiErrors, BreachStatus, RecordTypes},
+};
+
+use super::{
+    component::get_decrypted_user_records,
+    health::{breached_record, BreachedRecord},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BreachCheckReport {
+    pub checked: usize,
+    pub compromised: Vec<BreachedRecord>,
+}
+
+/**
+ * Check the passwords and emails of every password record of a user and store the results
+ */
+pub async fn check_user_breaches(
+    db: &dyn Storage,
+    source: &dyn BreachSource,
+    user_id: ObjectId,
+) -> Result<BreachCheckReport, ApiErrors> {
+    let records = get_decrypted_user_records(db, user_id).await?;
+    let mut checked = 0;
+    let mut compromised = Vec::new();
+
+    for mut record in records.into_iter().filter(|record| record.record_type == RecordTypes::Password) {
+        let record_id = match record.id {
+            Some(id) => id,
+            None => continue,
+        };
+        let password_count = match &record.password {
+            Some(password) => source.password_breaches(&sha1_hex(password)).await?,
+            None => 0,
+        };
+        let email_count = match &record.email {
+            Some(email) => source.email_breaches(&sha1_hex(&email.trim().to_lowercase())).await?,
+            None => 0,
+        };
+
+        let breach = BreachStatus {
+            password_count: password_count as i64,
+            email_count: email_count as i64,
+            checked_at: DateTime::now(),
+        };
+        db.set_record_breach(record_id, user_id, record.revision, breach.clone()).await?;
+        checked += 1;
+
+        record.breach = Some(breach);
+        if let Some(breached) = breached_record(&record) {
+            compromised.push(breached);
+        }
+    }
+
+    Ok(BreachCheckReport { checked, compromised })
+}
+
+/**
+ * Check every vault, a failing vault is logged and skipped
+ */
+async fn check_every_vault(db: &dyn Storage, source: &dyn BreachSource) {
+    let user_ids = match db.get_record_owner_ids().await {
+        Ok(user_ids) => user_ids,
+        Err(err) => {
+            println!("Scheduled breach check could not list vaults: {:?}", err);
+            return;
+        }
+    };
+    for user_id in user_ids {
+        if let Err(err) = check_user_breaches(db, source, user_id).await {
+            println!("Scheduled breach check of user {} failed: {:?}", user_id, err);
+        }
+    }
+}
+
+/**
+ * Check every vault now and then every `interval_hours`, an interval of 0 turns the job off
+ */
+pub fn spawn_breach_monitor(
+    db: Box<dyn Storage>,
+    source: Box<dyn BreachSource>,
+    interval_hours: u64,
+) {
+    if interval_hours == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(interval_hours * 60 * 60));
+        loop {
+            interval.tick().await;
+            check_every_vault(db.as_ref(), source.as_ref()).await;
+        }
+    });
+}
@@ -1,126 +1,392 @@
 use crate::{
-    drivers::mongodb::mongo_trait::TMongoClient,
+    drivers::storage::storage_trait::Storage,
+    modules::generate_module::component::generate_password,
     shared::{
-        encryption::{decrypt_password, encrypt_data},
-        types::{ApiErrors, Record, ResponseRecord, RecordTypes, UpdateRecord},
+        encryption::{decrypt_password, encrypt_data, encrypt_with_passphrase},
+        policy::{authorize, Action, Resource},
+        audit::record_event,
+        env_config::{get_bulk_max_operations, get_rotation_reminder_days},
+        etag::IfMatch,
+        pagination::{Page, PageRequest},
+        types::{
+            ApiErrors, AuditAction, BulkOperation, BulkRequest, BulkResponse, BulkResult, ImportIssue,
+            ImportReport, ImportedRecord, NewRecord, PasswordInput, Record, RecordTypes, RecordWrite,
+            RecordWriteResult, ResponseBreachStatus, ResponseRecord, ResponseRevealedRecord, UpdateRecord,
+        },
+    },
+};
+use super::{
+    export::{
+        self, EncryptedVaultExport, ExportFormat, ExportResponse, ExportedRecord, VaultExport,
+        EXPORT_VERSION, MIN_PASSPHRASE_LENGTH,
     },
+    health::{self, HealthReport},
+    import::{self, ImportFormat},
+    rotation::{self, DueRecord, MAX_ROTATION_PERIOD_DAYS},
+    uri_match::{self, MatchedRecord, UriMatcher},
+};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use std::collections::HashSet;
+use rocket::{
+    futures::stream::StreamExt,
+    http::{ContentType, Status},
+    serde::json::{serde_json, Json},
+    State,
 };
-use mongodb::bson::oid::ObjectId;
-use rocket::{futures::stream::StreamExt, State};
+
+pub const MAX_TAGS: usize = 20;
+pub const MAX_TAG_LENGTH: usize = 50;
+
+fn validate_tags(tags: &[String]) -> Result<(), ApiErrors> {
+    if tags.len() > MAX_TAGS {
+        return Err(ApiErrors::BadRequest(format!("A record can have at most {} tags", MAX_TAGS)));
+    }
+    if tags.iter().any(|tag| tag.trim().is_empty() || tag.chars().count() > MAX_TAG_LENGTH) {
+        return Err(ApiErrors::BadRequest(format!(
+            "Tags must be between 1 and {} characters",
+            MAX_TAG_LENGTH
+        )));
+    }
+    Ok(())
+}
 
 /**
- * Create a password record
+ * Validate the per type invariants of a record
  */
-pub(crate) async fn create_record(
-    db: &State<Box<dyn TMongoClient>>,
-    mut new_record: Record,
-    id: ObjectId,
-) -> Result<ObjectId, ApiErrors> {
-    match new_record.record_type {
+pub(crate) fn validate_record(record: &Record) -> Result<(), ApiErrors> {
+    if let Some(tags) = &record.tags {
+        validate_tags(tags)?;
+    }
+    match record.record_type {
         RecordTypes::Password => {
-            if new_record.password.is_none() || new_record.service.is_none() {
+            if record.password.is_none() || record.service.is_none() {
                 return Err(ApiErrors::BadRequest("Password and Service are required for a password record".to_string()));
             }
-            // Password is being created validate that either username or email is provided
-            if new_record.email.is_none() && new_record.username.is_none() {
+            // Validate that either username or email is provided
+            if record.email.is_none() && record.username.is_none() {
                 return Err(ApiErrors::BadRequest("Email or username is required for a password record".to_string()));
             }
+            if let Some(period) = record.rotation_period {
+                if !(1..=MAX_ROTATION_PERIOD_DAYS).contains(&period) {
+                    return Err(ApiErrors::BadRequest(format!(
+                        "Rotation period must be between 1 and {} days",
+                        MAX_ROTATION_PERIOD_DAYS
+                    )));
+                }
+            }
+            if let Some(uris) = &record.uris {
+                uri_match::validate_uris(uris)?;
+            }
             // Validate key and secret have not been passed in
-            if new_record.key.is_some() || new_record.secret.is_some() {
+            if record.key.is_some() || record.secret.is_some() {
                 return Err(ApiErrors::BadRequest("Cannot create a secret and password record at the same time".to_string()));
             }
-            if let Some(password) = new_record.password {
-                new_record.password = Some(encrypt_data(&password));
-            }
         },
         RecordTypes::Secret => {
-            // Secret is being created, validate that a key is provided
-            if new_record.key.is_none() {
+            // Validate that a key is provided
+            if record.key.is_none() {
                 return Err(ApiErrors::BadRequest("Secret record requires a key".to_string()));
             }
             // Validate password record info was not passed in
-            if new_record.email.is_some() || new_record.password.is_some() || new_record.username.is_some() {
+            if record.email.is_some() || record.password.is_some() || record.username.is_some() || record.two_factor.is_some() || record.rotation_period.is_some() || record.uris.is_some() {
                 return Err(ApiErrors::BadRequest("Cannot create a secret and password record at the same time".to_string()));
             }
-            if let Some(secret) = new_record.secret {
-                new_record.secret = Some(encrypt_data(&secret));
-            } else {
+            if record.secret.is_none() {
                 return Err(ApiErrors::BadRequest("Secret is required for a secret record".to_string()));
             }
         }
     }
+    Ok(())
+}
+
+/**
+ * Validate and encrypt a new record so it is ready to be stored
+ */
+pub(crate) fn prepare_record(mut new_record: Record, id: ObjectId) -> Result<Record, ApiErrors> {
+    validate_record(&new_record)?;
+
+    if let Some(password) = new_record.password {
+        new_record.password = Some(encrypt_data(&password));
+    }
+    if let Some(secret) = new_record.secret {
+        new_record.secret = Some(encrypt_data(&secret));
+    }
 
+    let now = DateTime::now();
     new_record.user_id = Some(id);
+    new_record.revision = Some(1);
+    new_record.created_at = Some(now);
+    new_record.updated_at = Some(now);
+    if new_record.password.is_some() {
+        new_record.password_changed_at = Some(now);
+    }
+
+    Ok(new_record)
+}
+
+/**
+ * Turn the body of a new record into a Record, generating its password if asked to.
+ * Returns the generated password so it can be handed back to the client
+ */
+pub(crate) fn resolve_new_record(new_record: NewRecord) -> Result<(Record, Option<String>), ApiErrors> {
+    let parse_id = |id: &Option<String>| {
+        id.as_deref()
+            .map(|id| ObjectId::parse_str(id).map_err(|_| ApiErrors::BadRequest(format!("{} is not a valid object id", id))))
+            .transpose()
+    };
+    let org_id = parse_id(&new_record.org_id)?;
+    let collection_id = parse_id(&new_record.collection_id)?;
+    if collection_id.is_some() && org_id.is_none() {
+        return Err(ApiErrors::BadRequest("A collection can only be set along with its organization".to_string()));
+    }
+
+    let (mut record, generated_password) = match new_record.password.clone() {
+        Some(PasswordInput::Generate { generate }) => {
+            let password = generate_password(&generate)?.value;
+            (new_record.into_record(Some(password.clone())), Some(password))
+        }
+        Some(PasswordInput::Value(password)) => (new_record.into_record(Some(password)), None),
+        None => (new_record.into_record(None), None),
+    };
+    record.org_id = org_id;
+    record.collection_id = collection_id;
+    Ok((record, generated_password))
+}
+
+/**
+ * Check the user can add a record to the organization and collection it is created in
+ */
+async fn authorize_new_record(db: &dyn Storage, record: &Record, user_id: ObjectId) -> Result<(), ApiErrors> {
+    let Some(org_id) = record.org_id else {
+        return Ok(());
+    };
+    authorize(db, user_id, Action::Write, Resource::Organization(org_id)).await?;
+    if let Some(collection_id) = record.collection_id {
+        let collections = db.get_org_collections(org_id).await?;
+        if !collections.iter().any(|collection| collection.id == Some(collection_id)) {
+            return Err(ApiErrors::NotFound("Collection not found".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Create a password record
+ */
+pub(crate) async fn create_record(
+    db: &State<Box<dyn Storage>>,
+    new_record: NewRecord,
+    id: ObjectId,
+) -> Result<(ObjectId, Option<String>), ApiErrors> {
+    let (new_record, generated_password) = resolve_new_record(new_record)?;
+    authorize_new_record(db.inner().as_ref(), &new_record, id).await?;
+    let new_record = prepare_record(new_record, id)?;
 
     // Store record in database
     let record_id = db.insert_record(new_record).await?;
-    Ok(record_id)
+    Ok((record_id, generated_password))
 }
 
-pub async fn update_record(
-    db: &State<Box<dyn TMongoClient>>,
+/**
+ * Apply a merge patch field, `null` removes the value
+ */
+fn merge_field(current: &mut Option<String>, patch: &Option<Option<String>>) {
+    if let Some(value) = patch {
+        *current = value.clone();
+    }
+}
+
+/**
+ * Validate a patch against the stored record and encrypt it so it is ready to be stored
+ */
+pub(crate) async fn prepare_update(
+    db: &State<Box<dyn Storage>>,
     mut updated_record: UpdateRecord,
     record_id: ObjectId,
     user_id: ObjectId,
-) -> Result<(), ApiErrors> {
+) -> Result<UpdateRecord, ApiErrors> {
     // Get the record && Check if it exists
-    let record = db.get_record(record_id, user_id).await?;
+    let mut record = db.get_record(record_id, user_id).await?;
 
+    // Clearing a field that does not belong to the record type is a no-op, setting one is not allowed
+    let sets = |field: &Option<Option<String>>| matches!(field, Some(Some(_)));
     match record.record_type {
         RecordTypes::Password => {
             // Validate no key/secret related items are attempting to be updated
-            if updated_record.key.is_some() || updated_record.secret.is_some() {
+            if sets(&updated_record.key) || sets(&updated_record.secret) {
                 return Err(ApiErrors::BadRequest("Record is a password record, cannot update secret fields".to_string()));
             }
-            if let Some(password) = updated_record.password {
-                updated_record.password = Some(encrypt_data(&password));
-            }
         },
         RecordTypes::Secret => {
             if 
-                updated_record.email.is_some() || 
-                updated_record.password.is_some() || 
-                updated_record.service.is_some() || 
-                updated_record.username.is_some()
+                sets(&updated_record.email) || 
+                sets(&updated_record.password) || 
+                sets(&updated_record.service) || 
+                sets(&updated_record.username) ||
+                matches!(updated_record.two_factor, Some(Some(_))) ||
+                matches!(updated_record.rotation_period, Some(Some(_))) ||
+                matches!(updated_record.uris, Some(Some(_)))
             {
                 return Err(ApiErrors::BadRequest("Record is secret record, cannot update password fields".to_string()));        
             }
-            if let Some(secret) = updated_record.secret {
-                updated_record.secret = Some(encrypt_data(&secret));
-            }
         }
     }
 
-    db.update_record(updated_record, record_id, user_id).await?;
+    // Check the record is still valid once the patch is applied
+    merge_field(&mut record.service, &updated_record.service);
+    merge_field(&mut record.password, &updated_record.password);
+    merge_field(&mut record.email, &updated_record.email);
+    merge_field(&mut record.username, &updated_record.username);
+    merge_field(&mut record.key, &updated_record.key);
+    merge_field(&mut record.secret, &updated_record.secret);
+    if let Some(two_factor) = updated_record.two_factor {
+        record.two_factor = two_factor;
+    }
+    if let Some(rotation_period) = updated_record.rotation_period {
+        record.rotation_period = rotation_period;
+    }
+    if let Some(uris) = updated_record.uris.clone() {
+        record.uris = uris;
+    }
+    if let Some(tags) = updated_record.tags.clone() {
+        record.tags = tags;
+    }
+    validate_record(&record)?;
+
+    if let Some(Some(password)) = updated_record.password {
+        updated_record.password = Some(Some(encrypt_data(&password)));
+    }
+    if let Some(Some(secret)) = updated_record.secret {
+        updated_record.secret = Some(Some(encrypt_data(&secret)));
+    }
 
-    Ok(())
+    Ok(updated_record)
+}
+
+pub async fn update_record(
+    db: &State<Box<dyn Storage>>,
+    updated_record: UpdateRecord,
+    record_id: ObjectId,
+    user_id: ObjectId,
+    if_match: &IfMatch,
+) -> Result<i64, ApiErrors> {
+    // Shared and organization records are stored under another user
+    let access = authorize(db.inner().as_ref(), user_id, Action::Write, Resource::Record(record_id)).await?;
+    let owner_id = access.owner_id;
+    let expected_revision = if_match.expected_revision(access.record.and_then(|record| record.revision))?;
+    let updated_record = prepare_update(db, updated_record, record_id, owner_id).await?;
+
+    // The revision is re-checked atomically in the database, the record may have changed since it was read
+    let revision = db.update_record(updated_record, record_id, owner_id, expected_revision).await?;
+
+    Ok(revision)
 }
 
 pub async fn delete_record(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     record_id: ObjectId,
     user_id: ObjectId,
+    if_match: &IfMatch,
 ) -> Result<(), ApiErrors> {
-    db.delete_record(record_id, user_id).await?;
+    let access = authorize(db.inner().as_ref(), user_id, Action::Delete, Resource::Record(record_id)).await?;
+    let expected_revision = if_match.expected_revision(access.record.and_then(|record| record.revision))?;
+    let owner_id = access.owner_id;
+    db.delete_record(record_id, owner_id, expected_revision).await?;
     Ok(())
 }
 
 pub async fn get_record(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     record_id: ObjectId,
     user_id: ObjectId,
 ) -> Result<ResponseRecord, ApiErrors> {
-    // Get the record
-    let mut record = db.get_record(record_id, user_id).await?;
+    // The user's own record, one shared with them or one of their organizations
+    let record = authorize(db.inner().as_ref(), user_id, Action::Read, Resource::Record(record_id))
+        .await?
+        .record
+        .ok_or_else(|| ApiErrors::NotFound("Record not found".to_string()))?;
+
+    let record = decrypt_record(record)?;
+    record_reveal(db.inner().as_ref(), user_id, &record).await?;
+    to_response_record(record)
+}
+
+/**
+ * The plaintext password or secret of a record
+ */
+pub async fn reveal_record(
+    db: &State<Box<dyn Storage>>,
+    record_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<ResponseRevealedRecord, ApiErrors> {
+    let record = authorize(db.inner().as_ref(), user_id, Action::Read, Resource::Record(record_id))
+        .await?
+        .record
+        .ok_or_else(|| ApiErrors::NotFound("Record not found".to_string()))?;
+
+    let record = decrypt_record(record)?;
+    record_reveal(db.inner().as_ref(), user_id, &record).await?;
+    Ok(ResponseRevealedRecord {
+        id: record_id.to_string(),
+        record_type: record.record_type,
+        password: record.password,
+        secret: record.secret,
+    })
+}
 
-    // Decrypt password
+/**
+ * Log that the plaintext of a record was sent to a user, under the user the record is stored under
+ */
+pub(crate) async fn record_reveal(db: &dyn Storage, actor_id: ObjectId, record: &Record) -> Result<(), ApiErrors> {
+    let owner_id = record.user_id.ok_or(ApiErrors::ServerError("User id was not in record".to_string()))?;
+    record_event(db, AuditAction::RecordRevealed, owner_id, Some(actor_id), record.id).await
+}
+
+/**
+ * Leave the password and secret out of a record, for lists of records
+ */
+pub(crate) fn mask_record(record: Record) -> Record {
+    Record { password: None, secret: None, ..record }
+}
+
+/**
+ * A record of a list, decrypted and logged as revealed when it goes to `reveal_to`, masked otherwise
+ */
+pub(crate) async fn reveal_or_mask(db: &dyn Storage, record: Record, reveal_to: Option<ObjectId>) -> Result<Record, ApiErrors> {
+    match reveal_to {
+        Some(actor_id) => {
+            let record = decrypt_record(record)?;
+            record_reveal(db, actor_id, &record).await?;
+            Ok(record)
+        }
+        None => Ok(mask_record(record)),
+    }
+}
+
+/**
+ * Decrypt the password or secret of a stored record
+ */
+pub(crate) fn decrypt_record(mut record: Record) -> Result<Record, ApiErrors> {
     if let Some(password) = record.password {
+        // Record is a password
+        record.record_type = RecordTypes::Password;
         record.password = Some(decrypt_password(&password)?);
     }
     if let Some(secret) = record.secret {
+        // Record is secret
+        record.record_type = RecordTypes::Secret;
         record.secret = Some(decrypt_password(&secret)?);
     }
+    Ok(record)
+}
+
+fn to_rfc3339(date: Option<DateTime>) -> Result<Option<String>, ApiErrors> {
+    date.map(|date| date.try_to_rfc3339_string().map_err(|err| ApiErrors::ServerError(err.to_string())))
+        .transpose()
+}
 
+pub(crate) fn to_response_record(record: Record) -> Result<ResponseRecord, ApiErrors> {
+    let rotation_due_at = rotation::due_at(&record);
     let user_id = Some(record.user_id.ok_or(ApiErrors::ServerError("User id was not in record".to_string()))?.to_string());
     let id = Some(record.id.ok_or(ApiErrors::ServerError("Object id was not found for record".to_string()))?.to_string());
 
@@ -133,53 +399,437 @@ pub async fn get_record(
         service: record.service,
         password: record.password,
         email: record.email,
-        username: record.username
+        username: record.username,
+        two_factor: record.two_factor,
+        revision: record.revision,
+        created_at: to_rfc3339(record.created_at)?,
+        updated_at: to_rfc3339(record.updated_at)?,
+        password_changed_at: to_rfc3339(record.password_changed_at)?,
+        breach: match record.breach {
+            Some(breach) => Some(ResponseBreachStatus {
+                password_count: breach.password_count,
+                email_count: breach.email_count,
+                checked_at: to_rfc3339(Some(breach.checked_at))?.unwrap_or_default(),
+            }),
+            None => None,
+        },
+        rotation_due_at: to_rfc3339(rotation_due_at)?,
+        rotation_period: record.rotation_period,
+        uris: record.uris,
+        tags: record.tags,
+        org_id: record.org_id.map(|id| id.to_string()),
+        collection_id: record.collection_id.map(|id| id.to_string()),
     })
 }
 
-pub async fn get_all_user_records(
-    db: &State<Box<dyn TMongoClient>>,
+/**
+ * Every record of a user with its password or secret decrypted
+ */
+pub(crate) async fn get_decrypted_user_records(
+    db: &dyn Storage,
+    user_id: ObjectId,
+) -> Result<Vec<Record>, ApiErrors> {
+    let mut stream = db.get_all_user_records(user_id).await?;
+    let mut records: Vec<Record> = Vec::new();
+
+    while let Some(record) = stream.next().await {
+        records.push(decrypt_record(record?)?);
+    }
+
+    Ok(records)
+}
+
+/**
+ * A page of the personal records of a user, with their passwords and secrets only when they are
+ * revealed to `reveal_to`
+ */
+pub async fn get_user_records_page(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    page: PageRequest,
+    reveal_to: Option<ObjectId>,
+) -> Result<Page<ResponseRecord>, ApiErrors> {
+    let total = db.count_user_records(user_id).await?;
+    let records = db.get_user_records_page(user_id, page.clone()).await?;
+    let records = match reveal_to {
+        Some(actor_id) => {
+            let mut revealed = Vec::with_capacity(records.len());
+            for (index, record) in records.into_iter().enumerate() {
+                let record = decrypt_record(record)?;
+                // The record after the limit only tells another page follows, it is not sent
+                if (index as i64) < page.limit {
+                    record_reveal(db.inner().as_ref(), actor_id, &record).await?;
+                }
+                revealed.push(record);
+            }
+            revealed
+        }
+        None => records.into_iter().map(mask_record).collect(),
+    };
+    page.page(records, total, to_response_record)
+}
+
+/**
+ * Report the weak, reused and old passwords of a user
+ */
+pub async fn vault_health(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    max_age_days: Option<u32>,
+) -> Result<HealthReport, ApiErrors> {
+    let records = get_decrypted_user_records(db.inner().as_ref(), user_id).await?;
+    Ok(health::build_report(
+        &records,
+        max_age_days.unwrap_or(health::DEFAULT_MAX_PASSWORD_AGE_DAYS),
+        DateTime::now(),
+    ))
+}
+
+/**
+ * Password records of a user that are overdue or due within `within_days`
+ */
+pub async fn get_due_for_rotation(
+    db: &State<Box<dyn Storage>>,
     user_id: ObjectId,
-) -> Result<Vec<ResponseRecord>, ApiErrors> {
-    let mut cursor = db.get_all_user_records(user_id).await?;
-    let mut records: Vec<ResponseRecord> = Vec::new();
-
-    while let Some(record) = cursor.next().await {
-        let mut record = record.map_err(|err| ApiErrors::ServerError(err.to_string()))?;
-        // Decrypt Password or secret
-        if let Some(password) = record.password {
-            // Record is a password
-            record.record_type = RecordTypes::Password;
-            record.password = Some(decrypt_password(&password)?);
-        }
-        if let Some(secret) = record.secret {
-            // Record is secret
-            record.record_type = RecordTypes::Secret;
-            record.secret = Some(decrypt_password(&secret)?);
-        }
-
-        let id = match record.id {
-            Some(id) => id.to_string(),
-            None => return Err(ApiErrors::ServerError("No ObjectId".to_string())),
+    within_days: Option<i64>,
+) -> Result<Vec<DueRecord>, ApiErrors> {
+    let within_days = within_days.unwrap_or_else(get_rotation_reminder_days);
+    if within_days < 0 {
+        return Err(ApiErrors::BadRequest("within_days cannot be negative".to_string()));
+    }
+    let records = db.get_user_record_metadata(user_id).await?;
+    let now = DateTime::now();
+    rotation::due_records(&records, now, within_days)
+        .into_iter()
+        .map(|(record, due_at)| rotation::to_due_record(record, due_at, now))
+        .collect()
+}
+
+/**
+ * Password records of a user that apply to a URI, most specific first
+ */
+pub async fn match_records(
+    db: &State<Box<dyn Storage>>,
+    matcher: &UriMatcher,
+    user_id: ObjectId,
+    uri: &str,
+) -> Result<Vec<MatchedRecord>, ApiErrors> {
+    let target = uri_match::parse_uri(uri)
+        .ok_or_else(|| ApiErrors::BadRequest(format!("{} is not a valid URI", uri)))?;
+    let records = db.get_user_record_metadata(user_id).await?;
+
+    let mut matches = Vec::new();
+    for record in records {
+        if let Some((matched_uri, score)) = matcher.best_match(&record, &target) {
+            matches.push(MatchedRecord {
+                record: to_response_record(record)?,
+                matched_uri: matched_uri.uri,
+                match_mode: matched_uri.match_mode,
+                score,
+            });
+        }
+    }
+    matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.record.service.cmp(&b.record.service)));
+    Ok(matches)
+}
+
+fn parse_record_id(id: &str) -> Result<ObjectId, ApiErrors> {
+    ObjectId::parse_str(id).map_err(|_| ApiErrors::BadRequest("ID is not formatted correctly".to_string()))
+}
+
+/**
+ * Validate a bulk operation and turn it into a write, along with the password generated for it
+ */
+async fn prepare_bulk_operation(
+    db: &State<Box<dyn Storage>>,
+    operation: BulkOperation,
+    user_id: ObjectId,
+) -> Result<(RecordWrite, Option<String>), ApiErrors> {
+    match operation {
+        BulkOperation::Create { record } => {
+            let (record, generated_password) = resolve_new_record(record)?;
+            authorize_new_record(db.inner().as_ref(), &record, user_id).await?;
+            Ok((RecordWrite::Insert(prepare_record(record, user_id)?), generated_password))
+        }
+        BulkOperation::Update { id, record, revision } => {
+            let record_id = parse_record_id(&id)?;
+            // Shared and organization records are stored under another user
+            let owner_id = authorize(db.inner().as_ref(), user_id, Action::Write, Resource::Record(record_id))
+                .await?
+                .owner_id;
+            let write = RecordWrite::Update {
+                record_id,
+                owner_id,
+                updated_record: prepare_update(db, record, record_id, owner_id).await?,
+                expected_revision: revision,
+            };
+            Ok((write, None))
+        }
+        BulkOperation::Delete { id, revision } => {
+            let record_id = parse_record_id(&id)?;
+            // Check it exists so the whole batch can be validated before anything is written
+            let owner_id = authorize(db.inner().as_ref(), user_id, Action::Delete, Resource::Record(record_id))
+                .await?
+                .owner_id;
+            Ok((RecordWrite::Delete { record_id, owner_id, expected_revision: revision }, None))
+        }
+    }
+}
+
+async fn apply_write(db: &State<Box<dyn Storage>>, write: RecordWrite) -> Result<RecordWriteResult, ApiErrors> {
+    match write {
+        RecordWrite::Insert(record) => Ok(RecordWriteResult::Inserted(db.insert_record(record).await?)),
+        RecordWrite::Update { record_id, owner_id, updated_record, expected_revision } => Ok(RecordWriteResult::Updated(
+            db.update_record(updated_record, record_id, owner_id, expected_revision).await?,
+        )),
+        RecordWrite::Delete { record_id, owner_id, expected_revision } => {
+            db.delete_record(record_id, owner_id, expected_revision).await?;
+            Ok(RecordWriteResult::Deleted)
+        }
+    }
+}
+
+fn bulk_success(index: usize, result: RecordWriteResult, password: Option<String>) -> BulkResult {
+    let (status, id, revision) = match result {
+        RecordWriteResult::Inserted(id) => (Status::Created, Some(id.to_string()), Some(1)),
+        RecordWriteResult::Updated(revision) => (Status::NoContent, None, Some(revision)),
+        RecordWriteResult::Deleted => (Status::NoContent, None, None),
+    };
+    BulkResult { index, status: status.code, id, revision, password, error: None }
+}
+
+fn bulk_failure(index: usize, status: Status, error: String) -> BulkResult {
+    BulkResult { index, status: status.code, id: None, revision: None, password: None, error: Some(error) }
+}
+
+/**
+ * Apply a batch of record operations.
+ * Each operation is validated on its own, in atomic mode nothing is written unless all of them succeed
+ */
+pub async fn bulk_records(
+    db: &State<Box<dyn Storage>>,
+    request: BulkRequest,
+    user_id: ObjectId,
+) -> Result<BulkResponse, ApiErrors> {
+    let max_operations = get_bulk_max_operations();
+    if request.operations.is_empty() {
+        return Err(ApiErrors::BadRequest("At least one operation is required".to_string()));
+    }
+    if request.operations.len() > max_operations {
+        return Err(ApiErrors::BadRequest(format!("A bulk request can have at most {} operations", max_operations)));
+    }
+
+    if !request.atomic {
+        let mut results = Vec::with_capacity(request.operations.len());
+        for (index, operation) in request.operations.into_iter().enumerate() {
+            let result = match prepare_bulk_operation(db, operation, user_id).await {
+                Ok((write, password)) => apply_write(db, write).await.map(|result| (result, password)),
+                Err(err) => Err(err),
+            };
+            results.push(match result {
+                Ok((result, password)) => bulk_success(index, result, password),
+                Err(err) => bulk_failure(index, err.status(), err.message()),
+            });
+        }
+        return Ok(BulkResponse { applied: true, results });
+    }
+
+    // Validate everything first so a bad item does not start a transaction
+    let mut writes = Vec::with_capacity(request.operations.len());
+    let mut passwords = Vec::with_capacity(request.operations.len());
+    let mut errors = Vec::new();
+    for (index, operation) in request.operations.into_iter().enumerate() {
+        match prepare_bulk_operation(db, operation, user_id).await {
+            Ok((write, password)) => {
+                writes.push(write);
+                passwords.push(password);
+            }
+            Err(err) => errors.push((index, err)),
+        }
+    }
+
+    let operation_count = writes.len() + errors.len();
+    if errors.is_empty() {
+        match db.write_records_atomically(writes).await {
+            Ok(results) => {
+                return Ok(BulkResponse {
+                    applied: true,
+                    results: results
+                        .into_iter()
+                        .zip(passwords)
+                        .enumerate()
+                        .map(|(index, (result, password))| bulk_success(index, result, password))
+                        .collect(),
+                })
+            }
+            Err(ApiErrors::ServerError(msg)) => return Err(ApiErrors::ServerError(msg)),
+            // A write lost a race after validation, ie a stale revision, the whole batch was rolled back
+            Err(err) => {
+                return Ok(BulkResponse {
+                    applied: false,
+                    results: (0..operation_count)
+                        .map(|index| bulk_failure(index, err.status(), err.message()))
+                        .collect(),
+                })
+            }
+        }
+    }
+
+    let mut errors = errors.into_iter().peekable();
+    let results = (0..operation_count)
+        .map(|index| match errors.next_if(|(failed, _)| *failed == index) {
+            Some((_, err)) => bulk_failure(index, err.status(), err.message()),
+            None => bulk_failure(
+                index,
+                Status::FailedDependency,
+                "Not applied, another operation in the batch failed".to_string(),
+            ),
+        })
+        .collect();
+
+    Ok(BulkResponse { applied: false, results })
+}
+
+/**
+ * Records are duplicates when they share a service and login, or a secret key
+ */
+fn duplicate_key(record: &Record) -> (String, String) {
+    let lower = |value: &Option<String>| value.as_deref().unwrap_or_default().to_lowercase();
+    match record.record_type {
+        RecordTypes::Password => (
+            lower(&record.service),
+            lower(if record.username.is_some() { &record.username } else { &record.email }),
+        ),
+        RecordTypes::Secret => (lower(&record.key), String::new()),
+    }
+}
+
+/**
+ * Import the records of another password manager's export
+ */
+pub async fn import_records(
+    db: &State<Box<dyn Storage>>,
+    format: ImportFormat,
+    data: &[u8],
+    passphrase: Option<&str>,
+    dry_run: bool,
+    user_id: ObjectId,
+) -> Result<ImportReport, ApiErrors> {
+    let entries = import::parse(format, data, passphrase)?;
+
+    let mut seen: HashSet<(String, String)> = db
+        .get_user_record_metadata(user_id)
+        .await?
+        .iter()
+        .map(duplicate_key)
+        .collect();
+
+    let mut report = ImportReport {
+        dry_run,
+        imported: Vec::new(),
+        skipped: Vec::new(),
+        ambiguous: Vec::new(),
+        duplicates: Vec::new(),
+    };
+
+    for (index, entry) in entries.iter().enumerate() {
+        let issue = |reason: String| ImportIssue { index, name: entry.name.clone(), reason };
+
+        let (record, warnings) = match import::to_record(entry) {
+            Ok(res) => res,
+            Err(reason) => {
+                report.skipped.push(issue(reason));
+                continue;
+            }
         };
-        let user_id = match record.user_id {
-            Some(id) => id.to_string(),
-            None => return Err(ApiErrors::ServerError("No ObjectId".to_string())),
+        if let Err(err) = validate_record(&record) {
+            report.skipped.push(issue(err.message()));
+            continue;
+        }
+        if !seen.insert(duplicate_key(&record)) {
+            report.duplicates.push(issue("A record with this service and username already exists".to_string()));
+            continue;
+        }
+        if !warnings.is_empty() {
+            report.ambiguous.push(issue(warnings.join(", ")));
+        }
+
+        let mut imported = ImportedRecord {
+            index,
+            id: None,
+            record_type: record.record_type.clone(),
+            service: record.service.clone(),
+            key: record.key.clone(),
+            email: record.email.clone(),
+            username: record.username.clone(),
         };
+        if !dry_run {
+            imported.id = Some(db.insert_record(prepare_record(record, user_id)?).await?.to_string());
+        }
+        report.imported.push(imported);
+    }
 
-        // add to vector
-        records.push(ResponseRecord {
-            id: Some(id),
+    Ok(report)
+}
+
+/**
+ * Export every record of the user, decrypted or encrypted with the export passphrase
+ */
+pub async fn export_records(
+    db: &State<Box<dyn Storage>>,
+    format: ExportFormat,
+    passphrase: Option<String>,
+    user_id: ObjectId,
+) -> Result<ExportResponse, ApiErrors> {
+    // Check the passphrase before decrypting the vault
+    let passphrase = match (format, passphrase) {
+        (ExportFormat::Encrypted, Some(passphrase)) if passphrase.chars().count() >= MIN_PASSPHRASE_LENGTH => Some(passphrase),
+        (ExportFormat::Encrypted, _) => {
+            return Err(ApiErrors::BadRequest(format!(
+                "An X-Export-Passphrase header of at least {} characters is required",
+                MIN_PASSPHRASE_LENGTH
+            )))
+        }
+        _ => None,
+    };
+
+    let records = get_decrypted_user_records(db.inner().as_ref(), user_id).await?;
+    // An export hands out every password and secret of the vault, each counts as revealed
+    for record in &records {
+        record_reveal(db.inner().as_ref(), user_id, record).await?;
+    }
+    let records = records
+        .into_iter()
+        .map(|record| ExportedRecord {
             record_type: record.record_type,
             service: record.service,
-            password: record.password,
             email: record.email,
             username: record.username,
-            user_id: Some(user_id),
+            password: record.password,
             key: record.key,
-            secret: record.secret
-        });
-    }
+            secret: record.secret,
+            uris: record.uris,
+            tags: record.tags,
+        })
+        .collect();
+    let vault = VaultExport {
+        version: EXPORT_VERSION,
+        exported_at: DateTime::now()
+            .try_to_rfc3339_string()
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+        records,
+    };
 
-    Ok(records)
+    match (format, passphrase) {
+        (ExportFormat::Json, _) => Ok(ExportResponse::Json(Json(vault))),
+        (ExportFormat::Csv, _) => Ok(ExportResponse::Csv((ContentType::CSV, export::to_csv(&vault)?))),
+        (ExportFormat::Encrypted, passphrase) => {
+            let data = serde_json::to_vec(&vault).map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+            Ok(ExportResponse::Encrypted(Json(EncryptedVaultExport {
+                version: EXPORT_VERSION,
+                encrypted: true,
+                payload: encrypt_with_passphrase(&data, passphrase.as_deref().unwrap_or_default())?,
+            })))
+        }
+    }
 }
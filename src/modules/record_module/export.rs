@@ -0,0 +1,130 @@
+/*
+    Export of a user's vault, either in plain text or encrypted with a passphrase.
+    Every format can be read back by POST /record/import
+*/
+use rocket::{
+    http::ContentType,
+    request::{self, FromRequest, Outcome},
+    serde::json::Json,
+    Request,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::shared::{
+    encryption::PassphraseEncrypted,
+    types::{ApiErrors, RecordTypes, RecordUri},
+};
+
+pub static EXPORT_VERSION: u32 = 1;
+/// Passphrases shorter than this are rejected for encrypted exports
+pub static MIN_PASSPHRASE_LENGTH: usize = 12;
+pub static CSV_HEADERS: [&str; 7] = ["record_type", "service", "email", "username", "password", "key", "secret"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum ExportFormat {
+    #[field(value = "json")]
+    Json,
+    #[field(value = "csv")]
+    Csv,
+    #[field(value = "encrypted")]
+    Encrypted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedRecord {
+    pub record_type: RecordTypes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Only kept by JSON exports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uris: Option<Vec<RecordUri>>,
+    /// Only kept by JSON exports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultExport {
+    pub version: u32,
+    pub exported_at: String,
+    pub records: Vec<ExportedRecord>,
+}
+
+/**
+ * A VaultExport serialized to JSON and encrypted with the export passphrase
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptedVaultExport {
+    pub version: u32,
+    pub encrypted: bool,
+    #[serde(flatten)]
+    pub payload: PassphraseEncrypted,
+}
+
+#[derive(Responder)]
+pub enum ExportResponse {
+    Json(Json<VaultExport>),
+    Csv((ContentType, String)),
+    Encrypted(Json<EncryptedVaultExport>),
+}
+
+/**
+ * Write the export as CSV, one row per record
+ */
+pub fn to_csv(export: &VaultExport) -> Result<String, ApiErrors> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let write_err = |err: csv::Error| ApiErrors::ServerError(err.to_string());
+    writer.write_record(CSV_HEADERS).map_err(write_err)?;
+    for record in &export.records {
+        let record_type = match record.record_type {
+            RecordTypes::Password => "Password",
+            RecordTypes::Secret => "Secret",
+        };
+        let field = |value: &Option<String>| value.clone().unwrap_or_default();
+        writer
+            .write_record([
+                record_type.to_string(),
+                field(&record.service),
+                field(&record.email),
+                field(&record.username),
+                field(&record.password),
+                field(&record.key),
+                field(&record.secret),
+            ])
+            .map_err(write_err)?;
+    }
+    let data = writer
+        .into_inner()
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+    String::from_utf8(data).map_err(|err| ApiErrors::ServerError(err.to_string()))
+}
+
+/**
+ * The passphrase of an encrypted export, sent in the `X-Export-Passphrase` header
+ */
+#[derive(Debug)]
+pub struct ExportPassphrase(pub Option<String>);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ExportPassphrase {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(ExportPassphrase(
+            request
+                .headers()
+                .get_one("X-Export-Passphrase")
+                .map(|passphrase| passphrase.to_string()),
+        ))
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::shared::types::{Record, RecordTypes};
+
+/// Passwords estimated below this many bits of entropy are reported as weak
+pub const WEAK_ENTROPY_BITS: f64 = 50.0;
+/// Passwords that have not changed for longer than this are reported as old
+pub const DEFAULT_MAX_PASSWORD_AGE_DAYS: u32 = 365;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+// How much each kind of issue takes off a record's score, a record never loses more than all of it
+const REUSED_PENALTY: f64 = 0.4;
+const WEAK_PENALTY: f64 = 0.35;
+const OLD_PENALTY: f64 = 0.15;
+const MISSING_TWO_FACTOR_PENALTY: f64 = 0.1;
+const BREACHED_PASSWORD_PENALTY: f64 = 1.0;
+
+/// Services known to support TOTP based 2FA, matched against the service name or domain of a record
+const TOTP_SERVICES: &[&str] = &[
+    "1password", "adobe", "amazon", "apple", "atlassian", "aws", "binance", "bitbucket", "bitwarden",
+    "cloudflare", "coinbase", "digitalocean", "discord", "docker", "dropbox", "epicgames", "evernote",
+    "facebook", "fidelity", "github", "gitlab", "gmail", "godaddy", "google", "heroku", "icloud",
+    "instagram", "kraken", "lastpass", "linkedin", "mailchimp", "microsoft", "mongodb", "namecheap",
+    "nintendo", "npm", "okta", "outlook", "paypal", "pinterest", "playstation", "proton", "protonmail",
+    "reddit", "robinhood", "shopify", "slack", "snapchat", "steam", "stripe", "tiktok", "tumblr",
+    "twitch", "twitter", "vanguard", "wordpress", "x", "xbox", "yahoo", "zoom",
+];
+
+/// Passwords and base words that are tried first by any attacker
+const COMMON_PASSWORDS: &[&str] = &[
+    "password", "passw0rd", "qwerty", "qwertyuiop", "asdfgh", "zxcvbn", "letmein", "welcome", "admin",
+    "administrator", "login", "iloveyou", "monkey", "dragon", "football", "baseball", "soccer",
+    "master", "shadow", "sunshine", "princess", "superman", "batman", "trustno1", "starwars",
+    "whatever", "freedom", "secret", "summer", "winter", "abc123", "123456", "654321", "111111",
+    "000000",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthRecord {
+    pub id: String,
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WeakPassword {
+    pub record: HealthRecord,
+    pub entropy_bits: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OldPassword {
+    pub record: HealthRecord,
+    pub age_days: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BreachedRecord {
+    pub record: HealthRecord,
+    pub password_count: i64,
+    pub email_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// 0 to 100, 100 meaning no issues were found
+    pub score: u8,
+    pub password_records: usize,
+    /// Records sharing the same password, one group per password
+    pub reused: Vec<Vec<HealthRecord>>,
+    pub weak: Vec<WeakPassword>,
+    pub old: Vec<OldPassword>,
+    /// Records created before password ages were tracked
+    pub unknown_age: usize,
+    pub missing_two_factor: Vec<HealthRecord>,
+    /// Records flagged by the last breach check
+    pub breached: Vec<BreachedRecord>,
+}
+
+pub fn breached_record(record: &Record) -> Option<BreachedRecord> {
+    record
+        .breach
+        .as_ref()
+        .filter(|breach| breach.password_count > 0 || breach.email_count > 0)
+        .map(|breach| BreachedRecord {
+            record: health_record(record),
+            password_count: breach.password_count,
+            email_count: breach.email_count,
+        })
+}
+
+fn health_record(record: &Record) -> HealthRecord {
+    HealthRecord {
+        id: record.id.map(|id| id.to_string()).unwrap_or_default(),
+        service: record.service.clone(),
+        email: record.email.clone(),
+        username: record.username.clone(),
+    }
+}
+
+/**
+ * Check a service name such as `GitHub` or `accounts.google.com` against the known TOTP services
+ */
+pub fn supports_totp(service: &str) -> bool {
+    let service = service.trim().to_lowercase();
+    let host = service
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split(['/', '?', '#', ':'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() > 1 {
+        // Compare the label left of the top level domain, ie `google` in `accounts.google.com`
+        return TOTP_SERVICES.contains(&labels[labels.len() - 2]);
+    }
+    let name: String = service.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    TOTP_SERVICES.contains(&name.as_str())
+}
+
+fn pool_size(password: &str) -> f64 {
+    let mut pool = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password.chars().any(|c| c.is_ascii_punctuation() || c == ' ') {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+    pool as f64
+}
+
+/**
+ * Characters repeating or continuing a run, ie `aaa` or `123`, only count for a quarter
+ */
+fn effective_length(password: &str) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .map(|(index, &c)| {
+            if index == 0 {
+                return 1.0;
+            }
+            let step = c as i64 - chars[index - 1] as i64;
+            if step.abs() <= 1 {
+                0.25
+            } else {
+                1.0
+            }
+        })
+        .sum()
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => c,
+    }
+}
+
+/**
+ * Estimate how many guesses, in bits, a password would take to crack.
+ * Starts from the size of the character pool and discounts runs, repeats and common passwords
+ */
+pub fn estimate_entropy(password: &str) -> f64 {
+    if password.is_empty() {
+        return 0.0;
+    }
+    // Lowering only ascii keeps a char for char mapping with the password
+    let lowered: String = password.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let normalized: String = lowered.chars().map(unleet).collect();
+
+    // Guessing a common word with its capitalisation and substitutions takes about as long as the list
+    let common = COMMON_PASSWORDS
+        .iter()
+        .filter_map(|word| {
+            [&lowered, &normalized]
+                .into_iter()
+                .find_map(|candidate| candidate.find(word).map(|start| candidate[..start].chars().count()))
+                .map(|start| (start, word.len()))
+        })
+        .max_by_key(|(_, len)| *len);
+
+    match common {
+        Some((start, len)) => {
+            let rest: String = password
+                .chars()
+                .take(start)
+                .chain(password.chars().skip(start + len))
+                .collect();
+            let word_bits = (COMMON_PASSWORDS.len() as f64).log2() + 1.0;
+            word_bits + rest_entropy(&rest)
+        }
+        None => rest_entropy(password),
+    }
+}
+
+fn rest_entropy(password: &str) -> f64 {
+    if password.is_empty() {
+        return 0.0;
+    }
+    effective_length(password) * pool_size(password).log2()
+}
+
+/**
+ * Build the health report of a vault from its decrypted records
+ */
+pub fn build_report(records: &[Record], max_age_days: u32, now: DateTime) -> HealthReport {
+    let passwords: Vec<(&Record, &String)> = records
+        .iter()
+        .filter(|record| record.record_type == RecordTypes::Password)
+        .filter_map(|record| record.password.as_ref().map(|password| (record, password)))
+        .collect();
+    let mut penalties: HashMap<ObjectId, f64> = HashMap::new();
+    let mut penalize = |record: &Record, penalty: f64| {
+        if let Some(id) = record.id {
+            *penalties.entry(id).or_default() += penalty;
+        }
+    };
+
+    // Group by hash so plaintext passwords are never compared or kept around
+    let mut by_hash: HashMap<Vec<u8>, Vec<&Record>> = HashMap::new();
+    for (record, password) in &passwords {
+        by_hash
+            .entry(Sha256::digest(password.as_bytes()).to_vec())
+            .or_default()
+            .push(record);
+    }
+    let mut reused: Vec<Vec<HealthRecord>> = Vec::new();
+    for group in by_hash.into_values().filter(|group| group.len() > 1) {
+        group.iter().for_each(|record| penalize(record, REUSED_PENALTY));
+        reused.push(group.into_iter().map(health_record).collect());
+    }
+    reused.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a[0].id.cmp(&b[0].id)));
+
+    let mut weak = Vec::new();
+    let mut old = Vec::new();
+    let mut unknown_age = 0;
+    let mut missing_two_factor = Vec::new();
+    let mut breached = Vec::new();
+    for (record, password) in &passwords {
+        if let Some(breach) = breached_record(record) {
+            // A leaked password is as bad as it gets, a leaked email alone is only reported
+            if breach.password_count > 0 {
+                penalize(record, BREACHED_PASSWORD_PENALTY);
+            }
+            breached.push(breach);
+        }
+
+        let entropy_bits = estimate_entropy(password);
+        if entropy_bits < WEAK_ENTROPY_BITS {
+            penalize(record, WEAK_PENALTY);
+            weak.push(WeakPassword {
+                record: health_record(record),
+                entropy_bits: (entropy_bits * 10.0).round() / 10.0,
+            });
+        }
+
+        match record.password_changed_at {
+            Some(changed_at) => {
+                let age_days = (now.timestamp_millis() - changed_at.timestamp_millis()) / MILLIS_PER_DAY;
+                if age_days > max_age_days as i64 {
+                    penalize(record, OLD_PENALTY);
+                    old.push(OldPassword { record: health_record(record), age_days });
+                }
+            }
+            None => unknown_age += 1,
+        }
+
+        let supported = record.service.as_deref().map(supports_totp).unwrap_or(false);
+        if supported && record.two_factor != Some(true) {
+            penalize(record, MISSING_TWO_FACTOR_PENALTY);
+            missing_two_factor.push(health_record(record));
+        }
+    }
+    weak.sort_by(|a, b| a.entropy_bits.total_cmp(&b.entropy_bits));
+    old.sort_by_key(|old| std::cmp::Reverse(old.age_days));
+
+    let score = if passwords.is_empty() {
+        100
+    } else {
+        let lost: f64 = penalties.values().map(|penalty| penalty.min(1.0)).sum();
+        (100.0 * (1.0 - lost / passwords.len() as f64)).round() as u8
+    };
+
+    HealthReport {
+        score,
+        password_records: passwords.len(),
+        reused,
+        weak,
+        old,
+        unknown_age,
+        missing_two_factor,
+        breached,
+    }
+}
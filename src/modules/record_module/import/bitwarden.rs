@@ -0,0 +1,134 @@
+use rocket::serde::json::{serde_json, Value};
+use serde::Deserialize;
+
+use super::{column, non_empty, read_csv, EntryKind, ImportEntry};
+use crate::shared::types::{ApiErrors, RecordUri, UriMatch};
+
+#[derive(Debug, Deserialize)]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitwardenItem {
+    #[serde(rename = "type")]
+    item_type: u8,
+    name: Option<String>,
+    notes: Option<String>,
+    login: Option<BitwardenLogin>,
+    #[serde(default)]
+    fields: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitwardenLogin {
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Vec<BitwardenUri>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+    #[serde(rename = "match")]
+    match_mode: Option<u8>,
+}
+
+/**
+ * Bitwarden stores match detection as a number, null meaning the default of domain
+ */
+fn match_of(match_mode: Option<u8>) -> UriMatch {
+    match match_mode {
+        Some(1) => UriMatch::Host,
+        Some(2) => UriMatch::StartsWith,
+        Some(3) => UriMatch::Exact,
+        Some(4) => UriMatch::Regex,
+        _ => UriMatch::Domain,
+    }
+}
+
+fn kind_of(item_type: &str) -> EntryKind {
+    match item_type {
+        "1" | "login" => EntryKind::Login,
+        "2" | "note" => EntryKind::Note,
+        "3" | "card" => EntryKind::Unsupported("Card".to_string()),
+        "4" | "identity" => EntryKind::Unsupported("Identity".to_string()),
+        other => EntryKind::Unsupported(format!("Type {}", other)),
+    }
+}
+
+/**
+ * Parse an unencrypted Bitwarden JSON export
+ */
+pub fn parse_json(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    let export: BitwardenExport = serde_json::from_slice(data)
+        .map_err(|err| ApiErrors::BadRequest(format!("Bitwarden export could not be read: {}", err)))?;
+    if export.encrypted {
+        return Err(ApiErrors::BadRequest(
+            "Encrypted Bitwarden exports are not supported, export as unencrypted JSON".to_string(),
+        ));
+    }
+
+    Ok(export
+        .items
+        .into_iter()
+        .map(|item| {
+            let mut entry = ImportEntry::new(kind_of(&item.item_type.to_string()));
+            entry.name = non_empty(item.name.as_deref());
+            entry.notes = non_empty(item.notes.as_deref());
+            if !item.fields.is_empty() {
+                entry.dropped_fields.push("Custom fields".to_string());
+            }
+            if let Some(login) = item.login {
+                entry.username = non_empty(login.username.as_deref());
+                entry.password = non_empty(login.password.as_deref());
+                entry.uris = login
+                    .uris
+                    .iter()
+                    .filter_map(|uri| {
+                        non_empty(uri.uri.as_deref()).map(|value| RecordUri {
+                            uri: value,
+                            match_mode: match_of(uri.match_mode),
+                        })
+                    })
+                    .collect();
+                if non_empty(login.totp.as_deref()).is_some() {
+                    entry.dropped_fields.push("TOTP".to_string());
+                }
+            }
+            entry
+        })
+        .collect())
+}
+
+/**
+ * Parse a Bitwarden CSV export
+ * folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp
+ */
+pub fn parse_csv(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    Ok(read_csv(data)?
+        .iter()
+        .map(|row| {
+            let mut entry = ImportEntry::new(kind_of(&column(row, &["type"]).unwrap_or_default()));
+            entry.name = column(row, &["name"]);
+            entry.notes = column(row, &["notes"]);
+            entry.username = column(row, &["login_username"]);
+            entry.password = column(row, &["login_password"]);
+            entry.uris = column(row, &["login_uri"])
+                .map(|uris| uris.split(',').filter_map(|uri| non_empty(Some(uri))).map(RecordUri::from).collect())
+                .unwrap_or_default();
+            if column(row, &["fields"]).is_some() {
+                entry.dropped_fields.push("Custom fields".to_string());
+            }
+            if column(row, &["login_totp"]).is_some() {
+                entry.dropped_fields.push("TOTP".to_string());
+            }
+            entry
+        })
+        .collect())
+}
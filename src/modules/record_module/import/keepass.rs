@@ -0,0 +1,77 @@
+use roxmltree::{Document, Node};
+
+use super::{non_empty, EntryKind, ImportEntry};
+use crate::shared::types::{ApiErrors, RecordUri};
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name).and_then(|child| non_empty(child.text()))
+}
+
+fn parse_entry(node: Node) -> ImportEntry {
+    let mut entry = ImportEntry::new(EntryKind::Login);
+    for string in node.children().filter(|child| child.has_tag_name("String")) {
+        let key = child_text(string, "Key").unwrap_or_default();
+        let value = child_text(string, "Value");
+        match key.as_str() {
+            "Title" => entry.name = value,
+            "UserName" => entry.username = value,
+            "Password" => entry.password = value,
+            "URL" => entry.uris = value.into_iter().map(RecordUri::from).collect(),
+            "Notes" => entry.notes = value,
+            // Anything else is a custom field
+            _ => {
+                if value.is_some() {
+                    entry.dropped_fields.push(format!("Field {}", key));
+                }
+            }
+        }
+    }
+    if node.children().any(|child| child.has_tag_name("Binary")) {
+        entry.dropped_fields.push("Attachments".to_string());
+    }
+    // Entries without credentials are plain notes
+    if entry.password.is_none() && entry.username.is_none() {
+        entry.kind = EntryKind::Note;
+    }
+    entry
+}
+
+fn parse_group(node: Node, recycle_bin: Option<&str>, entries: &mut Vec<ImportEntry>) {
+    if recycle_bin.is_some() && child_text(node, "UUID").as_deref() == recycle_bin {
+        return;
+    }
+    for child in node.children() {
+        // History holds old copies of an entry, it is never descended into
+        if child.has_tag_name("Entry") {
+            entries.push(parse_entry(child));
+        } else if child.has_tag_name("Group") {
+            parse_group(child, recycle_bin, entries);
+        }
+    }
+}
+
+/**
+ * Parse a KeePass 2.x XML export
+ */
+pub fn parse_xml(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    let xml = std::str::from_utf8(data)
+        .map_err(|_| ApiErrors::BadRequest("KeePass export is not valid UTF-8".to_string()))?;
+    let document = Document::parse(xml)
+        .map_err(|err| ApiErrors::BadRequest(format!("KeePass export could not be read: {}", err)))?;
+
+    let root = document.root_element();
+    if !root.has_tag_name("KeePassFile") {
+        return Err(ApiErrors::BadRequest("KeePass export is missing KeePassFile".to_string()));
+    }
+    let recycle_bin = child(root, "Meta").and_then(|meta| child_text(meta, "RecycleBinUUID"));
+
+    let mut entries = Vec::new();
+    if let Some(root_group) = child(root, "Root") {
+        parse_group(root_group, recycle_bin.as_deref(), &mut entries);
+    }
+    Ok(entries)
+}
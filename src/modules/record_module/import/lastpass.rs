@@ -0,0 +1,44 @@
+use super::{column, read_csv, EntryKind, ImportEntry};
+use crate::shared::types::{ApiErrors, RecordUri};
+
+/// LastPass exports secure notes as rows with this placeholder URL
+static SECURE_NOTE_URL: &str = "http://sn";
+
+/**
+ * Parse a LastPass CSV export
+ * url,username,password,totp,extra,name,grouping,fav
+ */
+pub fn parse_csv(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    Ok(read_csv(data)?
+        .iter()
+        .map(|row| {
+            let url = column(row, &["url"]);
+            let extra = column(row, &["extra"]);
+
+            let kind = if url.as_deref() != Some(SECURE_NOTE_URL) {
+                EntryKind::Login
+            } else {
+                // Typed notes (cards, addresses, ...) start with their type
+                match extra.as_deref().and_then(|extra| extra.strip_prefix("NoteType:")) {
+                    Some(note_type) => {
+                        EntryKind::Unsupported(note_type.lines().next().unwrap_or_default().trim().to_string())
+                    }
+                    None => EntryKind::Note,
+                }
+            };
+
+            let mut entry = ImportEntry::new(kind);
+            entry.name = column(row, &["name"]);
+            entry.notes = extra;
+            entry.username = column(row, &["username"]);
+            entry.password = column(row, &["password"]);
+            if entry.kind == EntryKind::Login {
+                entry.uris = url.into_iter().map(RecordUri::from).collect();
+            }
+            if column(row, &["totp"]).is_some() {
+                entry.dropped_fields.push("TOTP".to_string());
+            }
+            entry
+        })
+        .collect())
+}
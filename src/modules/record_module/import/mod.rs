@@ -0,0 +1,252 @@
+/*
+    Parsers for exports of other password managers.
+    Every format is parsed into ImportEntry, which is then mapped onto a Record
+*/
+use std::collections::HashMap;
+
+use crate::shared::types::{ApiErrors, Record, RecordTypes, RecordUri};
+
+pub mod bitwarden;
+pub mod keepass;
+pub mod lastpass;
+pub mod onepassword;
+pub mod vault;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum ImportFormat {
+    #[field(value = "bitwarden_json")]
+    BitwardenJson,
+    #[field(value = "bitwarden_csv")]
+    BitwardenCsv,
+    #[field(value = "lastpass_csv")]
+    LastPassCsv,
+    #[field(value = "onepassword_1pux")]
+    OnePassword1Pux,
+    #[field(value = "onepassword_csv")]
+    OnePasswordCsv,
+    #[field(value = "keepass_xml")]
+    KeePassXml,
+    #[field(value = "vault_json")]
+    VaultJson,
+    #[field(value = "vault_csv")]
+    VaultCsv,
+    #[field(value = "vault_encrypted")]
+    VaultEncrypted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    Login,
+    Note,
+    /// Cards, identities and other item types that have no matching RecordTypes
+    Unsupported(String),
+}
+
+/**
+ * A single item of an export, before it is mapped onto a Record
+ */
+#[derive(Debug, Clone)]
+pub struct ImportEntry {
+    pub kind: EntryKind,
+    pub name: Option<String>,
+    pub uris: Vec<RecordUri>,
+    /// Only set by formats that keep the email apart from the username
+    pub email: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+    /// Fields of the source item that have nowhere to go, ie TOTP seeds or custom fields
+    pub dropped_fields: Vec<String>,
+}
+
+impl ImportEntry {
+    pub fn new(kind: EntryKind) -> ImportEntry {
+        ImportEntry {
+            kind,
+            name: None,
+            uris: Vec::new(),
+            email: None,
+            username: None,
+            password: None,
+            notes: None,
+            tags: Vec::new(),
+            dropped_fields: Vec::new(),
+        }
+    }
+}
+
+/**
+ * Parse an export into its entries, the passphrase is only used by encrypted exports
+ */
+pub fn parse(format: ImportFormat, data: &[u8], passphrase: Option<&str>) -> Result<Vec<ImportEntry>, ApiErrors> {
+    match format {
+        ImportFormat::BitwardenJson => bitwarden::parse_json(data),
+        ImportFormat::BitwardenCsv => bitwarden::parse_csv(data),
+        ImportFormat::LastPassCsv => lastpass::parse_csv(data),
+        ImportFormat::OnePassword1Pux => onepassword::parse_1pux(data),
+        ImportFormat::OnePasswordCsv => onepassword::parse_csv(data),
+        ImportFormat::KeePassXml => keepass::parse_xml(data),
+        ImportFormat::VaultJson => vault::parse_json(data),
+        ImportFormat::VaultCsv => vault::parse_csv(data),
+        ImportFormat::VaultEncrypted => vault::parse_encrypted(data, passphrase),
+    }
+}
+
+/**
+ * Map an entry onto a record.
+ * Returns the reason the entry was skipped, or the record plus any data that could not be carried over
+ */
+pub fn to_record(entry: &ImportEntry) -> Result<(Record, Vec<String>), String> {
+    let mut warnings: Vec<String> = entry
+        .dropped_fields
+        .iter()
+        .map(|field| format!("{} was not imported", field))
+        .collect();
+
+    let record = match &entry.kind {
+        EntryKind::Unsupported(item_type) => {
+            return Err(format!("{} items are not supported", item_type));
+        }
+        EntryKind::Login => {
+            let service = entry
+                .name
+                .clone()
+                .or_else(|| entry.uris.first().and_then(|uri| host_of(&uri.uri)))
+                .ok_or_else(|| "Login has no name or URL".to_string())?;
+            let password = entry
+                .password
+                .clone()
+                .ok_or_else(|| "Login has no password".to_string())?;
+            // Most managers only have a single login field, anything that looks like an address is an email
+            let (email, username) = match (entry.email.clone(), entry.username.clone()) {
+                (None, Some(login)) if login.contains('@') => (Some(login), None),
+                (None, None) => return Err("Login has no username or email".to_string()),
+                fields => fields,
+            };
+            if entry.notes.is_some() {
+                warnings.push("Notes were not imported".to_string());
+            }
+            if entry.name.is_none() && entry.uris.len() > 1 {
+                warnings.push(format!("Service was taken from the first of {} URLs", entry.uris.len()));
+            }
+
+            Record {
+                record_type: RecordTypes::Password,
+                id: None,
+                user_id: None,
+                key: None,
+                secret: None,
+                service: Some(service),
+                password: Some(password),
+                email,
+                username,
+                two_factor: None,
+                revision: None,
+                created_at: None,
+                updated_at: None,
+                password_changed_at: None,
+                breach: None,
+                rotation_period: None,
+                rotation_reminded_at: None,
+                uris: if entry.uris.is_empty() { None } else { Some(entry.uris.clone()) },
+                tags: if entry.tags.is_empty() { None } else { Some(entry.tags.clone()) },
+                org_id: None,
+                collection_id: None,
+            }
+        }
+        EntryKind::Note => {
+            let key = entry
+                .name
+                .clone()
+                .ok_or_else(|| "Secure note has no name".to_string())?;
+            let secret = entry
+                .notes
+                .clone()
+                .ok_or_else(|| "Secure note is empty".to_string())?;
+
+            Record {
+                record_type: RecordTypes::Secret,
+                id: None,
+                user_id: None,
+                key: Some(key),
+                secret: Some(secret),
+                service: None,
+                password: None,
+                email: None,
+                username: None,
+                two_factor: None,
+                revision: None,
+                created_at: None,
+                updated_at: None,
+                password_changed_at: None,
+                breach: None,
+                rotation_period: None,
+                rotation_reminded_at: None,
+                uris: None,
+                tags: if entry.tags.is_empty() { None } else { Some(entry.tags.clone()) },
+                org_id: None,
+                collection_id: None,
+            }
+        }
+    };
+
+    Ok((record, warnings))
+}
+
+/**
+ * Trim a value, empty values are treated as missing
+ */
+pub(crate) fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/**
+ * Host of a URL, ie https://app.example.com:8080/login -> app.example.com
+ */
+pub(crate) fn host_of(uri: &str) -> Option<String> {
+    let without_scheme = uri.split_once("://").map(|(_, rest)| rest).unwrap_or(uri);
+    let authority = without_scheme
+        .split(['/', '?', '#'])
+        .next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    non_empty(Some(host)).map(|host| host.to_lowercase())
+}
+
+/**
+ * Read a CSV export into rows keyed by the lowercase header name
+ */
+pub(crate) fn read_csv(data: &[u8]) -> Result<Vec<HashMap<String, String>>, ApiErrors> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|err| ApiErrors::BadRequest(format!("CSV could not be read: {}", err)))?
+        .iter()
+        .map(|header| header.trim().trim_start_matches('\u{feff}').to_lowercase())
+        .collect();
+
+    let mut rows = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(|err| ApiErrors::BadRequest(format!("CSV could not be read: {}", err)))?;
+        rows.push(
+            headers
+                .iter()
+                .cloned()
+                .zip(row.iter().map(|value| value.to_string()))
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+/**
+ * First non empty value out of a list of possible column names
+ */
+pub(crate) fn column(row: &HashMap<String, String>, names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| non_empty(row.get(*name).map(|value| value.as_str())))
+}
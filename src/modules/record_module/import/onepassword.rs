@@ -0,0 +1,167 @@
+use std::io::{Cursor, Read};
+
+use rocket::serde::json::{serde_json, Value};
+use serde::Deserialize;
+
+use super::{column, non_empty, read_csv, EntryKind, ImportEntry};
+use crate::shared::types::{ApiErrors, RecordUri};
+
+#[derive(Debug, Deserialize)]
+struct Export {
+    #[serde(default)]
+    accounts: Vec<Account>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    #[serde(default)]
+    vaults: Vec<Vault>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vault {
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(default)]
+    state: Option<String>,
+    category_uuid: String,
+    overview: Overview,
+    details: Details,
+}
+
+#[derive(Debug, Deserialize)]
+struct Overview {
+    title: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    urls: Vec<Url>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Url {
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Details {
+    #[serde(default)]
+    login_fields: Vec<LoginField>,
+    notes_plain: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    sections: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginField {
+    value: Option<String>,
+    designation: Option<String>,
+}
+
+fn kind_of(category_uuid: &str) -> EntryKind {
+    match category_uuid {
+        "001" | "005" => EntryKind::Login,
+        "003" => EntryKind::Note,
+        "002" => EntryKind::Unsupported("Credit Card".to_string()),
+        "004" => EntryKind::Unsupported("Identity".to_string()),
+        other => EntryKind::Unsupported(format!("Category {}", other)),
+    }
+}
+
+/**
+ * Parse a 1Password 1PUX export, a zip archive holding the items in export.data
+ */
+pub fn parse_1pux(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|_| ApiErrors::BadRequest("1PUX export is not a valid archive".to_string()))?;
+    let mut export_data = String::new();
+    archive
+        .by_name("export.data")
+        .map_err(|_| ApiErrors::BadRequest("1PUX export is missing export.data".to_string()))?
+        .read_to_string(&mut export_data)
+        .map_err(|_| ApiErrors::BadRequest("1PUX export.data could not be read".to_string()))?;
+    let export: Export = serde_json::from_str(&export_data)
+        .map_err(|err| ApiErrors::BadRequest(format!("1PUX export could not be read: {}", err)))?;
+
+    Ok(export
+        .accounts
+        .into_iter()
+        .flat_map(|account| account.vaults)
+        .flat_map(|vault| vault.items)
+        .map(|item| {
+            let kind = match item.state.as_deref() {
+                Some("archived") => EntryKind::Unsupported("Archived".to_string()),
+                _ => kind_of(&item.category_uuid),
+            };
+            let mut entry = ImportEntry::new(kind);
+            entry.name = non_empty(item.overview.title.as_deref());
+            entry.notes = non_empty(item.details.notes_plain.as_deref());
+            entry.uris = item
+                .overview
+                .url
+                .iter()
+                .chain(item.overview.urls.iter().filter_map(|url| url.url.as_ref()))
+                .filter_map(|url| non_empty(Some(url)))
+                .map(RecordUri::from)
+                .fold(Vec::new(), |mut uris, url| {
+                    if !uris.contains(&url) {
+                        uris.push(url);
+                    }
+                    uris
+                });
+            for field in &item.details.login_fields {
+                match field.designation.as_deref() {
+                    Some("username") => entry.username = non_empty(field.value.as_deref()),
+                    Some("password") => entry.password = non_empty(field.value.as_deref()),
+                    _ => {}
+                }
+            }
+            // Password items keep their value outside of the login fields
+            if entry.password.is_none() {
+                entry.password = non_empty(item.details.password.as_deref());
+            }
+            if !item.details.sections.is_empty() {
+                entry.dropped_fields.push("Sections".to_string());
+            }
+            entry
+        })
+        .collect())
+}
+
+/**
+ * Parse a 1Password CSV export, the columns differ between versions so they are matched by name
+ */
+pub fn parse_csv(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    Ok(read_csv(data)?
+        .iter()
+        .map(|row| {
+            let username = column(row, &["username"]);
+            let password = column(row, &["password"]);
+            let kind = if username.is_some() || password.is_some() {
+                EntryKind::Login
+            } else {
+                EntryKind::Note
+            };
+
+            let mut entry = ImportEntry::new(kind);
+            entry.name = column(row, &["title", "name"]);
+            entry.notes = column(row, &["notes", "notesplain"]);
+            entry.username = username;
+            entry.password = password;
+            entry.uris = column(row, &["url", "urls", "website", "login url"])
+                .into_iter()
+                .map(RecordUri::from)
+                .collect();
+            if column(row, &["otpauth", "one-time password"]).is_some() {
+                entry.dropped_fields.push("TOTP".to_string());
+            }
+            entry
+        })
+        .collect())
+}
@@ -0,0 +1,80 @@
+use rocket::serde::json::serde_json;
+
+use super::{column, read_csv, EntryKind, ImportEntry};
+use crate::{
+    modules::record_module::export::{EncryptedVaultExport, ExportedRecord, VaultExport},
+    shared::{
+        encryption::decrypt_with_passphrase,
+        types::{ApiErrors, RecordTypes},
+    },
+};
+
+fn to_entry(record: ExportedRecord) -> ImportEntry {
+    match record.record_type {
+        RecordTypes::Password => {
+            let mut entry = ImportEntry::new(EntryKind::Login);
+            entry.name = record.service;
+            entry.email = record.email;
+            entry.username = record.username;
+            entry.password = record.password;
+            entry.uris = record.uris.unwrap_or_default();
+            entry.tags = record.tags.unwrap_or_default();
+            entry
+        }
+        RecordTypes::Secret => {
+            let mut entry = ImportEntry::new(EntryKind::Note);
+            entry.name = record.key;
+            entry.notes = record.secret;
+            entry.tags = record.tags.unwrap_or_default();
+            entry
+        }
+    }
+}
+
+/**
+ * Parse a JSON export of this API
+ */
+pub fn parse_json(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    let export: VaultExport = serde_json::from_slice(data)
+        .map_err(|err| ApiErrors::BadRequest(format!("Vault export could not be read: {}", err)))?;
+    Ok(export.records.into_iter().map(to_entry).collect())
+}
+
+/**
+ * Parse a CSV export of this API
+ */
+pub fn parse_csv(data: &[u8]) -> Result<Vec<ImportEntry>, ApiErrors> {
+    Ok(read_csv(data)?
+        .iter()
+        .map(|row| {
+            let kind = match column(row, &["record_type"]).as_deref() {
+                Some("Password") => EntryKind::Login,
+                Some("Secret") => EntryKind::Note,
+                other => EntryKind::Unsupported(other.unwrap_or("Untyped").to_string()),
+            };
+            let mut entry = ImportEntry::new(kind);
+            if entry.kind == EntryKind::Note {
+                entry.name = column(row, &["key"]);
+                entry.notes = column(row, &["secret"]);
+            } else {
+                entry.name = column(row, &["service"]);
+                entry.email = column(row, &["email"]);
+                entry.username = column(row, &["username"]);
+                entry.password = column(row, &["password"]);
+            }
+            entry
+        })
+        .collect())
+}
+
+/**
+ * Parse a passphrase encrypted export of this API
+ */
+pub fn parse_encrypted(data: &[u8], passphrase: Option<&str>) -> Result<Vec<ImportEntry>, ApiErrors> {
+    let passphrase = passphrase
+        .ok_or_else(|| ApiErrors::BadRequest("X-Export-Passphrase header is required".to_string()))?;
+    let export: EncryptedVaultExport = serde_json::from_slice(data)
+        .map_err(|err| ApiErrors::BadRequest(format!("Vault export could not be read: {}", err)))?;
+    let decrypted = decrypt_with_passphrase(&export.payload, passphrase)?;
+    parse_json(&decrypted)
+}
@@ -1,33 +1,78 @@
+pub mod attachments;
+pub mod breach;
 pub mod component;
+pub mod export;
+pub mod health;
+pub mod import;
+pub mod rotation;
+pub mod uri_match;
 
 use crate::{
-    drivers::mongodb::mongo_trait::TMongoClient,
+    drivers::{breach::breach_trait::BreachSource, storage::storage_trait::Storage},
+    modules::auth_module::reauth::{reveal_to, Reauthenticated},
     shared::{
+        env_config::get_import_max_bytes,
+        etag::{ETag, IfMatch},
         jwt_service::Token,
+        pagination::{Page, PageCursor, PageRequest},
+        policy::{authorize, Action, Resource},
         types::{
-            ApiErrors, CreatedResponse,
-            Record, ResponseRecord, UpdateRecord,
+            ApiErrors, BulkRequest, BulkResponse, CreatedResponse, ImportReport,
+            NewRecord, RecordResponse, ResponseAttachment, ResponseRecord, ResponseRevealedRecord,
+            UpdateRecord, UpdatedResponse,
         },
     },
 };
+use attachments::AttachmentDownload;
 use bson::oid::ObjectId;
+use breach::BreachCheckReport;
+use export::{ExportFormat, ExportPassphrase, ExportResponse};
+use health::HealthReport;
+use rotation::DueRecord;
+use uri_match::{MatchedRecord, UriMatcher};
+use import::ImportFormat;
 use mongodb::bson::doc;
-use rocket::{http::Status, serde::json::Json, State};
+use rocket::{data::ToByteUnit, http::{ContentType, Status}, serde::json::Json, Data, State};
 
 /*
     Routes in this file:
+    GET /password/:user_id/all?limit=&cursor=&sort=&include_secrets= -> Get a page of the records of a user
+    GET /password/:user_id/health?max_age_days= -> Report weak, reused and old passwords of a user
+    POST /password/:user_id/breaches -> Check the records of a user against the breach corpus
+    GET /password/due-for-rotation?within_days= -> Get the password records due for rotation
+    GET /password/match?uri= -> Get the password records that apply to a URI
     get /password/:id -> Get a password record
+    POST /password/:id/reveal -> Get the plaintext password or secret of a record
     POST /password -> Create a password record
     PATCH password/:id -> Update a password record
     DELETE /password/:id -> Delete a password record
+    POST /password/bulk -> Create, update and delete records in one request
+    POST /password/import?format=&dry_run= -> Import the export of another password manager
+    GET /password/export?format= -> Export every record of a user
+    POST /password/:id/attachments?name= -> Attach the file in the body to a record
+    GET /password/:id/attachments -> Get the attachments of a record
+    GET /password/:id/attachments/:attachment_id -> Download an attachment
+    DELETE /password/:id/attachments/:attachment_id -> Delete an attachment
 */
 
-#[get("/<user_id>/all")]
+pub const DEFAULT_RECORDS_LIMIT: i64 = 50;
+
+fn parse_id(id: String) -> Result<ObjectId, ApiErrors> {
+    ObjectId::parse_str(id).map_err(|_| ApiErrors::BadRequest("ID is not formatted correctly".to_string()))
+}
+
+#[get("/<user_id>/all?<limit>&<cursor>&<sort>&<include_secrets>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn get_all_user_records(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     user_id: String,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    include_secrets: Option<bool>,
+    reauth: Result<Reauthenticated, ApiErrors>,
     token: Token,
-) -> Result<Json<Vec<ResponseRecord>>, ApiErrors> {
+) -> Result<Json<Page<ResponseRecord>>, ApiErrors> {
     let user_id = match ObjectId::parse_str(user_id) {
         Ok(res) => res,
         Err(_) => {
@@ -36,19 +81,84 @@ pub async fn get_all_user_records(
             ))
         }
     };
-    if token.id != user_id {
-        return Err(ApiErrors::Unauthorized("Not Authorized".to_string()));
+    let page = PageRequest::new(limit, DEFAULT_RECORDS_LIMIT, sort.as_deref(), cursor.as_deref())?;
+    if let Some(PageCursor::Offset { .. }) = page.cursor {
+        return Err(ApiErrors::BadRequest("Cursor was made for another list".to_string()));
     }
-    let records = component::get_all_user_records(db, user_id).await?;
+    // Plaintext passwords and secrets need the account password again
+    let reveal_to = reveal_to(include_secrets, reauth)?;
+    authorize(db.inner().as_ref(), token.id, Action::Read, Resource::Vault(user_id)).await?;
+    let records = component::get_user_records_page(db, user_id, page, reveal_to).await?;
+    Ok(Json(records))
+}
+
+#[get("/<user_id>/health?<max_age_days>")]
+pub async fn get_vault_health(
+    db: &State<Box<dyn Storage>>,
+    user_id: String,
+    max_age_days: Option<u32>,
+    token: Token,
+) -> Result<Json<HealthReport>, ApiErrors> {
+    let user_id = match ObjectId::parse_str(user_id) {
+        Ok(res) => res,
+        Err(_) => {
+            return Err(ApiErrors::BadRequest(
+                "User Id is not formatted correctly".to_string(),
+            ))
+        }
+    };
+    authorize(db.inner().as_ref(), token.id, Action::Read, Resource::Vault(user_id)).await?;
+    let report = component::vault_health(db, user_id, max_age_days).await?;
+    Ok(Json(report))
+}
+
+#[post("/<user_id>/breaches")]
+pub async fn check_breaches(
+    db: &State<Box<dyn Storage>>,
+    source: &State<Box<dyn BreachSource>>,
+    user_id: String,
+    token: Token,
+) -> Result<Json<BreachCheckReport>, ApiErrors> {
+    let user_id = match ObjectId::parse_str(user_id) {
+        Ok(res) => res,
+        Err(_) => {
+            return Err(ApiErrors::BadRequest(
+                "User Id is not formatted correctly".to_string(),
+            ))
+        }
+    };
+    authorize(db.inner().as_ref(), token.id, Action::Write, Resource::Vault(user_id)).await?;
+    let report = breach::check_user_breaches(db.inner().as_ref(), source.inner().as_ref(), user_id).await?;
+    Ok(Json(report))
+}
+
+#[get("/due-for-rotation?<within_days>")]
+pub async fn get_due_for_rotation(
+    db: &State<Box<dyn Storage>>,
+    within_days: Option<i64>,
+    token: Token,
+) -> Result<Json<Vec<DueRecord>>, ApiErrors> {
+    let records = component::get_due_for_rotation(db, token.id, within_days).await?;
+    Ok(Json(records))
+}
+
+#[get("/match?<uri>")]
+pub async fn match_records(
+    db: &State<Box<dyn Storage>>,
+    matcher: &State<UriMatcher>,
+    uri: String,
+    token: Token,
+) -> Result<Json<Vec<MatchedRecord>>, ApiErrors> {
+    let records = component::match_records(db, matcher, token.id, &uri).await?;
     Ok(Json(records))
 }
 
 #[get("/<id>")]
 pub async fn get_record(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     id: String,
     user_id: Token,
-) -> Result<Json<ResponseRecord>, ApiErrors> {
+) -> Result<RecordResponse, ApiErrors> {
     let record_id = match ObjectId::parse_str(id) {
         Ok(res) => res,
         Err(_) => {
@@ -60,28 +170,106 @@ pub async fn get_record(
 
     let res = component::get_record(db, record_id, user_id.id).await?;
 
-    Ok(Json(res))
+    Ok(RecordResponse {
+        etag: ETag::from_revision(res.revision),
+        record: Json(res),
+    })
+}
+
+#[post("/<id>/reveal")]
+pub async fn reveal_record(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Json<ResponseRevealedRecord>, ApiErrors> {
+    let revealed = component::reveal_record(db, parse_id(id)?, token.id).await?;
+    Ok(Json(revealed))
 }
 
 #[post("/", data = "<record>")]
 pub async fn create_record(
-    db: &State<Box<dyn TMongoClient>>,
-    record: Json<Record>,
+    db: &State<Box<dyn Storage>>,
+    record: Json<NewRecord>,
     id: Token,
 ) -> Result<CreatedResponse, ApiErrors> {
-    let res = component::create_record(db, record.0, id.id).await?;
+    let (res, generated_password) = component::create_record(db, record.0, id.id).await?;
+    let mut body = doc! { "id": res.to_string() };
+    if let Some(password) = generated_password {
+        body.insert("password", password);
+    }
     Ok(CreatedResponse {
-        id: Json(doc! { "id": res.to_string() }),
+        id: Json(body),
     })
 }
 
+#[post("/bulk", data = "<bulk>")]
+pub async fn bulk_records(
+    db: &State<Box<dyn Storage>>,
+    bulk: Json<BulkRequest>,
+    token: Token,
+) -> Result<(Status, Json<BulkResponse>), ApiErrors> {
+    let res = component::bulk_records(db, bulk.0, token.id).await?;
+    // Only an atomic batch can be rejected as a whole, otherwise every item has its own status
+    let status = if res.applied { Status::Ok } else { Status::BadRequest };
+    Ok((status, Json(res)))
+}
+
+#[post("/import?<format>&<dry_run>", data = "<data>")]
+pub async fn import_records(
+    db: &State<Box<dyn Storage>>,
+    format: Option<ImportFormat>,
+    dry_run: Option<bool>,
+    data: Data<'_>,
+    passphrase: ExportPassphrase,
+    token: Token,
+) -> Result<Json<ImportReport>, ApiErrors> {
+    let format = format.ok_or_else(|| {
+        ApiErrors::BadRequest(
+            "format must be one of bitwarden_json, bitwarden_csv, lastpass_csv, onepassword_1pux, onepassword_csv, keepass_xml, vault_json, vault_csv, vault_encrypted".to_string(),
+        )
+    })?;
+    let data = data
+        .open(get_import_max_bytes().bytes())
+        .into_bytes()
+        .await
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+    if !data.is_complete() {
+        return Err(ApiErrors::BadRequest("Import file is too large".to_string()));
+    }
+
+    let report = component::import_records(
+        db,
+        format,
+        &data,
+        passphrase.0.as_deref(),
+        dry_run.unwrap_or(false),
+        token.id,
+    )
+    .await?;
+    Ok(Json(report))
+}
+
+#[get("/export?<format>")]
+pub async fn export_records(
+    db: &State<Box<dyn Storage>>,
+    format: Option<ExportFormat>,
+    passphrase: ExportPassphrase,
+    user: Reauthenticated,
+) -> Result<ExportResponse, ApiErrors> {
+    let format = format.ok_or_else(|| {
+        ApiErrors::BadRequest("format must be one of json, csv, encrypted".to_string())
+    })?;
+    component::export_records(db, format, passphrase.0, user.id).await
+}
+
 #[patch("/<id>", data = "<updated_record>")]
 pub async fn update_record(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     updated_record: Json<UpdateRecord>,
     id: String,
     user_id: Token,
-) -> Result<Status, ApiErrors> {
+    if_match: IfMatch,
+) -> Result<UpdatedResponse, ApiErrors> {
     let record_id = match ObjectId::parse_str(id) {
         Ok(res) => res,
         Err(_) => {
@@ -90,15 +278,19 @@ pub async fn update_record(
             ))
         }
     };
-    component::update_record(db, updated_record.0, record_id, user_id.id).await?;
-    Ok(Status::NoContent)
+    let revision = component::update_record(db, updated_record.0, record_id, user_id.id, &if_match).await?;
+    Ok(UpdatedResponse {
+        body: (),
+        etag: ETag(revision),
+    })
 }
 
 #[delete("/<id>")]
 pub async fn delete_record(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     id: String,
     user_id: Token,
+    if_match: IfMatch,
 ) -> Result<Status, ApiErrors> {
     let record_id = match ObjectId::parse_str(id) {
         Ok(res) => res,
@@ -108,17 +300,81 @@ pub async fn delete_record(
             ))
         }
     };
-    component::delete_record(db, record_id, user_id.id).await?;
+    component::delete_record(db, record_id, user_id.id, &if_match).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[post("/<id>/attachments?<name>", data = "<data>")]
+pub async fn upload_attachment(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    name: Option<String>,
+    content_type: Option<&ContentType>,
+    data: Data<'_>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let record_id = parse_id(id)?;
+    let name = name.ok_or_else(|| ApiErrors::BadRequest("name is required".to_string()))?;
+    let content_type = content_type.unwrap_or(&ContentType::Binary).to_string();
+
+    let attachment_id =
+        attachments::upload_attachment(db, record_id, token.id, name, content_type, data).await?;
+
+    Ok(CreatedResponse {
+        id: Json(doc! { "id": attachment_id.to_string() }),
+    })
+}
 
+#[get("/<id>/attachments")]
+pub async fn get_attachments(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Json<Vec<ResponseAttachment>>, ApiErrors> {
+    let attachments = attachments::get_attachments(db, parse_id(id)?, token.id).await?;
+    Ok(Json(attachments))
+}
+
+#[get("/<id>/attachments/<attachment_id>")]
+pub async fn download_attachment(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    attachment_id: String,
+    token: Token,
+) -> Result<AttachmentDownload, ApiErrors> {
+    attachments::download_attachment(db, parse_id(id)?, parse_id(attachment_id)?, token.id).await
+}
+
+#[delete("/<id>/attachments/<attachment_id>")]
+pub async fn delete_attachment(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    attachment_id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    attachments::delete_attachment(db, parse_id(id)?, parse_id(attachment_id)?, token.id).await?;
     Ok(Status::NoContent)
 }
 
 pub fn api() -> Vec<rocket::Route> {
     rocket::routes![
         get_record,
+        reveal_record,
         create_record,
         update_record,
         delete_record,
-        get_all_user_records
+        get_all_user_records,
+        get_vault_health,
+        check_breaches,
+        get_due_for_rotation,
+        match_records,
+        bulk_records,
+        import_records,
+        export_records,
+        upload_attachment,
+        get_attachments,
+        download_attachment,
+        delete_attachment
     ]
 }
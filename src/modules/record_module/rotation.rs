@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use bson::{oid::ObjectId, DateTime};
+use rocket::{
+    serde::json::serde_json::json,
+    tokio::{self, time},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    drivers::{notifier::notifier_trait::Notifier, storage::storage_trait::Storage},
+    shared::types::{ApiErrors, Notification, NotificationEvent, Record, RecordTypes},
+};
+
+pub const MAX_ROTATION_PERIOD_DAYS: i64 = 3650;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DueRecord {
+    pub id: String,
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub rotation_period: i64,
+    pub due_at: String,
+    /// Negative once the record is overdue
+    pub days_left: i64,
+    pub overdue: bool,
+}
+
+/**
+ * When the password was last rotated. Records from before change dates were stored fall back to
+ * their creation date, or count as never rotated
+ */
+fn rotated_at(record: &Record) -> DateTime {
+    record
+        .password_changed_at
+        .or(record.created_at)
+        .unwrap_or_else(|| DateTime::from_millis(0))
+}
+
+/**
+ * When the password of a record has to be rotated, None if it has no rotation period
+ */
+pub fn due_at(record: &Record) -> Option<DateTime> {
+    let period = record.rotation_period?;
+    Some(DateTime::from_millis(rotated_at(record).timestamp_millis() + period * MILLIS_PER_DAY))
+}
+
+/**
+ * Password records due within `within_days` of now or overdue, soonest first
+ */
+pub fn due_records(records: &[Record], now: DateTime, within_days: i64) -> Vec<(&Record, DateTime)> {
+    let horizon = now.timestamp_millis() + within_days * MILLIS_PER_DAY;
+    let mut due: Vec<(&Record, DateTime)> = records
+        .iter()
+        .filter(|record| record.record_type == RecordTypes::Password)
+        .filter_map(|record| due_at(record).map(|due_at| (record, due_at)))
+        .filter(|(_, due_at)| due_at.timestamp_millis() <= horizon)
+        .collect();
+    due.sort_by_key(|(_, due_at)| *due_at);
+    due
+}
+
+pub fn to_due_record(record: &Record, due_at: DateTime, now: DateTime) -> Result<DueRecord, ApiErrors> {
+    let millis_left = due_at.timestamp_millis() - now.timestamp_millis();
+    Ok(DueRecord {
+        id: record.id.map(|id| id.to_string()).unwrap_or_default(),
+        service: record.service.clone(),
+        email: record.email.clone(),
+        username: record.username.clone(),
+        rotation_period: record.rotation_period.unwrap_or_default(),
+        due_at: due_at
+            .try_to_rfc3339_string()
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+        days_left: millis_left / MILLIS_PER_DAY,
+        overdue: millis_left <= 0,
+    })
+}
+
+/**
+ * A record gets one reminder when it becomes due soon and another once it is overdue,
+ * rotating the password starts over
+ */
+fn needs_reminder(record: &Record, due_at: DateTime, now: DateTime) -> bool {
+    match record.rotation_reminded_at {
+        None => true,
+        Some(reminded_at) => {
+            reminded_at < rotated_at(record) || (due_at <= now && reminded_at < due_at)
+        }
+    }
+}
+
+fn reminder(user_id: ObjectId, email: Option<String>, due: &DueRecord, now: DateTime) -> Result<Notification, ApiErrors> {
+    let service = due.service.clone().unwrap_or_else(|| "a record".to_string());
+    let (event, subject) = if due.overdue {
+        (NotificationEvent::RotationOverdue, format!("The password for {} is overdue for rotation", service))
+    } else {
+        (NotificationEvent::RotationDueSoon, format!("The password for {} is due for rotation", service))
+    };
+    Ok(Notification {
+        event,
+        user_id: user_id.to_string(),
+        email,
+        message: format!(
+            "The password for {} has to be rotated every {} days and is due {}",
+            service, due.rotation_period, due.due_at
+        ),
+        subject,
+        data: json!(due),
+        created_at: now
+            .try_to_rfc3339_string()
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+    })
+}
+
+/**
+ * Send the reminders of one user that have not been sent yet, returns how many were sent
+ */
+pub async fn remind_user(
+    db: &dyn Storage,
+    notifier: &dyn Notifier,
+    user_id: ObjectId,
+    within_days: i64,
+) -> Result<usize, ApiErrors> {
+    let now = DateTime::now();
+    let records = db.get_user_record_metadata(user_id).await?;
+    let pending: Vec<(&Record, DateTime)> = due_records(&records, now, within_days)
+        .into_iter()
+        .filter(|(record, due_at)| needs_reminder(record, *due_at, now))
+        .collect();
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let email = db.get_user_by_id(user_id).await?.email;
+    let mut sent = 0;
+    for (record, due_at) in pending {
+        let record_id = match record.id {
+            Some(id) => id,
+            None => continue,
+        };
+        let due = to_due_record(record, due_at, now)?;
+        notifier.notify(&reminder(user_id, Some(email.clone()), &due, now)?).await?;
+        db.set_rotation_reminded(record_id, user_id, now).await?;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+async fn remind_every_user(db: &dyn Storage, notifier: &dyn Notifier, within_days: i64) {
+    let user_ids = match db.get_record_owner_ids().await {
+        Ok(user_ids) => user_ids,
+        Err(err) => {
+            println!("Rotation reminders could not list vaults: {:?}", err);
+            return;
+        }
+    };
+    for user_id in user_ids {
+        if let Err(err) = remind_user(db, notifier, user_id, within_days).await {
+            println!("Rotation reminders of user {} failed: {:?}", user_id, err);
+        }
+    }
+}
+
+/**
+ * Send rotation reminders now and then every `interval_hours`, an interval of 0 turns the job off
+ */
+pub fn spawn_rotation_reminders(
+    db: Box<dyn Storage>,
+    notifier: Box<dyn Notifier>,
+    interval_hours: u64,
+    within_days: i64,
+) {
+    if interval_hours == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(interval_hours * 60 * 60));
+        loop {
+            interval.tick().await;
+            remind_every_user(db.as_ref(), notifier.as_ref(), within_days).await;
+        }
+    });
+}
@@ -0,0 +1,243 @@
+use std::fs;
+
+use publicsuffix::{List, Psl};
+use regex::RegexBuilder;
+use rocket::serde::json::serde_json;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::shared::types::{ApiErrors, Record, RecordTypes, RecordUri, ResponseRecord, UriMatch};
+
+pub const MAX_URIS: usize = 50;
+pub const MAX_URI_LENGTH: usize = 2048;
+/// Public suffix list bundled with the api, used unless `PUBLIC_SUFFIX_LIST` points to another one
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../../../public_suffix_list.dat");
+/// Compiled size limit of a regex URI, keeps patterns from using too much memory
+const MAX_REGEX_SIZE: usize = 1 << 16;
+
+// Higher is more specific. A starts with match also gains a point per character of its prefix
+const EXACT_SCORE: u32 = 1000;
+const STARTS_WITH_SCORE: u32 = 400;
+const MAX_PREFIX_BONUS: u32 = 500;
+const HOST_SCORE: u32 = 300;
+const REGEX_SCORE: u32 = 250;
+const DOMAIN_SCORE: u32 = 200;
+const EQUIVALENT_DOMAIN_SCORE: u32 = 100;
+/// Records without URIs are matched on their service when it looks like a domain
+const SERVICE_DOMAIN_SCORE: u32 = 50;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MatchedRecord {
+    pub record: ResponseRecord,
+    /// The URI of the record that matched, or its service
+    pub matched_uri: String,
+    #[serde(rename = "match")]
+    pub match_mode: UriMatch,
+    pub score: u32,
+}
+
+/**
+ * Compares record URIs with the URI of a page, shared as managed state
+ */
+pub struct UriMatcher {
+    /// Without a suffix list base domains cannot be told apart, domain matches then compare hosts
+    suffixes: Option<List>,
+    /// Groups of base domains that share credentials, ie `google.com` and `youtube.com`
+    equivalent_domains: Vec<Vec<String>>,
+}
+
+impl UriMatcher {
+    pub fn new(suffixes: Option<List>, equivalent_domains: Vec<Vec<String>>) -> UriMatcher {
+        let equivalent_domains = equivalent_domains
+            .into_iter()
+            .map(|group| group.into_iter().map(|domain| domain.trim().to_lowercase()).collect())
+            .collect();
+        UriMatcher { suffixes, equivalent_domains }
+    }
+
+    /**
+     * Load the equivalent domains table from disk along with the public suffix list.
+     * A list at `suffix_list_path` replaces the bundled one, the bundled one is used when it cannot be read
+     */
+    pub fn load(suffix_list_path: Option<&str>, equivalent_domains_path: &str) -> UriMatcher {
+        let suffixes = suffix_list_path
+            .and_then(|path| match fs::read_to_string(path) {
+                Ok(contents) => parse_suffix_list(path, &contents),
+                Err(err) => {
+                    println!("Public suffix list {} could not be read: {}", path, err);
+                    None
+                }
+            })
+            .or_else(|| parse_suffix_list("bundled", PUBLIC_SUFFIX_LIST));
+        if suffixes.is_none() {
+            println!("No public suffix list is loaded, domain matches will only match the same host");
+        }
+        let equivalent_domains = match fs::read_to_string(equivalent_domains_path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                println!("Equivalent domains {} could not be parsed: {}", equivalent_domains_path, err);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        UriMatcher::new(suffixes, equivalent_domains)
+    }
+
+    /**
+     * The registrable domain of a host, ie `example.co.uk` for `app.example.co.uk`.
+     * IP addresses and hosts without a known suffix are returned as they are
+     */
+    pub fn base_domain(&self, host: &str) -> String {
+        let host = host.trim_end_matches('.').to_lowercase();
+        if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+            return host;
+        }
+        self.suffixes
+            .as_ref()
+            .and_then(|suffixes| suffixes.domain(host.as_bytes()))
+            .and_then(|domain| std::str::from_utf8(domain.as_bytes()).ok().map(|domain| domain.to_string()))
+            .unwrap_or(host)
+    }
+
+    fn are_equivalent(&self, a: &str, b: &str) -> bool {
+        self.equivalent_domains
+            .iter()
+            .any(|group| group.iter().any(|domain| domain == a) && group.iter().any(|domain| domain == b))
+    }
+
+    fn domain_score(&self, record_host: &str, target_host: &str) -> Option<u32> {
+        if self.suffixes.is_none() {
+            return record_host
+                .trim_end_matches('.')
+                .eq_ignore_ascii_case(target_host.trim_end_matches('.'))
+                .then_some(DOMAIN_SCORE);
+        }
+        let record_domain = self.base_domain(record_host);
+        let target_domain = self.base_domain(target_host);
+        if record_domain == target_domain {
+            Some(DOMAIN_SCORE)
+        } else if self.are_equivalent(&record_domain, &target_domain) {
+            Some(EQUIVALENT_DOMAIN_SCORE)
+        } else {
+            None
+        }
+    }
+
+    /**
+     * How specifically a URI of a record matches the target, None if it does not match
+     */
+    pub fn score(&self, record_uri: &RecordUri, target: &Url) -> Option<u32> {
+        match record_uri.match_mode {
+            UriMatch::Exact => {
+                let uri = parse_uri(&record_uri.uri)?;
+                (uri.as_str() == target.as_str()).then_some(EXACT_SCORE)
+            }
+            UriMatch::StartsWith => {
+                // Compared once normalized, so `example.com/login` matches `https://example.com/login`
+                let prefix = parse_uri(&record_uri.uri)?;
+                let prefix = prefix.as_str();
+                target
+                    .as_str()
+                    .starts_with(prefix)
+                    .then(|| STARTS_WITH_SCORE + (prefix.len() as u32).min(MAX_PREFIX_BONUS))
+            }
+            UriMatch::Host => {
+                let uri = parse_uri(&record_uri.uri)?;
+                let same_host = uri.host_str()?.eq_ignore_ascii_case(target.host_str()?)
+                    && uri.port_or_known_default() == target.port_or_known_default();
+                same_host.then_some(HOST_SCORE)
+            }
+            UriMatch::Regex => compile_regex(&record_uri.uri)
+                .ok()?
+                .is_match(target.as_str())
+                .then_some(REGEX_SCORE),
+            UriMatch::Domain => self.domain_score(parse_uri(&record_uri.uri)?.host_str()?, target.host_str()?),
+        }
+    }
+
+    /**
+     * The most specific match of a record, records without URIs fall back to their service
+     */
+    pub fn best_match(&self, record: &Record, target: &Url) -> Option<(RecordUri, u32)> {
+        if record.record_type != RecordTypes::Password {
+            return None;
+        }
+        match &record.uris {
+            Some(uris) if !uris.is_empty() => uris
+                .iter()
+                .filter_map(|uri| self.score(uri, target).map(|score| (uri.clone(), score)))
+                .max_by_key(|(_, score)| *score),
+            _ => {
+                let service = record.service.as_ref().filter(|service| service.contains('.'))?;
+                let host = parse_uri(service)?.host_str()?.to_string();
+                self.domain_score(&host, target.host_str()?)
+                    .map(|score| (RecordUri::from(service.clone()), score * SERVICE_DOMAIN_SCORE / DOMAIN_SCORE))
+            }
+        }
+    }
+}
+
+/**
+ * A suffix list without any rule is not one, it would take the last label of every host as its suffix
+ */
+fn parse_suffix_list(source: &str, contents: &str) -> Option<List> {
+    match contents.parse::<List>() {
+        Ok(list) if !list.is_empty() => Some(list),
+        Ok(_) => {
+            println!("Public suffix list {} has no rules", source);
+            None
+        }
+        Err(err) => {
+            println!("Public suffix list {} could not be parsed: {:?}", source, err);
+            None
+        }
+    }
+}
+
+/**
+ * Parse a URI, ones without a scheme such as `example.com/login` are taken as https
+ */
+pub fn parse_uri(uri: &str) -> Option<Url> {
+    let uri = uri.trim();
+    if uri.contains("://") {
+        Url::parse(uri).ok()
+    } else {
+        Url::parse(&format!("https://{}", uri)).ok()
+    }
+}
+
+fn compile_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(MAX_REGEX_SIZE)
+        .build()
+}
+
+/**
+ * Check the URIs of a record can be matched against
+ */
+pub fn validate_uris(uris: &[RecordUri]) -> Result<(), ApiErrors> {
+    if uris.len() > MAX_URIS {
+        return Err(ApiErrors::BadRequest(format!("A record can have at most {} URIs", MAX_URIS)));
+    }
+    for record_uri in uris {
+        let uri = record_uri.uri.trim();
+        if uri.is_empty() || uri.len() > MAX_URI_LENGTH {
+            return Err(ApiErrors::BadRequest(format!(
+                "URIs must be between 1 and {} characters",
+                MAX_URI_LENGTH
+            )));
+        }
+        match record_uri.match_mode {
+            UriMatch::Regex => {
+                compile_regex(uri)
+                    .map_err(|err| ApiErrors::BadRequest(format!("{} is not a valid regex: {}", uri, err)))?;
+            }
+            UriMatch::Domain | UriMatch::Host => {
+                if parse_uri(uri).and_then(|url| url.host_str().map(|_| ())).is_none() {
+                    return Err(ApiErrors::BadRequest(format!("{} does not have a host", uri)));
+                }
+            }
+            UriMatch::Exact | UriMatch::StartsWith => {}
+        }
+    }
+    Ok(())
+}
@@ -1,50 +1,143 @@
-use crate::shared::{types::ApiErrors, encryption::decrypt_password};
+use std::collections::HashMap;
+
+use bson::Bson;
+
+use crate::{
+    modules::record_module::component::{decrypt_record, mask_record, record_reveal},
+    shared::{env_config::get_search_max_candidates, types::{ApiErrors, Record}},
+};
 
 use super::*;
+use super::relevance::{rank, FUZZY_CANDIDATE_BATCH};
 
 
+/**
+ * Search the records of a user, passwords and secrets are left out unless they are revealed to
+ * `reveal_to`
+ */
 pub async fn search_records(
-    db: &State<Box<dyn TMongoClient>>,
-    search_params: SearchParams
-) -> Result<Vec<SearchResponse>, ApiErrors> {
-    // Inside search_params
-    let mut res = db.search_records(search_params).await?;
-
-    let mut record_vec = Vec::new();
-
-    while let Some(record) = res.next().await {
-        let mut record = record.map_err(|err| ApiErrors::ServerError(err.to_string()))?;
-        
-        match record.record_type {
-            RecordTypes::Password => {
-                if let Some(password) = record.password {
-                    // Decrypt Password
-                    record.password = Some(decrypt_password(&password)?);
-                }
-            },
-            RecordTypes::Secret => {
-                if let Some(secret) = record.secret {
-                    // Decrypt Secret 
-                    record.secret = Some(decrypt_password(&secret)?);
-                }
-            }
+    db: &State<Box<dyn Storage>>,
+    search_params: SearchParams,
+    reveal_to: Option<ObjectId>,
+) -> Result<Page<SearchResponse>, ApiErrors> {
+    // Records shared with the user are searched too, by the permission they were shared with
+    let shares: HashMap<ObjectId, SharePermission> = db
+        .get_shares_with_user(search_params.user_id)
+        .await?
+        .into_iter()
+        .map(|share| (share.record_id, share.permission))
+        .collect();
+    let org_ids = db
+        .get_user_memberships(search_params.user_id)
+        .await?
+        .into_iter()
+        .map(|member| member.org_id)
+        .collect();
+    let search_params = SearchParams {
+        shared_record_ids: shares.keys().copied().collect(),
+        org_ids,
+        ..search_params
+    };
+
+    let page = match search_params.query.clone().filter(Expr::has_fuzzy) {
+        Some(query) => ranked_page(db, search_params, &query).await?,
+        None => {
+            let total = db.count_search_records(search_params.clone()).await?;
+            let records = db.search_records(search_params.clone()).await?;
+            search_params.page.page(records, total, |record| Ok((record, None)))?
         }
+    };
 
-        let record_id = record.id.ok_or(ApiErrors::ServerError("Object id was not found".to_string()))?.to_string();
-        let user_id = record.user_id.ok_or(ApiErrors::ServerError("User id was not found in record".to_string()))?.to_string();
-        // add to vector
-        record_vec.push(SearchResponse::new(
-            record_id,
-            user_id,
-            record.record_type,
-            record.service,
-            record.username,
-            record.password,
-            record.email,
-            record.key,
-            record.secret,
-        ));
+    let mut items = Vec::with_capacity(page.items.len());
+    for (record, score) in page.items {
+        let record = match reveal_to {
+            Some(actor_id) => {
+                let record = decrypt_record(record)?;
+                record_reveal(db.inner().as_ref(), actor_id, &record).await?;
+                record
+            }
+            None => mask_record(record),
+        };
+        items.push(to_search_response(record, score, &shares)?);
     }
+    Ok(Page { items, total: page.total, next_cursor: page.next_cursor, truncated: page.truncated })
+}
+
+/**
+ * A page of a fuzzy search. The candidates are ranked as a whole and paged by offset, as their
+ * order is not one the database can continue from. At most `SEARCH_MAX_CANDIDATES` are read, the
+ * page is truncated when there were more
+ */
+async fn ranked_page(
+    db: &State<Box<dyn Storage>>,
+    search_params: SearchParams,
+    query: &Expr,
+) -> Result<Page<(Record, Option<f64>)>, ApiErrors> {
+    let limit = search_params.page.limit as u64;
+    let offset = match search_params.page.cursor {
+        Some(PageCursor::Offset { offset }) => offset,
+        _ => 0,
+    };
+    // Candidates are read in batches by id up to the max
+    let max_candidates = get_search_max_candidates() as usize;
+    let mut records = Vec::new();
+    let mut candidates = PageRequest { limit: FUZZY_CANDIDATE_BATCH, sort: None, cursor: None };
+    let truncated = loop {
+        candidates.limit = FUZZY_CANDIDATE_BATCH.min((max_candidates - records.len()) as i64);
+        let mut batch = db.search_records(SearchParams { page: candidates.clone(), ..search_params.clone() }).await?;
+        let more = batch.len() as i64 > candidates.limit;
+        batch.truncate(candidates.limit as usize);
+        let last_id = batch.last().and_then(|record| record.id);
+        records.extend(batch);
+        match (more, last_id) {
+            (true, _) if records.len() >= max_candidates => break true,
+            (true, Some(id)) => candidates.cursor = Some(PageCursor::After { sort: None, value: Bson::Null, id }),
+            _ => break false,
+        }
+    };
+
+    let ranked = rank(query, records);
+    let total = ranked.len() as u64;
+    let next_cursor = match offset + limit < total {
+        true => Some(PageCursor::Offset { offset: offset + limit }.encode()?),
+        false => None,
+    };
+    Ok(Page {
+        items: ranked
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(record, score)| (record, Some(score)))
+            .collect(),
+        total,
+        next_cursor,
+        truncated,
+    })
+}
 
-    Ok(record_vec)
-}
\ No newline at end of file
+fn to_search_response(
+    record: Record,
+    score: Option<f64>,
+    shares: &HashMap<ObjectId, SharePermission>,
+) -> Result<SearchResponse, ApiErrors> {
+    let record_id = record.id.ok_or(ApiErrors::ServerError("Object id was not found".to_string()))?;
+    let shared = shares.get(&record_id).copied();
+    let record_id = record_id.to_string();
+    let user_id = record.user_id.ok_or(ApiErrors::ServerError("User id was not found in record".to_string()))?.to_string();
+    Ok(SearchResponse::new(
+        record_id,
+        user_id,
+        record.record_type,
+        record.service,
+        record.username,
+        record.password,
+        record.email,
+        record.key,
+        record.secret,
+    )
+    .shared(shared)
+    .in_organization(record.org_id)
+    .with_tags(record.tags)
+    .with_uris(record.uris)
+    .with_score(score))
+}
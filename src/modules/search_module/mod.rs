@@ -1,14 +1,33 @@
 /*
     Searching Module will be used for searching through password records and secret records
-    /<user_id>/search?record=&page=&service=&key=
-    * Record will be either secret or password
-    * Page will be the pagination number
-    * Service will be the name of the service to search for
-    * Key will be the key of the secret to get
+    /search/record/<user_id>?limit=&cursor=&sort=&query=&type=&mode=
+    * Cursor is the next_cursor of the previous page, sort is the field to sort by, see pagination.rs
+    * Query is the search, see query.rs for its syntax
+    * Mode is text to look for the text of the query as it is, the default, regex for patterns or
+      fuzzy for text close to it, ranked with the closest first
+    * Type will be either password or secret, repeat it to search both
+    * Include secrets returns the plaintext passwords and secrets, it needs the account password in
+      the X-Account-Password header. They are left out otherwise
+
+    Routes in this file:
+    GET /search/record/:user_id?limit=&cursor=&sort=&query=&type=&mode=&include_secrets= -> Search the records of a user
+    POST /search/saved -> Save a search of the vault of the user
+    GET /search/saved -> Get the saved searches of the user
+    GET /search/saved/:id -> Get a saved search
+    PUT /search/saved/:id -> Replace a saved search
+    DELETE /search/saved/:id -> Delete a saved search
+    GET /search/saved/:id/results?limit=&cursor=&include_secrets= -> Run a saved search
 */
-use rocket::{futures::stream::StreamExt, serde::json::Json};
+use rocket::{
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    Request,
+};
 
 pub mod component;
+pub mod query;
+pub mod relevance;
+pub mod saved;
 
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,9 +48,22 @@ pub struct SearchResponse {
     key: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     secret: Option<String>,
+    /// Set when the record belongs to another user and was shared with this one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shared: Option<SharePermission>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uris: Option<Vec<RecordUri>>,
+    /// How close the record is to a fuzzy query, between 0 and 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
 }
 
 impl SearchResponse {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: String,
         user_id: String,
@@ -53,28 +85,62 @@ impl SearchResponse {
             email,
             key,
             secret,
+            shared: None,
+            org_id: None,
+            tags: None,
+            uris: None,
+            score: None,
         }
     }
+
+    pub fn shared(mut self, permission: Option<SharePermission>) -> Self {
+        self.shared = permission;
+        self
+    }
+
+    pub fn in_organization(mut self, org_id: Option<ObjectId>) -> Self {
+        self.org_id = org_id.map(|id| id.to_string());
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Option<Vec<String>>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_uris(mut self, uris: Option<Vec<RecordUri>>) -> Self {
+        self.uris = uris;
+        self
+    }
+
+    pub fn with_score(mut self, score: Option<f64>) -> Self {
+        self.score = score;
+        self
+    }
 }
 
+pub const DEFAULT_SEARCH_LIMIT: i64 = 10;
+
 pub struct SearchParamsBuilder {
     pub user_id: ObjectId,
     pub password_record: Option<RecordTypes>,
     pub secret_record: Option<RecordTypes>,
-    pub page: Option<u64>,
-    pub query: Option<String>,
-    pub limit: Option<i64>,
+    pub page: Option<PageRequest>,
+    pub query: Option<Expr>,
+    pub shared_record_ids: Vec<ObjectId>,
+    pub org_ids: Vec<ObjectId>,
 }
 
 impl SearchParamsBuilder {
     pub fn new(user_id: ObjectId) -> SearchParamsBuilder {
         SearchParamsBuilder {
             user_id,
+            shared_record_ids: Vec::new(),
+            org_ids: Vec::new(),
             password_record: None,
             secret_record: None,
             query: None,
             page: None,
-            limit: None,
         }
     }
 
@@ -86,18 +152,23 @@ impl SearchParamsBuilder {
         self
     }
 
-    pub fn add_page(mut self, page: Option<u64>) -> Self {
-        self.page = page;
+    pub fn add_page(mut self, page: PageRequest) -> Self {
+        self.page = Some(page);
         self
     }
 
-    pub fn add_limit(mut self, limit: Option<i64>) -> Self {
-        self.limit = limit;
+    pub fn add_query(mut self, query: Option<Expr>) -> Self {
+        self.query = query;
         self
     }
 
-    pub fn add_query(mut self, query: Option<String>) -> Self {
-        self.query = query;
+    pub fn add_shared_records(mut self, record_ids: Vec<ObjectId>) -> Self {
+        self.shared_record_ids = record_ids;
+        self
+    }
+
+    pub fn add_organizations(mut self, org_ids: Vec<ObjectId>) -> Self {
+        self.org_ids = org_ids;
         self
     }
 
@@ -106,9 +177,14 @@ impl SearchParamsBuilder {
             user_id: self.user_id,
             password_record: self.password_record,
             secret_record: self.secret_record,
-            page: self.page,
+            page: self.page.unwrap_or(PageRequest {
+                limit: DEFAULT_SEARCH_LIMIT,
+                sort: None,
+                cursor: None,
+            }),
             query: self.query,
-            limit: self.limit,
+            shared_record_ids: self.shared_record_ids,
+            org_ids: self.org_ids,
         }
     }
 }
@@ -118,52 +194,212 @@ pub struct SearchParams {
     pub user_id: ObjectId,
     pub password_record: Option<RecordTypes>,
     pub secret_record: Option<RecordTypes>,
-    pub page: Option<u64>,
-    pub query: Option<String>,
-    pub limit: Option<i64>,
+    pub page: PageRequest,
+    /// The parsed query, every record matches when there is none
+    pub query: Option<Expr>,
+    /// Records of other users shared with the user, searched along with their own
+    pub shared_record_ids: Vec<ObjectId>,
+    /// Organizations the user is a member of, their records are searched too
+    pub org_ids: Vec<ObjectId>,
 }
 
 use bson::oid::ObjectId;
-use rocket::State;
+use mongodb::bson::doc;
+use rocket::{http::Status, State};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    drivers::mongodb::mongo_trait::TMongoClient,
+    drivers::storage::storage_trait::Storage,
+    modules::auth_module::reauth::{reveal_to, Reauthenticated},
     shared::{
         jwt_service::Token,
-        types::{ApiErrors, RecordTypes},
+        pagination::{Page, PageCursor, PageRequest},
+        policy::{authorize, Action, Resource},
+        types::{ApiErrors, CreatedResponse, NewSavedSearch, RecordTypes, RecordUri, ResponseSavedSearch, SharePermission},
     },
 };
+use query::{parse_query, Expr, SearchMode};
+
+/**
+ * Every `type` query parameter, `type` cannot be the name of a route argument
+ */
+pub struct TypeFilter(pub Vec<String>);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for TypeFilter {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(TypeFilter(
+            request
+                .query_fields()
+                .filter(|field| field.name == "type")
+                .map(|field| field.value.to_string())
+                .collect(),
+        ))
+    }
+}
+
+fn parse_record_type(record_type: &str) -> Result<RecordTypes, ApiErrors> {
+    if record_type.eq_ignore_ascii_case("password") {
+        Ok(RecordTypes::Password)
+    } else if record_type.eq_ignore_ascii_case("secret") {
+        Ok(RecordTypes::Secret)
+    } else {
+        Err(ApiErrors::BadRequest("type must be password or secret".to_string()))
+    }
+}
+
+fn parse_id(id: &str) -> Result<ObjectId, ApiErrors> {
+    ObjectId::parse_str(id).map_err(|_| ApiErrors::BadRequest("Provided Id is not an object id".to_string()))
+}
+
+/**
+ * Parse the query of a search and check its page can be read
+ */
+fn parse_search(query: Option<&str>, mode: Option<&str>, page: &PageRequest) -> Result<Option<Expr>, ApiErrors> {
+    let mode = mode.map(SearchMode::parse).transpose()?.unwrap_or_default();
+    let query = query.map(|query| parse_query(query, mode)).transpose()?.flatten();
+    // Fuzzy results are ranked, they have no sort and are paged by offset
+    let ranked = query.as_ref().is_some_and(Expr::has_fuzzy);
+    match (ranked, page) {
+        (true, PageRequest { sort: Some(_), .. }) => {
+            Err(ApiErrors::BadRequest("sort cannot be used with fuzzy terms".to_string()))
+        }
+        (true, PageRequest { cursor: Some(PageCursor::After { .. }), .. })
+        | (false, PageRequest { cursor: Some(PageCursor::Offset { .. }), .. }) => {
+            Err(ApiErrors::BadRequest("Cursor was made for another search".to_string()))
+        }
+        _ => Ok(query),
+    }
+}
+
+fn search_params(user_id: ObjectId, record_types: Vec<RecordTypes>, page: PageRequest, query: Option<Expr>) -> SearchParams {
+    record_types
+        .into_iter()
+        .fold(SearchParamsBuilder::new(user_id), |builder, record_type| builder.add_record(record_type))
+        .add_page(page)
+        .add_query(query)
+        .build()
+}
 
-#[get("/record/<user_id>?<page>&<limit>&<query>")]
+#[get("/record/<user_id>?<limit>&<cursor>&<sort>&<query>&<mode>&<include_secrets>")]
+#[allow(clippy::too_many_arguments)]
 async fn search_records(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     user_id: String,
-    page: Option<u64>,
-    query: Option<String>,
     limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    query: Option<String>,
+    mode: Option<String>,
+    include_secrets: Option<bool>,
+    types: TypeFilter,
+    reauth: Result<Reauthenticated, ApiErrors>,
     token: Token,
-) -> Result<Json<Vec<SearchResponse>>, ApiErrors>{
+) -> Result<Json<Page<SearchResponse>>, ApiErrors>{
     // Validate user_id
-    let user_id = ObjectId::parse_str(user_id)
-        .map_err(|_| ApiErrors::BadRequest("Provided Id is not an object id".to_string()))?;
+    let user_id = parse_id(&user_id)?;
+    let record_types = types
+        .0
+        .iter()
+        .map(|record_type| parse_record_type(record_type))
+        .collect::<Result<Vec<RecordTypes>, ApiErrors>>()?;
+    let page = PageRequest::new(limit, DEFAULT_SEARCH_LIMIT, sort.as_deref(), cursor.as_deref())?;
+    let query = parse_search(query.as_deref(), mode.as_deref(), &page)?;
+    let reveal_to = reveal_to(include_secrets, reauth)?;
 
-    // Check to see if the provided user_id is the same as the token.id
-    if user_id != token.id {
-        return Err(ApiErrors::BadRequest("Not Authorized".to_string()));
-    }
+    authorize(db.inner().as_ref(), token.id, Action::Read, Resource::Vault(user_id)).await?;
 
-    let search_params = SearchParamsBuilder::new(user_id)
-        .add_limit(limit)
-        .add_page(page)
-        .add_query(query)
-        .build();
+    let search_params = search_params(user_id, record_types, page, query);
+    let records = component::search_records(db, search_params, reveal_to).await?;
+
+    Ok(Json(records))
+}
+
+#[post("/saved", data = "<new_search>")]
+async fn create_saved_search(
+    db: &State<Box<dyn Storage>>,
+    new_search: Json<NewSavedSearch>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let search_id = saved::create_saved_search(db.inner().as_ref(), token.id, new_search.0).await?;
+
+    Ok(CreatedResponse {
+        id: Json(doc! { "id": search_id.to_string() }),
+    })
+}
+
+#[get("/saved")]
+async fn get_saved_searches(
+    db: &State<Box<dyn Storage>>,
+    token: Token,
+) -> Result<Json<Vec<ResponseSavedSearch>>, ApiErrors> {
+    let searches = saved::get_saved_searches(db.inner().as_ref(), token.id).await?;
+    Ok(Json(searches))
+}
+
+#[get("/saved/<id>")]
+async fn get_saved_search(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Json<ResponseSavedSearch>, ApiErrors> {
+    let search = saved::get_saved_search(db.inner().as_ref(), token.id, parse_id(&id)?).await?;
+    Ok(Json(search))
+}
+
+#[put("/saved/<id>", data = "<new_search>")]
+async fn update_saved_search(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    new_search: Json<NewSavedSearch>,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    saved::update_saved_search(db.inner().as_ref(), token.id, parse_id(&id)?, new_search.0).await?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/saved/<id>")]
+async fn delete_saved_search(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    saved::delete_saved_search(db.inner().as_ref(), token.id, parse_id(&id)?).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/saved/<id>/results?<limit>&<cursor>&<include_secrets>")]
+async fn get_saved_search_results(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    include_secrets: Option<bool>,
+    reauth: Result<Reauthenticated, ApiErrors>,
+    token: Token,
+) -> Result<Json<Page<SearchResponse>>, ApiErrors> {
+    let saved = db.get_saved_search(parse_id(&id)?, token.id).await?;
+    let page = PageRequest::new(limit, DEFAULT_SEARCH_LIMIT, saved.sort.as_deref(), cursor.as_deref())?;
+    let query = parse_search(saved.query.as_deref(), saved.mode.as_deref(), &page)?;
+    let reveal_to = reveal_to(include_secrets, reauth)?;
 
-    let records = component::search_records(db, search_params).await?;
+    // Saved searches run against the vault of their user
+    let search_params = search_params(token.id, saved.types, page, query);
+    let records = component::search_records(db, search_params, reveal_to).await?;
 
     Ok(Json(records))
 }
 
 pub fn api() -> Vec<rocket::Route> {
-    rocket::routes![search_records]
+    rocket::routes![
+        search_records,
+        create_saved_search,
+        get_saved_searches,
+        get_saved_search,
+        update_saved_search,
+        delete_saved_search,
+        get_saved_search_results
+    ]
 }
@@ -0,0 +1,605 @@
+/*
+    The search query language, parsed into an `Expr` that each storage compiles to its own filter.
+
+    query    := or
+    or       := and ("OR" and)*
+    and      := not ("AND"? not)*         terms next to each other have to match both
+    not      := "NOT" not | "-" term | primary
+    primary  := "(" or ")" | term
+    term     := text | field ":" text | date_field ":" range
+    text     := word | "quoted phrase"
+    range    := date | date ".." date | ".." date | date ".." | (">" | ">=" | "<" | "<=") date
+    date     := YYYY-MM-DD | RFC 3339 date time
+
+    Keywords are upper case, `and` and `or` in lower case are searched for like any other word.
+    Text is matched case insensitively, free text against every searchable field. Text is looked
+    for as it is, in the regex search mode it is a pattern instead. In the fuzzy search mode text
+    matches values that are close to it and records are ranked by how close, text after a NOT is
+    still looked for as it is
+*/
+use bson::DateTime;
+use regex::RegexBuilder;
+
+use crate::shared::types::ApiErrors;
+
+pub const MAX_QUERY_LENGTH: usize = 1000;
+pub const MAX_DEPTH: usize = 20;
+pub const MAX_PATTERN_LENGTH: usize = 100;
+pub const MAX_PATTERN_QUANTIFIERS: usize = 10;
+const MAX_PATTERN_SIZE: usize = 64 * 1024;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/**
+ * A record field that can be searched on its own
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchField {
+    Service,
+    Username,
+    Email,
+    Tag,
+    Uri,
+    Key,
+}
+
+impl SearchField {
+    pub const ALL: [SearchField; 6] = [
+        SearchField::Service,
+        SearchField::Username,
+        SearchField::Email,
+        SearchField::Tag,
+        SearchField::Uri,
+        SearchField::Key,
+    ];
+
+    pub fn parse(name: &str) -> Option<SearchField> {
+        match name.to_ascii_lowercase().as_str() {
+            "service" => Some(SearchField::Service),
+            "user" | "username" => Some(SearchField::Username),
+            "email" => Some(SearchField::Email),
+            "tag" => Some(SearchField::Tag),
+            "uri" | "url" => Some(SearchField::Uri),
+            "key" => Some(SearchField::Key),
+            _ => None,
+        }
+    }
+
+    /**
+     * Path of the field in a stored record
+     */
+    pub fn path(&self) -> &'static str {
+        match self {
+            SearchField::Service => "service",
+            SearchField::Username => "username",
+            SearchField::Email => "email",
+            SearchField::Tag => "tags",
+            SearchField::Uri => "uris.uri",
+            SearchField::Key => "key",
+        }
+    }
+}
+
+/**
+ * A timestamp of a record that can be searched by range
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    Updated,
+    PasswordChanged,
+}
+
+impl DateField {
+    pub fn parse(name: &str) -> Option<DateField> {
+        match name.to_ascii_lowercase().as_str() {
+            "created" => Some(DateField::Created),
+            "updated" => Some(DateField::Updated),
+            "changed" | "password_changed" => Some(DateField::PasswordChanged),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self) -> &'static str {
+        match self {
+            DateField::Created => "created_at",
+            DateField::Updated => "updated_at",
+            DateField::PasswordChanged => "password_changed_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bound {
+    pub at: DateTime,
+    pub inclusive: bool,
+}
+
+/**
+ * Dates between `start` and `end`, a missing bound leaves that side open
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: Option<Bound>,
+    pub end: Option<Bound>,
+}
+
+/**
+ * How the text of a query is matched
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchMode {
+    /// Text is looked for as it is
+    #[default]
+    Text,
+    /// Text is a regex pattern
+    Regex,
+    /// Text matches values close to it, see relevance.rs
+    Fuzzy,
+}
+
+impl SearchMode {
+    pub fn parse(mode: &str) -> Result<SearchMode, ApiErrors> {
+        if mode.eq_ignore_ascii_case("text") {
+            Ok(SearchMode::Text)
+        } else if mode.eq_ignore_ascii_case("regex") {
+            Ok(SearchMode::Regex)
+        } else if mode.eq_ignore_ascii_case("fuzzy") {
+            Ok(SearchMode::Fuzzy)
+        } else {
+            Err(ApiErrors::BadRequest("mode must be text, regex or fuzzy".to_string()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    /// Text in one field, or in every searchable field when none is given
+    Text { field: Option<SearchField>, value: String },
+    /// A regex pattern, in one field or in every searchable field
+    Pattern { field: Option<SearchField>, pattern: String },
+    /// Text close to a value of one field, or of any searchable field
+    Fuzzy { field: Option<SearchField>, value: String },
+    Date { field: DateField, range: DateRange },
+}
+
+impl Expr {
+    /**
+     * The fields a text term or a pattern is matched against
+     */
+    pub fn text_fields(field: Option<SearchField>) -> Vec<SearchField> {
+        match field {
+            Some(field) => vec![field],
+            None => SearchField::ALL.to_vec(),
+        }
+    }
+
+    /**
+     * Whether records are ranked by the query
+     */
+    pub fn has_fuzzy(&self) -> bool {
+        match self {
+            Expr::And(operands) | Expr::Or(operands) => operands.iter().any(Expr::has_fuzzy),
+            Expr::Not(operand) => operand.has_fuzzy(),
+            Expr::Fuzzy { .. } => true,
+            Expr::Text { .. } | Expr::Pattern { .. } | Expr::Date { .. } => false,
+        }
+    }
+
+    /**
+     * The same query with fuzzy text looked for as it is, for the terms of a NOT. Excluding
+     * every record close to a word would leave little to rank
+     */
+    fn without_fuzzy(self) -> Expr {
+        match self {
+            Expr::And(operands) => Expr::And(operands.into_iter().map(Expr::without_fuzzy).collect()),
+            Expr::Or(operands) => Expr::Or(operands.into_iter().map(Expr::without_fuzzy).collect()),
+            Expr::Not(operand) => Expr::Not(Box::new(operand.without_fuzzy())),
+            Expr::Fuzzy { field, value } => Expr::Text { field, value },
+            expr => expr,
+        }
+    }
+}
+
+/**
+ * A syntax error, `position` is the 1 based character it was found at
+ */
+fn syntax_error(position: usize, message: &str) -> ApiErrors {
+    ApiErrors::BadRequest(format!("Invalid query at position {}: {}", position, message))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    Word(String),
+    Phrase(String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// 1 based position of the first character
+    start: usize,
+    /// Position right after the last character, to tell which tokens touch
+    end: usize,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, ApiErrors> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i + 1;
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token { kind: TokenKind::LParen, start, end: start + 1 });
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token { kind: TokenKind::RParen, start, end: start + 1 });
+                i += 1;
+            }
+            '"' => {
+                let close = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .ok_or_else(|| syntax_error(start, "quote is never closed"))?;
+                let phrase: String = chars[i + 1..i + 1 + close].iter().collect();
+                i += close + 2;
+                tokens.push(Token { kind: TokenKind::Phrase(phrase), start, end: i + 1 });
+            }
+            _ => {
+                let length = chars[i..]
+                    .iter()
+                    .position(|c| c.is_whitespace() || matches!(c, '(' | ')' | '"'))
+                    .unwrap_or(chars.len() - i);
+                let word: String = chars[i..i + length].iter().collect();
+                i += length;
+                tokens.push(Token { kind: TokenKind::Word(word), start, end: i + 1 });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/**
+ * A date, and whether it is a whole day rather than an instant
+ */
+fn parse_date(value: &str, position: usize) -> Result<(DateTime, bool), ApiErrors> {
+    if let Ok(at) = DateTime::parse_rfc3339_str(value) {
+        return Ok((at, false));
+    }
+    DateTime::parse_rfc3339_str(format!("{}T00:00:00Z", value))
+        .map(|at| (at, true))
+        .map_err(|_| syntax_error(position, &format!("{} is not a date, use YYYY-MM-DD", value)))
+}
+
+fn next_day(at: DateTime) -> DateTime {
+    DateTime::from_millis(at.timestamp_millis() + MILLIS_PER_DAY)
+}
+
+/**
+ * Start of a range, a day starts at midnight
+ */
+fn start_bound(value: &str, position: usize) -> Result<Bound, ApiErrors> {
+    let (at, _) = parse_date(value, position)?;
+    Ok(Bound { at, inclusive: true })
+}
+
+/**
+ * End of a range, a day ends with it
+ */
+fn end_bound(value: &str, position: usize) -> Result<Bound, ApiErrors> {
+    let (at, whole_day) = parse_date(value, position)?;
+    Ok(match whole_day {
+        true => Bound { at: next_day(at), inclusive: false },
+        false => Bound { at, inclusive: true },
+    })
+}
+
+fn parse_range(value: &str, position: usize) -> Result<DateRange, ApiErrors> {
+    if let Some(date) = value.strip_prefix(">=") {
+        return Ok(DateRange { start: Some(start_bound(date, position + 2)?), end: None });
+    }
+    if let Some(date) = value.strip_prefix("<=") {
+        return Ok(DateRange { start: None, end: Some(end_bound(date, position + 2)?) });
+    }
+    // After a day is from the next one on, before a day is up to its start
+    if let Some(date) = value.strip_prefix('>') {
+        let end = end_bound(date, position + 1)?;
+        return Ok(DateRange { start: Some(Bound { at: end.at, inclusive: !end.inclusive }), end: None });
+    }
+    if let Some(date) = value.strip_prefix('<') {
+        let start = start_bound(date, position + 1)?;
+        return Ok(DateRange { start: None, end: Some(Bound { at: start.at, inclusive: false }) });
+    }
+
+    match value.split_once("..") {
+        Some(("", "")) => Err(syntax_error(position, "a range needs at least one date")),
+        Some((start, end)) => Ok(DateRange {
+            start: match start {
+                "" => None,
+                start => Some(start_bound(start, position)?),
+            },
+            end: match end {
+                "" => None,
+                end => Some(end_bound(end, position + start.chars().count() + 2)?),
+            },
+        }),
+        None => Ok(DateRange {
+            start: Some(start_bound(value, position)?),
+            end: Some(end_bound(value, position)?),
+        }),
+    }
+}
+
+/**
+ * Patterns run on the database, so the ones that can backtrack for long are turned down before
+ * they get there. The regex crate has no backreferences or lookarounds, a pattern it compiles
+ * is safe apart from nested quantifiers such as `(a+)+`, which are looked for here
+ */
+fn validate_pattern(pattern: &str, position: usize) -> Result<(), ApiErrors> {
+    if pattern.chars().count() > MAX_PATTERN_LENGTH {
+        return Err(syntax_error(
+            position,
+            &format!("a pattern must be at most {} characters", MAX_PATTERN_LENGTH),
+        ));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(MAX_PATTERN_SIZE)
+        .build()
+        .map_err(|_| syntax_error(position, &format!("{} is not a valid regex", pattern)))?;
+
+    // Whether each open group has a quantifier inside
+    let mut groups: Vec<bool> = Vec::new();
+    let mut quantifiers = 0;
+    let mut closed_quantified_group = false;
+    let mut after_quantifier = false;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let quantifies_group = std::mem::take(&mut closed_quantified_group);
+        let lazy = std::mem::take(&mut after_quantifier) && c == '?';
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => {
+                // Quantifiers in a class are plain characters
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        ']' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '(' => {
+                // Flags and group names, ie (?i) or (?P<name>
+                if chars.peek() == Some(&'?') {
+                    while chars.peek().is_some_and(|c| !matches!(c, ':' | ')' | '>')) {
+                        chars.next();
+                    }
+                    // A flag group such as (?i) only sets flags
+                    if chars.next() == Some(')') {
+                        continue;
+                    }
+                }
+                groups.push(false);
+            }
+            ')' => {
+                closed_quantified_group = groups.pop().unwrap_or(false);
+                if closed_quantified_group {
+                    if let Some(outer) = groups.last_mut() {
+                        *outer = true;
+                    }
+                }
+            }
+            '?' if lazy => {}
+            '*' | '+' | '?' | '{' => {
+                if c == '{' {
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                    }
+                }
+                if quantifies_group {
+                    return Err(syntax_error(position, "quantifiers cannot be nested in a pattern"));
+                }
+                quantifiers += 1;
+                if quantifiers > MAX_PATTERN_QUANTIFIERS {
+                    return Err(syntax_error(
+                        position,
+                        &format!("a pattern can have at most {} quantifiers", MAX_PATTERN_QUANTIFIERS),
+                    ));
+                }
+                if let Some(group) = groups.last_mut() {
+                    *group = true;
+                }
+                after_quantifier = true;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+struct Parser {
+    mode: SearchMode,
+    tokens: Vec<Token>,
+    next: usize,
+    /// Position right after the query, for errors at its end
+    end: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Word(word), .. }) if word == keyword)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    /**
+     * Text found at `position`, a pattern in the regex mode
+     */
+    fn text(&self, field: Option<SearchField>, value: String, position: usize) -> Result<Expr, ApiErrors> {
+        match self.mode {
+            SearchMode::Text => Ok(Expr::Text { field, value }),
+            SearchMode::Regex => {
+                validate_pattern(&value, position)?;
+                Ok(Expr::Pattern { field, pattern: value })
+            }
+            SearchMode::Fuzzy => Ok(Expr::Fuzzy { field, value }),
+        }
+    }
+
+    fn position(&self) -> usize {
+        self.peek().map(|token| token.start).unwrap_or(self.end)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ApiErrors> {
+        let mut operands = vec![self.parse_and()?];
+        while self.peek_keyword("OR") {
+            self.advance();
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { Expr::Or(operands) })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ApiErrors> {
+        let mut operands = vec![self.parse_not()?];
+        loop {
+            if self.peek_keyword("AND") {
+                self.advance();
+            } else if self.peek().is_none()
+                || self.peek_keyword("OR")
+                || matches!(self.peek(), Some(Token { kind: TokenKind::RParen, .. }))
+            {
+                break;
+            }
+            operands.push(self.parse_not()?);
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { Expr::And(operands) })
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, ApiErrors> {
+        if self.peek_keyword("NOT") {
+            self.advance();
+            return Ok(Expr::Not(Box::new(self.parse_not()?.without_fuzzy())));
+        }
+        if let Some(Token { kind: TokenKind::Word(word), start, end }) = self.peek().cloned() {
+            if let Some(negated) = word.strip_prefix('-').filter(|negated| !negated.is_empty()) {
+                self.advance();
+                let term = self.parse_term(negated.to_string(), start + 1, end)?;
+                return Ok(Expr::Not(Box::new(term.without_fuzzy())));
+            }
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ApiErrors> {
+        let position = self.position();
+        match self.advance() {
+            Some(Token { kind: TokenKind::LParen, start, .. }) => {
+                self.depth += 1;
+                if self.depth > MAX_DEPTH {
+                    return Err(syntax_error(start, &format!("parentheses are nested more than {} deep", MAX_DEPTH)));
+                }
+                let expr = self.parse_or()?;
+                match self.advance() {
+                    Some(Token { kind: TokenKind::RParen, .. }) => {
+                        self.depth -= 1;
+                        Ok(expr)
+                    }
+                    _ => Err(syntax_error(start, "parenthesis is never closed")),
+                }
+            }
+            Some(Token { kind: TokenKind::RParen, start, .. }) => {
+                Err(syntax_error(start, "parenthesis was never opened"))
+            }
+            Some(Token { kind: TokenKind::Phrase(phrase), start, .. }) => self.text(None, phrase, start + 1),
+            Some(Token { kind: TokenKind::Word(word), start, end }) => {
+                if matches!(word.as_str(), "AND" | "OR" | "NOT") {
+                    return Err(syntax_error(start, &format!("expected a search term before {}", word)));
+                }
+                self.parse_term(word, start, end)
+            }
+            None => Err(syntax_error(position, "expected a search term")),
+        }
+    }
+
+    /**
+     * A word, which may be qualified by a field. A qualified phrase is a word ending in `:`
+     * right before the phrase, ie `tag:"my bank"`
+     */
+    fn parse_term(&mut self, word: String, start: usize, end: usize) -> Result<Expr, ApiErrors> {
+        let (name, value) = match word.split_once(':') {
+            Some(parts) => parts,
+            None => return self.text(None, word, start),
+        };
+        let value_position = start + name.chars().count() + 1;
+
+        if let Some(field) = DateField::parse(name) {
+            if value.is_empty() {
+                return Err(syntax_error(value_position, &format!("expected a date after {}:", name)));
+            }
+            return Ok(Expr::Date { field, range: parse_range(value, value_position)? });
+        }
+        match SearchField::parse(name) {
+            Some(field) if value.is_empty() => match self.peek().cloned() {
+                Some(Token { kind: TokenKind::Phrase(phrase), start: phrase_start, .. }) if phrase_start == end => {
+                    self.advance();
+                    self.text(Some(field), phrase, phrase_start + 1)
+                }
+                _ => Err(syntax_error(value_position, &format!("expected a value after {}:", name))),
+            },
+            Some(field) => self.text(Some(field), value.to_string(), value_position),
+            // URLs are searched as they are
+            None if value.starts_with("//") => self.text(None, word, start),
+            None => Err(syntax_error(
+                start,
+                &format!("unknown field {}, put the term in quotes to search for it as is", name),
+            )),
+        }
+    }
+}
+
+/**
+ * Parse a query, None when it has no terms
+ */
+pub fn parse_query(query: &str, mode: SearchMode) -> Result<Option<Expr>, ApiErrors> {
+    let length = query.chars().count();
+    if length > MAX_QUERY_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Query must be at most {} characters",
+            MAX_QUERY_LENGTH
+        )));
+    }
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parser = Parser { mode, tokens, next: 0, end: length + 1, depth: 0 };
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(Some(expr)),
+        Some(token) => Err(syntax_error(token.start, "parenthesis was never opened")),
+    }
+}
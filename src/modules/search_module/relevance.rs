@@ -0,0 +1,143 @@
+/*
+    Ranking of records for the fuzzy search mode. A fuzzy term scores the closest word of its
+    fields by edit distance, 1 when a value contains the term. A record matches a query when every
+    fuzzy term it has to match scores at least MIN_SIMILARITY, terms of an AND score as the worst
+    of them and terms of an OR as the best. Other terms score 1 when they match
+*/
+use regex::RegexBuilder;
+
+use crate::shared::types::Record;
+
+use super::query::{DateField, DateRange, Expr, SearchField};
+
+/// Lowest score of a fuzzy term that still matches, "gthub" is 0.83 close to "github"
+pub const MIN_SIMILARITY: f64 = 0.6;
+/// Records read at once to be ranked, up to `SEARCH_MAX_CANDIDATES` in all
+pub const FUZZY_CANDIDATE_BATCH: i64 = 1000;
+
+/**
+ * Values of a field of a record
+ */
+fn field_values(record: &Record, field: SearchField) -> Vec<&str> {
+    match field {
+        SearchField::Service => record.service.as_deref().into_iter().collect(),
+        SearchField::Username => record.username.as_deref().into_iter().collect(),
+        SearchField::Email => record.email.as_deref().into_iter().collect(),
+        SearchField::Key => record.key.as_deref().into_iter().collect(),
+        SearchField::Tag => record.tags.iter().flatten().map(String::as_str).collect(),
+        SearchField::Uri => record.uris.iter().flatten().map(|uri| uri.uri.as_str()).collect(),
+    }
+}
+
+fn date_value(record: &Record, field: DateField) -> Option<bson::DateTime> {
+    match field {
+        DateField::Created => record.created_at,
+        DateField::Updated => record.updated_at,
+        DateField::PasswordChanged => record.password_changed_at,
+    }
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/**
+ * How close a term is to a value between 0 and 1, against the whole value and each of its words
+ */
+pub fn similarity(term: &str, value: &str) -> f64 {
+    let term = term.to_lowercase();
+    let value = value.to_lowercase();
+    if term.is_empty() {
+        return 0.0;
+    }
+    if value.contains(&term) {
+        return 1.0;
+    }
+    let term: Vec<char> = term.chars().collect();
+    std::iter::once(value.as_str())
+        .chain(value.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let word: Vec<char> = word.chars().collect();
+            1.0 - levenshtein(&term, &word) as f64 / term.len().max(word.len()) as f64
+        })
+        .fold(0.0, f64::max)
+}
+
+fn in_range(at: bson::DateTime, range: &DateRange) -> bool {
+    let after_start = range.start.is_none_or(|start| match start.inclusive {
+        true => at >= start.at,
+        false => at > start.at,
+    });
+    let before_end = range.end.is_none_or(|end| match end.inclusive {
+        true => at <= end.at,
+        false => at < end.at,
+    });
+    after_start && before_end
+}
+
+/**
+ * Score of a record for a query, None when it does not match
+ */
+pub fn relevance(expr: &Expr, record: &Record) -> Option<f64> {
+    let matched = |matches: bool| if matches { Some(1.0) } else { None };
+    match expr {
+        Expr::And(operands) => operands
+            .iter()
+            .map(|operand| relevance(operand, record))
+            .try_fold(1.0, |score, operand| operand.map(|operand| f64::min(score, operand))),
+        Expr::Or(operands) => operands
+            .iter()
+            .filter_map(|operand| relevance(operand, record))
+            .reduce(f64::max),
+        Expr::Not(operand) => matched(relevance(operand, record).is_none()),
+        Expr::Fuzzy { field, value } => {
+            let score = Expr::text_fields(*field)
+                .into_iter()
+                .flat_map(|field| field_values(record, field))
+                .map(|field_value| similarity(value, field_value))
+                .fold(0.0, f64::max);
+            if score >= MIN_SIMILARITY { Some(score) } else { None }
+        }
+        Expr::Text { field, value } => {
+            let value = value.to_lowercase();
+            matched(
+                Expr::text_fields(*field)
+                    .into_iter()
+                    .flat_map(|field| field_values(record, field))
+                    .any(|field_value| field_value.to_lowercase().contains(&value)),
+            )
+        }
+        Expr::Pattern { field, pattern } => {
+            let regex = RegexBuilder::new(pattern).case_insensitive(true).build().ok()?;
+            matched(
+                Expr::text_fields(*field)
+                    .into_iter()
+                    .flat_map(|field| field_values(record, field))
+                    .any(|field_value| regex.is_match(field_value)),
+            )
+        }
+        Expr::Date { field, range } => matched(date_value(record, *field).is_some_and(|at| in_range(at, range))),
+    }
+}
+
+/**
+ * Records that match a query with their score, the closest first
+ */
+pub fn rank(expr: &Expr, records: Vec<Record>) -> Vec<(Record, f64)> {
+    let mut ranked: Vec<(Record, f64)> = records
+        .into_iter()
+        .filter_map(|record| relevance(expr, &record).map(|score| (record, score)))
+        .collect();
+    ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    ranked
+}
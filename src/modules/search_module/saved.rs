@@ -0,0 +1,129 @@
+/*
+    Saved searches of a user. The query, mode, types and sort are checked when a search is saved
+    and kept as they were written, a saved search is parsed again each time it runs
+*/
+use bson::{oid::ObjectId, DateTime};
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    shared::{
+        pagination::PageRequest,
+        types::{ApiErrors, NewSavedSearch, RecordTypes, ResponseSavedSearch, SavedSearch},
+    },
+};
+
+use super::{parse_record_type, parse_search, DEFAULT_SEARCH_LIMIT};
+
+pub const MAX_SAVED_SEARCHES: usize = 100;
+pub const MAX_NAME_LENGTH: usize = 100;
+
+fn to_rfc3339(date: DateTime) -> Result<String, ApiErrors> {
+    date.try_to_rfc3339_string()
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))
+}
+
+fn to_response(search: SavedSearch) -> Result<ResponseSavedSearch, ApiErrors> {
+    Ok(ResponseSavedSearch {
+        id: search
+            .id
+            .ok_or_else(|| ApiErrors::ServerError("Object id was not found".to_string()))?
+            .to_string(),
+        name: search.name,
+        query: search.query,
+        mode: search.mode,
+        types: search.types,
+        sort: search.sort,
+        created_at: to_rfc3339(search.created_at)?,
+        updated_at: to_rfc3339(search.updated_at)?,
+    })
+}
+
+/**
+ * Check a search can run before it is saved, returns its trimmed name and record types
+ */
+fn validate(new_search: &NewSavedSearch) -> Result<(String, Vec<RecordTypes>), ApiErrors> {
+    let name = new_search.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Name must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    let types = new_search
+        .types
+        .iter()
+        .flatten()
+        .map(|record_type| parse_record_type(record_type))
+        .collect::<Result<Vec<RecordTypes>, ApiErrors>>()?;
+    let page = PageRequest::new(None, DEFAULT_SEARCH_LIMIT, new_search.sort.as_deref(), None)?;
+    parse_search(new_search.query.as_deref(), new_search.mode.as_deref(), &page)?;
+    Ok((name.to_string(), types))
+}
+
+pub async fn create_saved_search(
+    db: &dyn Storage,
+    user_id: ObjectId,
+    new_search: NewSavedSearch,
+) -> Result<ObjectId, ApiErrors> {
+    let (name, types) = validate(&new_search)?;
+    if db.get_saved_searches(user_id).await?.len() >= MAX_SAVED_SEARCHES {
+        return Err(ApiErrors::BadRequest(format!(
+            "A user can save at most {} searches",
+            MAX_SAVED_SEARCHES
+        )));
+    }
+
+    let now = DateTime::now();
+    db.insert_saved_search(SavedSearch {
+        id: None,
+        user_id,
+        name,
+        query: new_search.query,
+        mode: new_search.mode,
+        types,
+        sort: new_search.sort,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+}
+
+pub async fn get_saved_searches(db: &dyn Storage, user_id: ObjectId) -> Result<Vec<ResponseSavedSearch>, ApiErrors> {
+    db.get_saved_searches(user_id).await?.into_iter().map(to_response).collect()
+}
+
+pub async fn get_saved_search(
+    db: &dyn Storage,
+    user_id: ObjectId,
+    search_id: ObjectId,
+) -> Result<ResponseSavedSearch, ApiErrors> {
+    to_response(db.get_saved_search(search_id, user_id).await?)
+}
+
+/**
+ * Replace a saved search, it keeps its id and creation date
+ */
+pub async fn update_saved_search(
+    db: &dyn Storage,
+    user_id: ObjectId,
+    search_id: ObjectId,
+    new_search: NewSavedSearch,
+) -> Result<(), ApiErrors> {
+    let (name, types) = validate(&new_search)?;
+    let saved = db.get_saved_search(search_id, user_id).await?;
+
+    db.replace_saved_search(SavedSearch {
+        name,
+        query: new_search.query,
+        mode: new_search.mode,
+        types,
+        sort: new_search.sort,
+        updated_at: DateTime::now(),
+        ..saved
+    })
+    .await
+}
+
+pub async fn delete_saved_search(db: &dyn Storage, user_id: ObjectId, search_id: ObjectId) -> Result<(), ApiErrors> {
+    db.delete_saved_search(search_id, user_id).await
+}
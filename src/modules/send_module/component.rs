@@ -0,0 +1,147 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use bson::{oid::ObjectId, DateTime};
+use pwhash::bcrypt;
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    shared::{
+        encryption::{decrypt_password, encrypt_data},
+        types::{ApiErrors, NewSecretSend, ResponseSecretSend, SecretSend},
+    },
+};
+
+pub const MAX_TEXT_LENGTH: usize = 10_000;
+pub const MAX_FILE_BYTES: usize = 512 * 1024;
+pub const MAX_VIEWS: i64 = 100;
+pub const MAX_EXPIRY_HOURS: i64 = 30 * 24;
+
+const DEFAULT_VIEWS: i64 = 1;
+const DEFAULT_EXPIRY_HOURS: i64 = 24;
+
+/**
+ * 256 random bits, url safe so the id can be used as the link as is
+ */
+fn new_send_id() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn not_found() -> ApiErrors {
+    ApiErrors::NotFound("Send not found".to_string())
+}
+
+/**
+ * Store a text or a small file to be opened by link, returns the id of the link
+ */
+pub async fn create_send(
+    db: &dyn Storage,
+    user_id: ObjectId,
+    new_send: NewSecretSend,
+) -> Result<String, ApiErrors> {
+    let (text, file_name, file_content) = match (new_send.text, new_send.file_name, new_send.file_content) {
+        (Some(text), None, None) => {
+            if text.is_empty() || text.chars().count() > MAX_TEXT_LENGTH {
+                return Err(ApiErrors::BadRequest(format!(
+                    "Text must be between 1 and {} characters",
+                    MAX_TEXT_LENGTH
+                )));
+            }
+            (Some(encrypt_data(&text)), None, None)
+        }
+        (None, Some(file_name), Some(file_content)) => {
+            if file_name.trim().is_empty() {
+                return Err(ApiErrors::BadRequest("File name is required".to_string()));
+            }
+            let bytes = STANDARD
+                .decode(&file_content)
+                .map_err(|_| ApiErrors::BadRequest("File content is not valid base64".to_string()))?;
+            if bytes.is_empty() || bytes.len() > MAX_FILE_BYTES {
+                return Err(ApiErrors::BadRequest(format!(
+                    "File must be between 1 and {} bytes",
+                    MAX_FILE_BYTES
+                )));
+            }
+            (None, Some(file_name), Some(encrypt_data(&file_content)))
+        }
+        _ => {
+            return Err(ApiErrors::BadRequest(
+                "Either a text or a file name and content is required".to_string(),
+            ))
+        }
+    };
+
+    let max_views = new_send.max_views.unwrap_or(DEFAULT_VIEWS);
+    if !(1..=MAX_VIEWS).contains(&max_views) {
+        return Err(ApiErrors::BadRequest(format!("Max views must be between 1 and {}", MAX_VIEWS)));
+    }
+    let expires_in_hours = new_send.expires_in_hours.unwrap_or(DEFAULT_EXPIRY_HOURS);
+    if !(1..=MAX_EXPIRY_HOURS).contains(&expires_in_hours) {
+        return Err(ApiErrors::BadRequest(format!(
+            "Expiry must be between 1 and {} hours",
+            MAX_EXPIRY_HOURS
+        )));
+    }
+    let password = match new_send.password {
+        Some(password) if password.is_empty() => {
+            return Err(ApiErrors::BadRequest("Password cannot be empty".to_string()))
+        }
+        Some(password) => Some(bcrypt::hash(password).map_err(|err| ApiErrors::ServerError(err.to_string()))?),
+        None => None,
+    };
+
+    let now = DateTime::now();
+    let send_id = new_send_id();
+    db.insert_send(SecretSend {
+        id: send_id.clone(),
+        user_id,
+        text,
+        file_name,
+        file_content,
+        password,
+        max_views,
+        views_left: max_views,
+        expires_at: DateTime::from_millis(now.timestamp_millis() + expires_in_hours * 60 * 60 * 1000),
+        created_at: now,
+    })
+    .await?;
+    Ok(send_id)
+}
+
+/**
+ * Open a send by link and use up one of its views. A wrong password does not use up a view,
+ * sends that expired or are used up are reported as not found
+ */
+pub async fn open_send(
+    db: &dyn Storage,
+    send_id: &str,
+    password: Option<String>,
+) -> Result<ResponseSecretSend, ApiErrors> {
+    let now = DateTime::now();
+    let send = db.get_send(send_id).await?;
+    if send.expires_at <= now || send.views_left <= 0 {
+        return Err(not_found());
+    }
+    if let Some(hash) = &send.password {
+        let password = password.ok_or_else(|| ApiErrors::Unauthorized("Password is required".to_string()))?;
+        if !bcrypt::verify(password, hash) {
+            return Err(ApiErrors::Unauthorized("Password is incorrect".to_string()));
+        }
+    }
+
+    let send = db.consume_send(send_id, now).await?.ok_or_else(not_found)?;
+    Ok(ResponseSecretSend {
+        text: send.text.as_ref().map(decrypt_password).transpose()?,
+        file_name: send.file_name,
+        file_content: send.file_content.as_ref().map(decrypt_password).transpose()?,
+        views_left: send.views_left - 1,
+        expires_at: send
+            .expires_at
+            .try_to_rfc3339_string()
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+    })
+}
@@ -0,0 +1,71 @@
+pub mod component;
+pub mod purge;
+
+use mongodb::bson::doc;
+use rocket::{
+    http::Header,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    Request, State,
+};
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    shared::{
+        jwt_service::Token,
+        types::{ApiErrors, CreatedResponse, NewSecretSend, OpenedSendResponse},
+    },
+};
+
+/*
+    Routes in this file:
+    POST /send -> Store a secret to share by link
+    GET /send/:id -> Open a send, no token needed, uses up one of its views
+*/
+
+/**
+ * The password of a send, given in the `X-Send-Password` header
+ */
+pub struct SendPassword(pub Option<String>);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for SendPassword {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(SendPassword(
+            request.headers().get_one("X-Send-Password").map(|password| password.to_string()),
+        ))
+    }
+}
+
+#[post("/", data = "<new_send>")]
+pub async fn create_send(
+    db: &State<Box<dyn Storage>>,
+    new_send: Json<NewSecretSend>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let send_id = component::create_send(db.inner().as_ref(), token.id, new_send.0).await?;
+
+    Ok(CreatedResponse {
+        id: Json(doc! { "id": send_id }),
+    })
+}
+
+#[get("/<id>")]
+pub async fn open_send(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    password: SendPassword,
+) -> Result<OpenedSendResponse, ApiErrors> {
+    let send = component::open_send(db.inner().as_ref(), &id, password.0).await?;
+
+    Ok(OpenedSendResponse {
+        send: Json(send),
+        cache_control: Header::new("Cache-Control", "no-store"),
+    })
+}
+
+pub fn api() -> Vec<rocket::Route> {
+    rocket::routes![create_send, open_send]
+}
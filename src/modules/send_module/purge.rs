@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use bson::DateTime;
+use rocket::tokio::{self, time};
+
+use crate::drivers::storage::storage_trait::Storage;
+
+/**
+ * Delete expired sends now and then every `interval_hours`, an interval of 0 turns the job off.
+ * Expired sends cannot be opened either way, this keeps their payloads from lingering
+ */
+pub fn spawn_send_purge(db: Box<dyn Storage>, interval_hours: u64) {
+    if interval_hours == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(interval_hours * 60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(err) = db.delete_expired_sends(DateTime::now()).await {
+                println!("Purging expired sends failed: {:?}", err);
+            }
+        }
+    });
+}
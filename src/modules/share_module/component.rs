@@ -0,0 +1,108 @@
+use bson::{oid::ObjectId, DateTime};
+use rocket::State;
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    modules::record_module::component::{reveal_or_mask, to_response_record},
+    shared::{
+        policy::{authorize, Action, Resource},
+        types::{ApiErrors, NewShare, RecordShare, ResponseShare, SharedRecord},
+    },
+};
+
+/**
+ * Share a record of the owner with another user, returns the id of the share
+ */
+pub async fn share_record(
+    db: &State<Box<dyn Storage>>,
+    record_id: ObjectId,
+    owner_id: ObjectId,
+    new_share: NewShare,
+) -> Result<ObjectId, ApiErrors> {
+    let username = new_share.username.trim();
+    if username.is_empty() {
+        return Err(ApiErrors::BadRequest("Username is required".to_string()));
+    }
+    authorize(db.inner().as_ref(), owner_id, Action::Share, Resource::Record(record_id)).await?;
+
+    let grantee = db.get_user_by_username(username).await?;
+    let grantee_id = ObjectId::parse_str(&grantee.id)
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+    if grantee_id == owner_id {
+        return Err(ApiErrors::BadRequest("A record cannot be shared with its owner".to_string()));
+    }
+    let owner = db.get_user_by_id(owner_id).await?;
+
+    db.upsert_record_share(RecordShare {
+        id: None,
+        record_id,
+        owner_id,
+        owner_username: owner.username,
+        grantee_id,
+        grantee_username: grantee.username,
+        permission: new_share.permission,
+        created_at: DateTime::now(),
+    })
+    .await
+}
+
+/**
+ * Records other users have shared with the user, along with the share they come from.
+ * Passwords and secrets are masked unless they are revealed to `reveal_to`
+ */
+pub async fn get_shared_with_me(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+    reveal_to: Option<ObjectId>,
+) -> Result<Vec<SharedRecord>, ApiErrors> {
+    let mut shared = Vec::new();
+    for share in db.get_shares_with_user(user_id).await? {
+        let record = match db.get_record(share.record_id, share.owner_id).await {
+            Ok(record) => record,
+            // The record was deleted after being shared
+            Err(ApiErrors::NotFound(_)) => continue,
+            Err(err) => return Err(err),
+        };
+        shared.push(SharedRecord {
+            share: to_response_share(share)?,
+            record: to_response_record(reveal_or_mask(db.inner().as_ref(), record, reveal_to).await?)?,
+        });
+    }
+    Ok(shared)
+}
+
+pub async fn get_shared_by_me(
+    db: &State<Box<dyn Storage>>,
+    user_id: ObjectId,
+) -> Result<Vec<ResponseShare>, ApiErrors> {
+    db.get_shares_by_user(user_id)
+        .await?
+        .into_iter()
+        .map(to_response_share)
+        .collect()
+}
+
+pub async fn revoke_share(
+    db: &State<Box<dyn Storage>>,
+    share_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<(), ApiErrors> {
+    db.delete_record_share(share_id, user_id).await
+}
+
+fn to_response_share(share: RecordShare) -> Result<ResponseShare, ApiErrors> {
+    Ok(ResponseShare {
+        id: share
+            .id
+            .ok_or(ApiErrors::ServerError("Object id was not found for share".to_string()))?
+            .to_string(),
+        record_id: share.record_id.to_string(),
+        owner_username: share.owner_username,
+        grantee_username: share.grantee_username,
+        permission: share.permission,
+        created_at: share
+            .created_at
+            .try_to_rfc3339_string()
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+    })
+}
@@ -0,0 +1,79 @@
+pub mod component;
+
+use bson::oid::ObjectId;
+use mongodb::bson::doc;
+use rocket::{http::Status, serde::json::Json, State};
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    modules::auth_module::reauth::{reveal_to, Reauthenticated},
+    shared::{
+        jwt_service::Token,
+        types::{ApiErrors, CreatedResponse, NewShare, ResponseShare, SharedRecord},
+    },
+};
+
+/*
+    Routes in this file:
+    POST /share/record/:record_id -> Share a record with another user by username
+    GET /share/with-me?include_secrets= -> Get the records shared with the user
+    GET /share/by-me -> Get the shares the user has made
+    DELETE /share/:id -> Revoke a share, or leave one shared with the user
+*/
+
+#[post("/record/<record_id>", data = "<new_share>")]
+pub async fn share_record(
+    db: &State<Box<dyn Storage>>,
+    record_id: String,
+    new_share: Json<NewShare>,
+    token: Token,
+) -> Result<CreatedResponse, ApiErrors> {
+    let record_id = ObjectId::parse_str(record_id)
+        .map_err(|_| ApiErrors::BadRequest("ID is not formatted correctly".to_string()))?;
+
+    let share_id = component::share_record(db, record_id, token.id, new_share.0).await?;
+
+    Ok(CreatedResponse {
+        id: Json(doc! { "id": share_id.to_string() }),
+    })
+}
+
+#[get("/with-me?<include_secrets>")]
+pub async fn get_shared_with_me(
+    db: &State<Box<dyn Storage>>,
+    include_secrets: Option<bool>,
+    reauth: Result<Reauthenticated, ApiErrors>,
+    token: Token,
+) -> Result<Json<Vec<SharedRecord>>, ApiErrors> {
+    // Plaintext passwords and secrets need the account password again
+    let reveal_to = reveal_to(include_secrets, reauth)?;
+    let records = component::get_shared_with_me(db, token.id, reveal_to).await?;
+    Ok(Json(records))
+}
+
+#[get("/by-me")]
+pub async fn get_shared_by_me(
+    db: &State<Box<dyn Storage>>,
+    token: Token,
+) -> Result<Json<Vec<ResponseShare>>, ApiErrors> {
+    let shares = component::get_shared_by_me(db, token.id).await?;
+    Ok(Json(shares))
+}
+
+#[delete("/<id>")]
+pub async fn revoke_share(
+    db: &State<Box<dyn Storage>>,
+    id: String,
+    token: Token,
+) -> Result<Status, ApiErrors> {
+    let share_id = ObjectId::parse_str(id)
+        .map_err(|_| ApiErrors::BadRequest("ID is not formatted correctly".to_string()))?;
+
+    component::revoke_share(db, share_id, token.id).await?;
+
+    Ok(Status::NoContent)
+}
+
+pub fn api() -> Vec<rocket::Route> {
+    rocket::routes![share_record, get_shared_with_me, get_shared_by_me, revoke_share]
+}
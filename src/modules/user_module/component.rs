@@ -2,11 +2,11 @@ use bson::oid::ObjectId;
 use pwhash::bcrypt;
 use rocket::State;
 
-use crate::{drivers::mongodb::mongo_trait::TMongoClient, shared::types::{AuthUser, ApiErrors, UpdateUser}};
+use crate::{drivers::storage::storage_trait::Storage, modules::auth_module::auth_component::verify_password, shared::types::{AuthUser, ApiErrors, UpdateUser}};
 
 
 pub async fn get_user(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     user_id: ObjectId
 ) -> Result<AuthUser, ApiErrors> {
     let user = db.get_user_by_id(user_id).await?;
@@ -15,7 +15,7 @@ pub async fn get_user(
 }
 
 pub async fn update_user(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     user_id: ObjectId,
     mut updated_user: UpdateUser
 ) -> Result<(), ApiErrors> {
@@ -25,9 +25,8 @@ pub async fn update_user(
 
     // Check Passwords
     let user = db.get_user_by_id(user_id).await?;
-    let user_password = db.get_user(&user.email).await?.password;
 
-    if !bcrypt::verify(updated_user.password.clone(), &user_password) {
+    if !verify_password(db.inner().as_ref(), user_id, &updated_user.password).await? {
         // Passwords do not match
         return Err(ApiErrors::BadRequest("Password is incorrect".to_string()))
     }
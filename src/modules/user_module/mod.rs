@@ -1,12 +1,12 @@
 use bson::oid::ObjectId;
 use rocket::{serde::json::Json, State, http::Status};
 
-use crate::{drivers::mongodb::mongo_trait::TMongoClient, shared::{jwt_service::Token, types::{AuthUser, ApiErrors, UpdateUser}}};
+use crate::{drivers::storage::storage_trait::Storage, shared::{jwt_service::Token, types::{AuthUser, ApiErrors, UpdateUser}}};
 
 
 #[get("/<user_id>")]
 pub async fn get_user(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     user_id: String,
     user_token: Token
 ) -> Result<Json<AuthUser>, ApiErrors> {
@@ -24,7 +24,7 @@ pub async fn get_user(
 
 #[patch("/<user_id>", data = "<updated_user>")]
 pub async fn update_user(
-    db: &State<Box<dyn TMongoClient>>,
+    db: &State<Box<dyn Storage>>,
     user_token: Token,
     user_id: String,
     updated_user: Json<UpdateUser>
@@ -0,0 +1,41 @@
+use bson::{oid::ObjectId, DateTime};
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    shared::types::{ApiErrors, AuditAction, AuditEvent, ResponseAuditEvent},
+};
+
+/**
+ * Store an audit event. Callers fail when it cannot be stored, a step that is not recorded is not taken
+ */
+pub async fn record_event(
+    db: &dyn Storage,
+    action: AuditAction,
+    owner_id: ObjectId,
+    actor_id: Option<ObjectId>,
+    target_id: Option<ObjectId>,
+) -> Result<(), ApiErrors> {
+    db.insert_audit_event(AuditEvent {
+        id: None,
+        action,
+        owner_id,
+        actor_id,
+        target_id,
+        created_at: DateTime::now(),
+    })
+    .await?;
+    Ok(())
+}
+
+pub fn to_response_event(event: AuditEvent) -> Result<ResponseAuditEvent, ApiErrors> {
+    Ok(ResponseAuditEvent {
+        action: event.action,
+        owner_id: event.owner_id.to_string(),
+        actor_id: event.actor_id.map(|id| id.to_string()),
+        target_id: event.target_id.map(|id| id.to_string()),
+        created_at: event
+            .created_at
+            .try_to_rfc3339_string()
+            .map_err(|err| ApiErrors::ServerError(err.to_string()))?,
+    })
+}
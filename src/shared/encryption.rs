@@ -1,4 +1,12 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
 
 use super::types::ApiErrors;
 
@@ -17,3 +25,143 @@ pub fn decrypt_password(encrypted: &String) -> Result<String, ApiErrors> {
         )),
     }
 }
+
+/// Argon2id cost used for new exports
+static EXPORT_MEMORY_KIB: u32 = 19 * 1024;
+static EXPORT_ITERATIONS: u32 = 2;
+static EXPORT_PARALLELISM: u32 = 1;
+/// Upper bound on the cost accepted from a file, a crafted file should not exhaust the server
+static MAX_MEMORY_KIB: u32 = 256 * 1024;
+static MAX_ITERATIONS: u32 = 10;
+static MAX_PARALLELISM: u32 = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub algorithm: String,
+    pub nonce: String,
+}
+
+/**
+ * Data encrypted with AES-256-GCM under a key derived from a passphrase with Argon2id
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PassphraseEncrypted {
+    pub kdf: KdfParams,
+    pub cipher: CipherParams,
+    pub data: String,
+}
+
+fn derive_key(passphrase: &str, kdf: &KdfParams, salt: &[u8]) -> Result<[u8; 32], ApiErrors> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|_| ApiErrors::BadRequest("Invalid key derivation parameters".to_string()))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+    Ok(key)
+}
+
+pub fn encrypt_with_passphrase(data: &[u8], passphrase: &str) -> Result<PassphraseEncrypted, ApiErrors> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let kdf = KdfParams {
+        algorithm: "argon2id".to_string(),
+        memory_kib: EXPORT_MEMORY_KIB,
+        iterations: EXPORT_ITERATIONS,
+        parallelism: EXPORT_PARALLELISM,
+        salt: STANDARD.encode(salt),
+    };
+    let key = derive_key(passphrase, &kdf, &salt)?;
+    let encrypted = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .map_err(|_| ApiErrors::ServerError("There was an issue encrypting".to_string()))?;
+
+    Ok(PassphraseEncrypted {
+        kdf,
+        cipher: CipherParams {
+            algorithm: "aes-256-gcm".to_string(),
+            nonce: STANDARD.encode(nonce),
+        },
+        data: STANDARD.encode(encrypted),
+    })
+}
+
+pub fn decrypt_with_passphrase(encrypted: &PassphraseEncrypted, passphrase: &str) -> Result<Vec<u8>, ApiErrors> {
+    let kdf = &encrypted.kdf;
+    if kdf.algorithm != "argon2id" || encrypted.cipher.algorithm != "aes-256-gcm" {
+        return Err(ApiErrors::BadRequest("Unsupported encryption algorithm".to_string()));
+    }
+    if kdf.memory_kib > MAX_MEMORY_KIB || kdf.iterations > MAX_ITERATIONS || kdf.parallelism > MAX_PARALLELISM {
+        return Err(ApiErrors::BadRequest("Key derivation parameters are too expensive".to_string()));
+    }
+
+    let decode = |value: &str| {
+        STANDARD
+            .decode(value)
+            .map_err(|_| ApiErrors::BadRequest("Encrypted data is not valid base64".to_string()))
+    };
+    let salt = decode(&kdf.salt)?;
+    let nonce = decode(&encrypted.cipher.nonce)?;
+    let data = decode(&encrypted.data)?;
+    if nonce.len() != 12 {
+        return Err(ApiErrors::BadRequest("Invalid nonce".to_string()));
+    }
+
+    let key = derive_key(passphrase, kdf, &salt)?;
+    Aes256Gcm::new(&key.into())
+        .decrypt(Nonce::from_slice(&nonce), data.as_slice())
+        // The tag check fails for a wrong passphrase and for tampered data alike
+        .map_err(|_| ApiErrors::BadRequest("Passphrase is incorrect or the data is corrupted".to_string()))
+}
+
+/**
+ * A random key for one file, returned with its copy encrypted by the server key for storage
+ */
+pub fn new_file_key() -> ([u8; 32], String) {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    (key, encrypt_data(&STANDARD.encode(key)))
+}
+
+pub fn decrypt_file_key(stored: &String) -> Result<[u8; 32], ApiErrors> {
+    STANDARD
+        .decode(decrypt_password(stored)?)
+        .ok()
+        .and_then(|key| <[u8; 32]>::try_from(key).ok())
+        .ok_or_else(|| ApiErrors::ServerError("File key is corrupted".to_string()))
+}
+
+/**
+ * The nonce of a chunk is its index and whether it is the last one, so chunks cannot be
+ * reordered or a file cut short without failing to decrypt. A key is only used for one file
+ */
+fn chunk_nonce(n: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(&n.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+pub fn encrypt_chunk(key: &[u8; 32], n: u64, last: bool, data: &[u8]) -> Result<Vec<u8>, ApiErrors> {
+    Aes256Gcm::new(key.into())
+        .encrypt(Nonce::from_slice(&chunk_nonce(n, last)), data)
+        .map_err(|_| ApiErrors::ServerError("There was an issue encrypting".to_string()))
+}
+
+pub fn decrypt_chunk(key: &[u8; 32], n: u64, last: bool, data: &[u8]) -> Result<Vec<u8>, ApiErrors> {
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(&chunk_nonce(n, last)), data)
+        .map_err(|_| ApiErrors::ServerError("File is corrupted".to_string()))
+}
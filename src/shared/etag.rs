@@ -0,0 +1,108 @@
+use rocket::{
+    http::{Header, Status},
+    request::{self, FromRequest, Outcome},
+    Request,
+};
+
+use super::types::ApiErrors;
+
+/**
+ * Strong ETag derived from a record revision
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ETag(pub i64);
+
+impl ETag {
+    /**
+     * Records written before revisions existed are treated as revision 0
+     */
+    pub fn from_revision(revision: Option<i64>) -> ETag {
+        ETag(revision.unwrap_or(0))
+    }
+}
+
+impl From<ETag> for Header<'static> {
+    fn from(etag: ETag) -> Self {
+        Header::new("ETag", format!("\"{}\"", etag.0))
+    }
+}
+
+/**
+ * Parse a single strong entity tag, ie "3"
+ */
+pub fn parse_etag(value: &str) -> Option<i64> {
+    value
+        .trim()
+        .strip_prefix('"')?
+        .strip_suffix('"')?
+        .parse::<i64>()
+        .ok()
+}
+
+/**
+ * The revisions the client expects the record to be at, any of them matches.
+ * None when the header is missing or is `*`
+ */
+#[derive(Debug)]
+pub struct IfMatch(pub Option<Vec<i64>>);
+
+impl IfMatch {
+    /**
+     * The revision a write has to find the record at, the current one when it is listed.
+     * None when any revision is accepted
+     */
+    pub fn expected_revision(&self, current: Option<i64>) -> Result<Option<i64>, ApiErrors> {
+        let current = ETag::from_revision(current).0;
+        match &self.0 {
+            None => Ok(None),
+            Some(revisions) if revisions.contains(&current) => Ok(Some(current)),
+            Some(_) => Err(ApiErrors::PreconditionFailed("If-Match does not match the record".to_string())),
+        }
+    }
+}
+
+/**
+ * The revisions of a list of entity tags, ie `"3", "4"`. Weak tags and tags that are not a
+ * revision are valid but never match under the strong comparison If-Match requires.
+ * None when the list cannot be parsed
+ */
+fn parse_etag_list(header: &str) -> Option<Vec<i64>> {
+    let mut revisions = Vec::new();
+    let mut tags = 0;
+    for tag in header.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+        let opaque = tag.strip_prefix("W/").unwrap_or(tag);
+        let quoted = opaque.len() >= 2 && opaque.starts_with('"') && opaque.ends_with('"');
+        if !quoted || opaque[1..opaque.len() - 1].contains('"') {
+            return None;
+        }
+        if !tag.starts_with("W/") {
+            revisions.extend(parse_etag(tag));
+        }
+        tags += 1;
+    }
+    (tags > 0).then_some(revisions)
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let header = match request.headers().get_one("If-Match") {
+            Some(header) => header.trim(),
+            None => return Outcome::Success(IfMatch(None)),
+        };
+
+        if header == "*" {
+            return Outcome::Success(IfMatch(None));
+        }
+
+        match parse_etag_list(header) {
+            Some(revisions) => Outcome::Success(IfMatch(Some(revisions))),
+            None => Outcome::Failure((
+                Status::BadRequest,
+                ApiErrors::BadRequest("If-Match is not a valid list of entity tags".to_string()),
+            )),
+        }
+    }
+}
@@ -1,4 +1,8 @@
+pub mod audit;
 pub mod encryption;
+pub mod etag;
 pub mod env_config;
 pub mod jwt_service;
+pub mod pagination;
+pub mod policy;
 pub mod types;
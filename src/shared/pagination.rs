@@ -0,0 +1,206 @@
+/*
+    Keyset pagination of records. A page ends with a cursor holding the sort value and id of its
+    last record, the next page starts right after them so records written in between do not shift
+    the pages. Records are ordered by their id when no sort is given, and by their id after the
+    sort value when they share one
+*/
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bson::{oid::ObjectId, Bson};
+use serde::{Deserialize, Serialize};
+
+use crate::shared::types::{ApiErrors, Record};
+
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Service,
+    Key,
+    Created,
+    Updated,
+}
+
+impl SortField {
+    /**
+     * Path of the field in a stored record
+     */
+    pub fn path(&self) -> &'static str {
+        match self {
+            SortField::Service => "service",
+            SortField::Key => "key",
+            SortField::Created => "created_at",
+            SortField::Updated => "updated_at",
+        }
+    }
+
+    /**
+     * Value of the field in a record, null when the record does not have it
+     */
+    pub fn value(&self, record: &Record) -> Bson {
+        match self {
+            SortField::Service => record.service.clone().map(Bson::String),
+            SortField::Key => record.key.clone().map(Bson::String),
+            SortField::Created => record.created_at.map(Bson::DateTime),
+            SortField::Updated => record.updated_at.map(Bson::DateTime),
+        }
+        .unwrap_or(Bson::Null)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sort {
+    pub field: SortField,
+    pub direction: SortDirection,
+}
+
+impl Sort {
+    /**
+     * Parse a sort such as `service` or `created:desc`, ascending by default
+     */
+    pub fn parse(sort: &str) -> Result<Sort, ApiErrors> {
+        let invalid = || {
+            ApiErrors::BadRequest(
+                "sort must be service, key, created or updated, optionally followed by :asc or :desc".to_string(),
+            )
+        };
+        let (field, direction) = sort.split_once(':').unwrap_or((sort, "asc"));
+        let field = match field.to_ascii_lowercase().as_str() {
+            "service" => SortField::Service,
+            "key" => SortField::Key,
+            "created" => SortField::Created,
+            "updated" => SortField::Updated,
+            _ => return Err(invalid()),
+        };
+        let direction = match direction.to_ascii_lowercase().as_str() {
+            "asc" => SortDirection::Asc,
+            "desc" => SortDirection::Desc,
+            _ => return Err(invalid()),
+        };
+        Ok(Sort { field, direction })
+    }
+}
+
+/**
+ * Where a page starts, opaque to clients
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PageCursor {
+    /// Right after the record with this id and sort value, for the sort it was made with
+    After { sort: Option<Sort>, value: Bson, id: ObjectId },
+    /// After this many records, for results that are ranked rather than sorted
+    Offset { offset: u64 },
+}
+
+impl PageCursor {
+    pub fn encode(&self) -> Result<String, ApiErrors> {
+        let bytes = bson::to_vec(self).map_err(|err| ApiErrors::ServerError(err.to_string()))?;
+        Ok(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn decode(cursor: &str) -> Result<PageCursor, ApiErrors> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| bson::from_slice(&bytes).ok())
+            .ok_or_else(|| ApiErrors::BadRequest("Cursor is not valid".to_string()))
+    }
+}
+
+/**
+ * A page of records to read
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRequest {
+    pub limit: i64,
+    pub sort: Option<Sort>,
+    pub cursor: Option<PageCursor>,
+}
+
+impl PageRequest {
+    pub fn new(
+        limit: Option<i64>,
+        default_limit: i64,
+        sort: Option<&str>,
+        cursor: Option<&str>,
+    ) -> Result<PageRequest, ApiErrors> {
+        let limit = limit.unwrap_or(default_limit);
+        if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+            return Err(ApiErrors::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_LIMIT)));
+        }
+        let sort = sort.map(Sort::parse).transpose()?;
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        if let Some(PageCursor::After { sort: cursor_sort, .. }) = &cursor {
+            if *cursor_sort != sort {
+                return Err(ApiErrors::BadRequest("Cursor was made for another sort".to_string()));
+            }
+        }
+        Ok(PageRequest { limit, sort, cursor })
+    }
+
+    /**
+     * The cursor of the page after the one ending with `record`
+     */
+    pub fn cursor_after(&self, record: &Record) -> Result<String, ApiErrors> {
+        let id = record
+            .id
+            .ok_or_else(|| ApiErrors::ServerError("Object id was not found".to_string()))?;
+        PageCursor::After {
+            sort: self.sort,
+            value: self.sort.map(|sort| sort.field.value(record)).unwrap_or(Bson::Null),
+            id,
+        }
+        .encode()
+    }
+
+    /**
+     * A page of `records`, read with one record more than the limit to tell whether another
+     * page follows
+     */
+    pub fn page<T>(
+        &self,
+        mut records: Vec<Record>,
+        total: u64,
+        to_item: impl Fn(Record) -> Result<T, ApiErrors>,
+    ) -> Result<Page<T>, ApiErrors> {
+        let next_cursor = match records.len() as i64 > self.limit {
+            true => {
+                records.truncate(self.limit as usize);
+                records.last().map(|record| self.cursor_after(record)).transpose()?
+            }
+            false => None,
+        };
+        Ok(Page {
+            items: records.into_iter().map(to_item).collect::<Result<_, _>>()?,
+            total,
+            next_cursor,
+            truncated: false,
+        })
+    }
+}
+
+/**
+ * A page of results, `next_cursor` is left out on the last page. `truncated` is only sent when
+ * the search stopped before it read every record it could match
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub truncated: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
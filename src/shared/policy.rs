@@ -0,0 +1,137 @@
+use bson::oid::ObjectId;
+
+use crate::{
+    drivers::storage::storage_trait::Storage,
+    shared::types::{ApiErrors, OrgRole, Record, SharePermission},
+};
+
+/*
+    Every check of who may do what to a vault, record or organization goes through `authorize`.
+    Records are stored under the user who created them, the policy works out which user that is
+    so routes can keep using the user scoped database methods
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Read,
+    Write,
+    Delete,
+    Share,
+    ManageMembers,
+    ManageCollections,
+    DeleteOrganization,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Resource {
+    /// The personal records of a user
+    Vault(ObjectId),
+    Record(ObjectId),
+    Organization(ObjectId),
+}
+
+#[derive(Debug)]
+pub struct Access {
+    /// The user the records are stored under
+    pub owner_id: ObjectId,
+    /// The record that was checked, for record resources
+    pub record: Option<Record>,
+    /// Role of the actor, for organization resources and organization records
+    pub role: Option<OrgRole>,
+}
+
+impl OrgRole {
+    pub fn allows(&self, action: Action) -> bool {
+        match action {
+            Action::Read => true,
+            Action::Write | Action::Delete => *self != OrgRole::ReadOnly,
+            Action::ManageMembers | Action::ManageCollections => {
+                matches!(self, OrgRole::Owner | OrgRole::Admin)
+            }
+            Action::DeleteOrganization => *self == OrgRole::Owner,
+            // Organization records are shared through their organization
+            Action::Share => false,
+        }
+    }
+}
+
+impl SharePermission {
+    pub fn allows(&self, action: Action) -> bool {
+        match action {
+            Action::Read => true,
+            Action::Write => self.can_write(),
+            _ => false,
+        }
+    }
+}
+
+/**
+ * Check the actor can perform the action on the resource.
+ * Resources the actor cannot see at all are reported as not found so their existence is not leaked
+ */
+pub async fn authorize(
+    db: &dyn Storage,
+    actor: ObjectId,
+    action: Action,
+    resource: Resource,
+) -> Result<Access, ApiErrors> {
+    match resource {
+        Resource::Vault(user_id) => {
+            if actor != user_id {
+                return Err(ApiErrors::Unauthorized("Not Authorized".to_string()));
+            }
+            Ok(Access { owner_id: user_id, record: None, role: None })
+        }
+        Resource::Organization(org_id) => {
+            let role = org_role(db, actor, org_id, action).await?;
+            Ok(Access { owner_id: actor, record: None, role: Some(role) })
+        }
+        Resource::Record(record_id) => {
+            let record = db.get_record_by_id(record_id).await?;
+            let owner_id = record
+                .user_id
+                .ok_or(ApiErrors::ServerError("User id was not in record".to_string()))?;
+
+            if let Some(org_id) = record.org_id {
+                // Membership decides, even for whoever created the record
+                let role = org_role(db, actor, org_id, action).await.map_err(|err| match err {
+                    ApiErrors::NotFound(_) => ApiErrors::NotFound("Record not found".to_string()),
+                    err => err,
+                })?;
+                return Ok(Access { owner_id, record: Some(record), role: Some(role) });
+            }
+            if owner_id == actor {
+                return Ok(Access { owner_id, record: Some(record), role: None });
+            }
+            match db.get_record_share(record_id, actor).await? {
+                Some(share) if share.permission.allows(action) => {
+                    Ok(Access { owner_id, record: Some(record), role: None })
+                }
+                Some(share) if share.permission == SharePermission::ReadOnly && action == Action::Write => Err(
+                    ApiErrors::Forbidden("Record is shared as read only".to_string()),
+                ),
+                Some(_) => Err(ApiErrors::Forbidden("Only the owner of a record can do this".to_string())),
+                None => Err(ApiErrors::NotFound("Record not found".to_string())),
+            }
+        }
+    }
+}
+
+async fn org_role(
+    db: &dyn Storage,
+    actor: ObjectId,
+    org_id: ObjectId,
+    action: Action,
+) -> Result<OrgRole, ApiErrors> {
+    let member = db
+        .get_org_member(org_id, actor)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Organization not found".to_string()))?;
+    if !member.role.allows(action) {
+        return Err(ApiErrors::Forbidden(format!(
+            "The {} role does not allow this",
+            member.role.as_str()
+        )));
+    }
+    Ok(member.role)
+}
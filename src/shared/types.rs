@@ -1,16 +1,18 @@
 use std::{collections::BTreeMap, io::Cursor};
 
-use bson::Document;
-use mongodb::bson::oid::ObjectId;
+use bson::{doc, Bson, DateTime, Document};
+use mongodb::bson::{oid::ObjectId, Binary};
 use rocket::{
-    http::{ContentType, Status},
+    http::{ContentType, Header, Status},
     response::{self, Responder},
     serde::json::{serde_json, Json},
     Request, Response,
 };
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use validator::Validate;
 
+use super::etag::ETag;
+
 #[derive(Debug)]
 pub enum ApiErrors {
     ServerError(String),
@@ -18,7 +20,41 @@ pub enum ApiErrors {
     Forbidden(String),
     Unauthorized(String),
     NotFound(String),
+    PreconditionFailed(String),
+    PayloadTooLarge(String),
+}
+impl ApiErrors {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiErrors::ServerError(_) => Status::InternalServerError,
+            ApiErrors::BadRequest(_) => Status::BadRequest,
+            ApiErrors::Forbidden(_) => Status::Forbidden,
+            ApiErrors::Unauthorized(_) => Status::Unauthorized,
+            ApiErrors::NotFound(_) => Status::NotFound,
+            ApiErrors::PreconditionFailed(_) => Status::PreconditionFailed,
+            ApiErrors::PayloadTooLarge(_) => Status::PayloadTooLarge,
+        }
+    }
+
+    /**
+     * The message that is safe to show to a client, server errors are logged instead
+     */
+    pub fn message(&self) -> String {
+        match self {
+            ApiErrors::ServerError(msg) => {
+                println!("{}", msg);
+                "Internal Service Error".to_string()
+            }
+            ApiErrors::BadRequest(msg)
+            | ApiErrors::Forbidden(msg)
+            | ApiErrors::Unauthorized(msg)
+            | ApiErrors::NotFound(msg)
+            | ApiErrors::PreconditionFailed(msg)
+            | ApiErrors::PayloadTooLarge(msg) => msg.clone(),
+        }
+    }
 }
+
 impl<'r> Responder<'r, 'r> for ApiErrors {
     fn respond_to(self, _: &'r Request<'_>) -> response::Result<'r> {
         let string = serde_json::to_string(&self).map_err(|e| {
@@ -27,28 +63,8 @@ impl<'r> Responder<'r, 'r> for ApiErrors {
         })?;
 
         let mut res = Response::build();
-        match self {
-            ApiErrors::ServerError(_) => {
-                res.status(Status::InternalServerError)
-                    .sized_body(string.len(), Cursor::new(string));
-            }
-            ApiErrors::BadRequest(_) => {
-                res.status(Status::BadRequest)
-                    .sized_body(string.len(), Cursor::new(string));
-            }
-            ApiErrors::Forbidden(_) => {
-                res.status(Status::Forbidden)
-                    .sized_body(string.len(), Cursor::new(string));
-            }
-            ApiErrors::Unauthorized(_) => {
-                res.status(Status::Unauthorized)
-                    .sized_body(string.len(), Cursor::new(string));
-            }
-            ApiErrors::NotFound(_) => {
-                res.status(Status::NotFound)
-                    .sized_body(string.len(), Cursor::new(string));
-            }
-        };
+        res.status(self.status())
+            .sized_body(string.len(), Cursor::new(string));
         res.header(ContentType::JSON);
         res.ok()
     }
@@ -59,20 +75,9 @@ impl Serialize for ApiErrors {
     where
         S: Serializer,
     {
-        let mut error_obj: BTreeMap<&str, BTreeMap<&str, &String>> = BTreeMap::new();
-        let mut error_msg: BTreeMap<&str, &String> = BTreeMap::new();
-        let server_error_msg = "Internal Service Error".to_string();
-        match self {
-            ApiErrors::ServerError(msg) => {
-                // Log Error Message
-                println!("{}", msg);
-                error_msg.insert("message", &server_error_msg)
-            }
-            ApiErrors::BadRequest(msg) => error_msg.insert("message", msg),
-            ApiErrors::Forbidden(msg) => error_msg.insert("message", msg),
-            ApiErrors::Unauthorized(msg) => error_msg.insert("message", msg),
-            ApiErrors::NotFound(msg) => error_msg.insert("message", msg),
-        };
+        let mut error_obj: BTreeMap<&str, BTreeMap<&str, String>> = BTreeMap::new();
+        let mut error_msg: BTreeMap<&str, String> = BTreeMap::new();
+        error_msg.insert("message", self.message());
         error_obj.insert("error", error_msg);
 
         serializer.collect_map(error_obj.iter())
@@ -90,7 +95,7 @@ pub struct RegistrationForm {
     pub password: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -132,20 +137,43 @@ pub struct LoginForm {
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/**
+ * A JSON Merge Patch (RFC 7396) for a record.
+ * A missing field is left untouched, `null` removes the field and a value replaces it
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UpdateRecord  {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub service: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub password: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub email: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub username: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub key: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub secret: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub service: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub password: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub username: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub key: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub secret: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub rotation_period: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub uris: Option<Option<Vec<RecordUri>>>,
+    #[serde(default, deserialize_with = "deserialize_patch_field", skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Option<Vec<String>>>,
+}
+
+/**
+ * Serde maps both a missing field and `null` to None, wrap present values so `null` becomes Some(None)
+ */
+fn deserialize_patch_field<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -157,7 +185,7 @@ pub struct UpdateUser {
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
     pub record_type: RecordTypes,
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -179,6 +207,110 @@ pub struct Record {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
+
+    /// Whether the user has 2FA turned on for the service
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<bool>,
+
+    /// Incremented on every write, exposed to clients as the record's ETag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime>,
+    /// Set when the record is created and whenever its password changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_changed_at: Option<DateTime>,
+
+    /// Result of the last breach check, cleared when the password or email changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breach: Option<BreachStatus>,
+
+    /// Days after which the password has to be rotated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_period: Option<i64>,
+    /// When the last rotation reminder was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_reminded_at: Option<DateTime>,
+
+    /// Where the credentials can be used, for autofill lookups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uris: Option<Vec<RecordUri>>,
+
+    /// Labels picked by the user, for any record type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    /// Set when the record belongs to an organization vault, `user_id` is then who created it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection_id: Option<ObjectId>,
+}
+
+/**
+ * How a URI of a record is compared to the URI of the page being filled
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UriMatch {
+    /// Same registrable domain, ie `app.example.com` and `example.com`
+    #[default]
+    Domain,
+    /// Same host and port
+    Host,
+    StartsWith,
+    Exact,
+    Regex,
+}
+
+impl UriMatch {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UriMatch::Domain => "domain",
+            UriMatch::Host => "host",
+            UriMatch::StartsWith => "starts_with",
+            UriMatch::Exact => "exact",
+            UriMatch::Regex => "regex",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordUri {
+    pub uri: String,
+    #[serde(default, rename = "match")]
+    pub match_mode: UriMatch,
+}
+
+impl From<String> for RecordUri {
+    fn from(uri: String) -> Self {
+        RecordUri { uri, match_mode: UriMatch::Domain }
+    }
+}
+
+impl From<RecordUri> for Bson {
+    fn from(uri: RecordUri) -> Self {
+        Bson::Document(doc! { "uri": uri.uri, "match": uri.match_mode.as_str() })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreachStatus {
+    /// Times the password shows up in the breach corpus
+    pub password_count: i64,
+    /// Breaches the email shows up in
+    pub email_count: i64,
+    pub checked_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseBreachStatus {
+    pub password_count: i64,
+    pub email_count: i64,
+    pub checked_at: String,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, FromFormField, Serialize, Deserialize)]
@@ -186,6 +318,20 @@ pub enum RecordTypes {
     Password,
     Secret,
 }
+/**
+ * The plaintext password or secret of a record
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseRevealedRecord {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub record_type: RecordTypes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResponseRecord {
     pub record_type: RecordTypes,
@@ -208,6 +354,37 @@ pub struct ResponseRecord {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_changed_at: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breach: Option<ResponseBreachStatus>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_period: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_due_at: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uris: Option<Vec<RecordUri>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection_id: Option<String>,
 }
 
 #[derive(Responder)]
@@ -215,3 +392,744 @@ pub struct ResponseRecord {
 pub struct CreatedResponse {
     pub id: Json<Document>,
 }
+
+#[derive(Responder)]
+pub struct RecordResponse {
+    pub record: Json<ResponseRecord>,
+    pub etag: ETag,
+}
+
+/**
+ * An opened send, kept out of caches since it may not be viewable again
+ */
+#[derive(Responder)]
+pub struct OpenedSendResponse {
+    pub send: Json<ResponseSecretSend>,
+    pub cache_control: Header<'static>,
+}
+
+#[derive(Responder)]
+#[response(status = 204)]
+pub struct UpdatedResponse {
+    pub body: (),
+    pub etag: ETag,
+}
+
+/**
+ * A single operation of a bulk request, `revision` behaves like the If-Match header
+ */
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BulkOperation {
+    Create {
+        record: NewRecord,
+    },
+    Update {
+        id: String,
+        record: UpdateRecord,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        revision: Option<i64>,
+    },
+    Delete {
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        revision: Option<i64>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkRequest {
+    /// When set either every operation is applied or none are
+    #[serde(default)]
+    pub atomic: bool,
+    pub operations: Vec<BulkOperation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkResult {
+    pub index: usize,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<i64>,
+    /// Only set when the server generated the password of a created record
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkResponse {
+    pub applied: bool,
+    pub results: Vec<BulkResult>,
+}
+
+/**
+ * A validated record write, ready to be stored
+ */
+#[derive(Debug, Clone)]
+pub enum RecordWrite {
+    Insert(Record),
+    /// `owner_id` is the user the record is stored under, not always the one writing it
+    Update {
+        record_id: ObjectId,
+        owner_id: ObjectId,
+        updated_record: UpdateRecord,
+        expected_revision: Option<i64>,
+    },
+    Delete {
+        record_id: ObjectId,
+        owner_id: ObjectId,
+        expected_revision: Option<i64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordWriteResult {
+    Inserted(ObjectId),
+    Updated(i64),
+    Deleted,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportIssue {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub reason: String,
+}
+
+/**
+ * A record created by an import, secrets are never echoed back
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportedRecord {
+    pub index: usize,
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub record_type: RecordTypes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Records that were created, or would be created on a dry run
+    pub imported: Vec<ImportedRecord>,
+    /// Entries that could not be mapped onto a record
+    pub skipped: Vec<ImportIssue>,
+    /// Entries that were imported but lost data on the way
+    pub ambiguous: Vec<ImportIssue>,
+    /// Entries with the same service and username as an existing record or an earlier entry
+    pub duplicates: Vec<ImportIssue>,
+}
+
+/**
+ * Options of the password generator, min counts default to one of every enabled class
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordOptions {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    /// Leave out characters that are easy to mistake for each other, ie l, 1 and I
+    pub exclude_ambiguous: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_lowercase: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_uppercase: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_digits: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_symbols: Option<usize>,
+}
+
+impl Default for PasswordOptions {
+    fn default() -> Self {
+        PasswordOptions {
+            length: 20,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            exclude_ambiguous: false,
+            min_lowercase: None,
+            min_uppercase: None,
+            min_digits: None,
+            min_symbols: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PassphraseOptions {
+    pub words: usize,
+    pub separator: String,
+    pub capitalize: bool,
+    /// Append a random digit to one of the words
+    pub include_number: bool,
+}
+
+impl Default for PassphraseOptions {
+    fn default() -> Self {
+        PassphraseOptions {
+            words: 6,
+            separator: "-".to_string(),
+            capitalize: false,
+            include_number: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeneratedSecret {
+    pub value: String,
+    pub entropy_bits: f64,
+}
+
+/**
+ * The password of a new record, either given by the client or generated by the server
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PasswordInput {
+    Value(String),
+    Generate { generate: PasswordOptions },
+}
+
+/**
+ * Body of POST /record, a Record whose password can be generated by the server
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewRecord {
+    pub record_type: RecordTypes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<PasswordInput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_period: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uris: Option<Vec<RecordUri>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Create the record in an organization vault instead of the user's own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection_id: Option<String>,
+}
+
+impl NewRecord {
+    pub fn into_record(self, password: Option<String>) -> Record {
+        Record {
+            record_type: self.record_type,
+            id: None,
+            user_id: None,
+            key: self.key,
+            secret: self.secret,
+            service: self.service,
+            password,
+            email: self.email,
+            username: self.username,
+            two_factor: self.two_factor,
+            revision: None,
+            created_at: None,
+            updated_at: None,
+            password_changed_at: None,
+            breach: None,
+            rotation_period: self.rotation_period,
+            rotation_reminded_at: None,
+            uris: self.uris,
+            tags: self.tags,
+            org_id: None,
+            collection_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    RotationDueSoon,
+    RotationOverdue,
+    EmergencyContactAdded,
+    EmergencyAccessRequested,
+    EmergencyAccessApproved,
+    EmergencyAccessRejected,
+    EmergencyVaultViewed,
+    EmergencyTakeover,
+    EmergencyContactRemoved,
+}
+
+/**
+ * An event sent to a user through the configured notifier
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub event: NotificationEvent,
+    pub user_id: String,
+    /// Where an email notifier should deliver it
+    pub email: Option<String>,
+    pub subject: String,
+    pub message: String,
+    pub data: serde_json::Value,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SharePermission {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl SharePermission {
+    pub fn can_write(&self) -> bool {
+        matches!(self, SharePermission::ReadWrite)
+    }
+}
+
+/**
+ * A grant on a single record, stored in the `record_shares` collection
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordShare {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub record_id: ObjectId,
+    pub owner_id: ObjectId,
+    pub owner_username: String,
+    pub grantee_id: ObjectId,
+    pub grantee_username: String,
+    pub permission: SharePermission,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewShare {
+    pub username: String,
+    pub permission: SharePermission,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseShare {
+    pub id: String,
+    pub record_id: String,
+    pub owner_username: String,
+    pub grantee_username: String,
+    pub permission: SharePermission,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedRecord {
+    pub share: ResponseShare,
+    pub record: ResponseRecord,
+}
+
+/**
+ * Role of a member in an organization, from most to least privileged
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    Owner,
+    Admin,
+    Member,
+    ReadOnly,
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Owner => "owner",
+            OrgRole::Admin => "admin",
+            OrgRole::Member => "member",
+            OrgRole::ReadOnly => "read_only",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    pub created_by: ObjectId,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgMember {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub org_id: ObjectId,
+    pub org_name: String,
+    pub user_id: ObjectId,
+    pub username: String,
+    pub role: OrgRole,
+    pub joined_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgInvitation {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub org_id: ObjectId,
+    pub org_name: String,
+    /// The invited user
+    pub user_id: ObjectId,
+    pub username: String,
+    pub role: OrgRole,
+    pub invited_by: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgCollection {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub org_id: ObjectId,
+    pub name: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewOrganization {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewInvitation {
+    pub username: String,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateMember {
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewCollection {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseOrganization {
+    pub id: String,
+    pub name: String,
+    /// Role of the user asking
+    pub role: OrgRole,
+    pub joined_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseMember {
+    pub user_id: String,
+    pub username: String,
+    pub role: OrgRole,
+    pub joined_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseInvitation {
+    pub id: String,
+    pub org_id: String,
+    pub org_name: String,
+    pub username: String,
+    pub role: OrgRole,
+    pub invited_by: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseCollection {
+    pub id: String,
+    pub org_id: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    EmergencyContactAdded,
+    EmergencyAccessRequested,
+    EmergencyAccessApproved,
+    EmergencyAccessAutoApproved,
+    EmergencyAccessRejected,
+    EmergencyVaultViewed,
+    EmergencyTakeover,
+    EmergencyContactRemoved,
+    /// The password or secret of a record was sent to a user in plaintext
+    RecordRevealed,
+}
+
+/**
+ * A security relevant event, stored in the `audit_log` collection
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: AuditAction,
+    /// Whose account or records the event is about
+    pub owner_id: ObjectId,
+    /// Who caused the event, none when the server did
+    pub actor_id: Option<ObjectId>,
+    /// What the event applies to, ie an emergency access
+    pub target_id: Option<ObjectId>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseAuditEvent {
+    pub action: AuditAction,
+    pub owner_id: String,
+    pub actor_id: Option<String>,
+    pub target_id: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmergencyAccessType {
+    /// Read the records of the vault
+    View,
+    /// Set a new login password for the account
+    Takeover,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmergencyStatus {
+    /// The contact has not asked for access
+    Idle,
+    Requested,
+    Approved,
+}
+
+/**
+ * A contact trusted with access to the vault of a user, stored in the `emergency_access` collection
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmergencyAccess {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// The user whose vault is accessed
+    pub grantor_id: ObjectId,
+    pub grantor_username: String,
+    /// The emergency contact
+    pub grantee_id: ObjectId,
+    pub grantee_username: String,
+    pub access: EmergencyAccessType,
+    /// Days after a request it is approved if the grantor has not answered
+    pub wait_days: i64,
+    pub status: EmergencyStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested_at: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approved_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewEmergencyContact {
+    pub username: String,
+    pub access: EmergencyAccessType,
+    pub wait_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmergencyTakeover {
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseEmergencyAccess {
+    pub id: String,
+    pub grantor_username: String,
+    pub grantee_username: String,
+    pub access: EmergencyAccessType,
+    pub wait_days: i64,
+    pub status: EmergencyStatus,
+    pub requested_at: Option<String>,
+    pub approved_at: Option<String>,
+    /// When a pending request is approved without an answer
+    pub auto_approve_at: Option<String>,
+    pub created_at: String,
+}
+
+/**
+ * A secret shared through a link, stored in the `sends` collection. The id is the link,
+ * the payload is encrypted and the send is deleted once its views are used up
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretSend {
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// The user who created the send
+    pub user_id: ObjectId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    /// Base64 of the file before it is encrypted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_content: Option<String>,
+    /// Hash of the password needed to open the send
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub max_views: i64,
+    pub views_left: i64,
+    pub expires_at: DateTime,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewSecretSend {
+    pub text: Option<String>,
+    pub file_name: Option<String>,
+    /// Base64 of the file
+    pub file_content: Option<String>,
+    pub password: Option<String>,
+    pub max_views: Option<i64>,
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseSecretSend {
+    pub text: Option<String>,
+    pub file_name: Option<String>,
+    pub file_content: Option<String>,
+    /// Views left after this one, the send is gone at 0
+    pub views_left: i64,
+    pub expires_at: String,
+}
+
+/**
+ * What is known about an attachment besides its name, kept in the `metadata` field of its GridFS file
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentMetadata {
+    pub record_id: ObjectId,
+    /// The owner of the record, whose quota the attachment counts against
+    pub user_id: ObjectId,
+    pub uploaded_by: ObjectId,
+    pub content_type: String,
+    /// Size before encryption
+    pub size: i64,
+    /// The key of the file, encrypted with the server key
+    pub key: String,
+}
+
+/**
+ * GridFS file of an attachment, stored in the `attachments.files` collection
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// Size of the encrypted chunks
+    pub length: i64,
+    #[serde(rename = "chunkSize")]
+    pub chunk_size: i32,
+    #[serde(rename = "uploadDate")]
+    pub upload_date: DateTime,
+    pub filename: String,
+    pub metadata: AttachmentMetadata,
+}
+
+/**
+ * GridFS chunk of an attachment, stored in the `attachments.chunks` collection
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentChunk {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub files_id: ObjectId,
+    pub n: i32,
+    pub data: Binary,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseAttachment {
+    pub id: String,
+    pub record_id: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub uploaded_at: String,
+}
+
+/**
+ * A search a user stored to run again, its query is kept as written and parsed when it runs
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// text, regex or fuzzy, text when there is none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// Both types are searched when there is none
+    pub types: Vec<RecordTypes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewSavedSearch {
+    pub name: String,
+    pub query: Option<String>,
+    pub mode: Option<String>,
+    /// password or secret
+    pub types: Option<Vec<String>>,
+    pub sort: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseSavedSearch {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    pub types: Vec<RecordTypes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}